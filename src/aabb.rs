@@ -0,0 +1,120 @@
+use crate::ray::{Ray};
+use crate::vec3::{Point, Vec3};
+
+// flat boxes get this much thickness so rays never slip through a zero width slab
+const PADDING: f64 = 1e-4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    min: Point,
+    max: Point,
+}
+
+impl Aabb {
+    pub fn new(a: Point, b: Point) -> Aabb {
+        let mut min = Point::new([a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())]);
+        let mut max = Point::new([a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())]);
+        for i in 0..3 {
+            if max[i] - min[i] < PADDING {
+                min[i] -= PADDING / 2.0;
+                max[i] += PADDING / 2.0;
+            }
+        }
+        Aabb { min, max }
+    }
+
+    pub fn from_points(points: &[Point]) -> Aabb {
+        let mut min = Point::new([f64::INFINITY; 3]);
+        let mut max = Point::new([f64::NEG_INFINITY; 3]);
+        for p in points {
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        Aabb::new(min, max)
+    }
+
+    pub fn min(&self) -> &Point {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Point {
+        &self.max
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Point::new([
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ]),
+            Point::new([
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ]),
+        )
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let e = self.extent();
+        if e.x() > e.y() && e.x() > e.z() {
+            0
+        } else if e.y() > e.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let e = self.extent();
+        2.0 * (e.x() * e.y() + e.y() * e.z() + e.z() * e.x())
+    }
+
+    pub fn corners(&self) -> [Point; 8] {
+        let mut corners = [self.min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            for axis in 0..3 {
+                if i & (1 << axis) != 0 {
+                    corner[axis] = self.max[axis];
+                }
+            }
+        }
+        corners
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+
+    // the part of [t_min, t_max] where the ray is inside the box
+    pub fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for i in 0..3 {
+            let inv = 1.0 / ray.direct()[i];
+            let mut t0 = (self.min[i] - ray.org()[i]) * inv;
+            let mut t1 = (self.max[i] - ray.org()[i]) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
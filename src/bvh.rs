@@ -0,0 +1,157 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::aabb::{Aabb};
+use std::sync::{Arc};
+
+const LEAF_SIZE: usize = 2;
+
+// Bounding volume hierarchy over a fixed set of objects, stored flat in depth first order so
+// an interior node's first child directly follows it. Unbounded objects such as planes cannot
+// be placed in the tree and are tested against every ray.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    objects: Vec<Arc<dyn Hittable>>,
+    unbounded: Vec<Arc<dyn Hittable>>,
+}
+
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Leaf { start: usize, count: usize },
+    Interior { second: usize },
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Arc<dyn Hittable>>) -> Bvh {
+        let mut bounded = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for object in objects {
+            match object.bounding_box() {
+                Some(b) => bounded.push((b, object)),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            objects: Vec::with_capacity(bounded.len()),
+            unbounded,
+        };
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded, 0);
+        }
+        bvh.objects = bounded.into_iter().map(|(_, object)| object).collect();
+        bvh
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // splits at the median centroid along the axis where the centroids spread the most;
+    // `items` is reordered in place so leaves refer to contiguous runs of it
+    fn build(&mut self, items: &mut [(Aabb, Arc<dyn Hittable>)], offset: usize) -> usize {
+        let bounds = items.iter().skip(1).fold(items[0].0, |acc, (b, _)| acc.union(b));
+        let index = self.nodes.len();
+
+        if items.len() <= LEAF_SIZE {
+            self.nodes.push(Node { bounds, kind: NodeKind::Leaf { start: offset, count: items.len() } });
+            return index;
+        }
+
+        let centroids: Vec<_> = items.iter().map(|(b, _)| b.centroid()).collect();
+        let axis = Aabb::from_points(&centroids).longest_axis();
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |a, b| a.0.centroid()[axis].total_cmp(&b.0.centroid()[axis]));
+
+        self.nodes.push(Node { bounds, kind: NodeKind::Interior { second: 0 } });
+        let (left, right) = items.split_at_mut(mid);
+        self.build(left, offset);
+        let second = self.build(right, offset + mid);
+        self.nodes[index].kind = NodeKind::Interior { second };
+        index
+    }
+}
+
+impl Hittable for Bvh {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest = t_max;
+        let mut result = None;
+
+        for object in self.unbounded.iter() {
+            if let Some(rec) = object.intersect(ray, t_min, closest) {
+                closest = rec.t();
+                result = Some(rec);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return result;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.hit(ray, t_min, closest) {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    for object in &self.objects[start..start + count] {
+                        if let Some(rec) = object.intersect(ray, t_min, closest) {
+                            closest = rec.t();
+                            result = Some(rec);
+                        }
+                    }
+                },
+                NodeKind::Interior { second } => {
+                    stack.push(second);
+                    stack.push(index + 1);
+                },
+            }
+        }
+        result
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut result = 1.0;
+        for object in self.unbounded.iter() {
+            result *= object.transmittance(ray, t_min, t_max);
+        }
+
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(index) = stack.pop() {
+            if result == 0.0 {
+                break;
+            }
+            let node = &self.nodes[index];
+            if !node.bounds.hit(ray, t_min, t_max) {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    for object in &self.objects[start..start + count] {
+                        result *= object.transmittance(ray, t_min, t_max);
+                    }
+                },
+                NodeKind::Interior { second } => {
+                    stack.push(second);
+                    stack.push(index + 1);
+                },
+            }
+        }
+        result
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| node.bounds)
+    }
+}
@@ -1,21 +1,36 @@
-use crate::ray::{Ray, Hittable};
+use crate::ray::{Ray};
+use crate::world::{World};
 use crate::vec3::{Point, Vec3};
 use crate::color::*;
-use std::fs::File;
-use std::io::{Write, BufWriter};
-use std::sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}};
+use crate::image::{Image};
+use crate::film::{Film, TileSamples};
+use crate::observer::{RenderObserver, Progress, CancelToken};
+use std::sync::{Arc, mpsc, atomic::{AtomicUsize, Ordering}};
 use std::thread;
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 const ASPECT_RATIO: f64 = 16.0 / 9.0;
 const V_FOV: f64 = 20.0;    // vertical field of view
 const WIDTH: f64 = 1920.0;
-const THREADS_NUM: i64 = 12;
 const SAMPLE_NUM: u16 = 500;
 const REFLECT_DEPTH: u8 = 20;
 const FOCUS_DIST: f64 = 10.0;
 const DEFOCUS_ANGLE: f64 = 0.6;
+// passes after the first are sized to take about this long
+const PASS_SECONDS: f64 = 10.0;
+// noise estimates from fewer samples are too unreliable to stop on
+const MIN_NOISE_SAMPLES: u32 = 16;
+// width and height of the pieces workers take in turn
+const TILE_SIZE: usize = 32;
 
 pub struct Camera {
+    look_from: Point,
+    look_at: Point,
+    vup: Vec3,
+    v_fov: f64,
+    focus_dist: f64,
     eye: Point,
     width: f64,
     height: f64,
@@ -27,189 +42,401 @@ pub struct Camera {
     defocus_angle: f64,
     disk_u: Vec3,
     disk_v: Vec3,
+    shutter_open: f64,
+    shutter_close: f64,
+    threads: usize,
+    time_limit: Option<Duration>,
+    noise_target: Option<f64>,
 }
 
 impl Camera {
     pub fn new(look_from: Point, look_at: Point) -> Camera {
-        let width = WIDTH;
-        let height = (width / ASPECT_RATIO).max(1.0).floor();
+        let mut camera = Camera {
+            look_from,
+            look_at,
+            vup: Vec3::new([0.0, 1.0, 0.0]),
+            v_fov: V_FOV,
+            focus_dist: FOCUS_DIST,
+            eye: look_from,
+            width: WIDTH,
+            height: (WIDTH / ASPECT_RATIO).floor(),
+            pixel_start: look_from,
+            delta_u: Vec3::new([0.0; 3]),
+            delta_v: Vec3::new([0.0; 3]),
+            sample_num: SAMPLE_NUM,
+            reflect_depth: REFLECT_DEPTH,
+            defocus_angle: DEFOCUS_ANGLE,
+            disk_u: Vec3::new([0.0; 3]),
+            disk_v: Vec3::new([0.0; 3]),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            time_limit: None,
+            noise_target: None,
+        };
+        camera.update();
+        camera
+    }
 
-        let focus_dist = FOCUS_DIST;
-        let defocus_angle = DEFOCUS_ANGLE;
-        let theta = V_FOV.to_radians();
+    // derives the viewport from the current settings
+    fn update(&mut self) {
+        let theta = self.v_fov.to_radians();
         let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * focus_dist;
-        let viewport_width = viewport_height * ASPECT_RATIO;
-        
-        let vup = Vec3::new([0.0, 1.0, 0.0]);
-        let w = (look_from - look_at).unit();
-        let u = vup.cross(&w).unit();
+        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_width = viewport_height * self.width / self.height;
+
+        let w = (self.look_from - self.look_at).unit();
+        let u = self.vup.cross(&w).unit();
         let v = w.cross(&u);
-        
+
         let viewport_u = viewport_width * u;
         let viewport_v = viewport_height * v.reverse();
 
-        let delta_u = viewport_u / width;
-        let delta_v = viewport_v / height;
-        let viewport_upper_left = look_from - focus_dist * w - viewport_u / 2.0 - viewport_v / 2.0;
-        let start = viewport_upper_left + (delta_u + delta_v) / 2.0;
-        
-        let defocus_radius = focus_dist * f64::from(defocus_angle / 2.0).to_radians().tan();
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
+        self.delta_u = viewport_u / self.width;
+        self.delta_v = viewport_v / self.height;
+        let viewport_upper_left = self.look_from - self.focus_dist * w - viewport_u / 2.0 - viewport_v / 2.0;
+        self.pixel_start = viewport_upper_left + (self.delta_u + self.delta_v) / 2.0;
+        self.eye = self.look_from;
 
-        Camera {
-            eye: look_from,
-            width: width,
-            height: height,
-            pixel_start: start,
-            delta_u: delta_u,
-            delta_v: delta_v,
-            sample_num: SAMPLE_NUM,
-            reflect_depth: REFLECT_DEPTH,
-            defocus_angle: defocus_angle,
-            disk_u: defocus_disk_u,
-            disk_v: defocus_disk_v,
-        }
+        let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
+        self.disk_u = u * defocus_radius;
+        self.disk_v = v * defocus_radius;
+    }
+
+    pub fn up(mut self, vup: Vec3) -> Camera {
+        self.vup = vup;
+        self.update();
+        self
+    }
+
+    // vertical field of view in degrees
+    pub fn v_fov(mut self, degrees: f64) -> Camera {
+        self.v_fov = degrees.clamp(1e-3, 179.0);
+        self.update();
+        self
+    }
+
+    // image size in pixels; the aspect ratio follows from it
+    pub fn resolution(mut self, width: u32, height: u32) -> Camera {
+        self.width = width.max(1) as f64;
+        self.height = height.max(1) as f64;
+        self.update();
+        self
+    }
+
+    // `angle` is the cone of rays through each pixel in degrees, zero for a pinhole camera
+    pub fn defocus(mut self, angle: f64, focus_dist: f64) -> Camera {
+        self.defocus_angle = angle.max(0.0);
+        self.focus_dist = focus_dist.max(1e-6);
+        self.update();
+        self
     }
 
-    pub fn render(&self, environment: Arc<impl Hittable + Sync + Send + 'static>) {
-        let now = std::time::Instant::now();
-        let mut photo = match File::create("out.ppm") {
-            Err(e) => panic!("Could not create photo: {}", e),
-            Ok(file) => BufWriter::new(file),
+    pub fn samples(mut self, samples: u16) -> Camera {
+        self.sample_num = samples.max(1);
+        self
+    }
+
+    pub fn max_depth(mut self, depth: u8) -> Camera {
+        self.reflect_depth = depth.max(1);
+        self
+    }
+
+    // rays get a time spread uniformly over [open, close]; moving objects span [0, 1]
+    pub fn shutter(mut self, open: f64, close: f64) -> Camera {
+        self.shutter_open = open.min(close);
+        self.shutter_close = open.max(close);
+        self
+    }
+
+    // worker threads used by `render`, one per core unless set
+    pub fn threads(mut self, threads: usize) -> Camera {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // renders stop adding samples once `limit` has passed, even short of `sample_num`
+    pub fn time_limit(mut self, limit: Duration) -> Camera {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    // renders stop adding samples once the estimated noise, see `Film::noise`, is at most
+    // `target`, even short of `sample_num`
+    pub fn noise_target(mut self, target: f64) -> Camera {
+        self.noise_target = Some(target.max(0.0));
+        self
+    }
+
+    pub fn look_from(&self) -> &Point { &self.look_from }
+    pub fn look_at(&self) -> &Point { &self.look_at }
+    pub fn vup(&self) -> &Vec3 { &self.vup }
+    pub fn fov(&self) -> f64 { self.v_fov }
+    pub fn width(&self) -> u32 { self.width as u32 }
+    pub fn height(&self) -> u32 { self.height as u32 }
+    pub fn defocus_angle(&self) -> f64 { self.defocus_angle }
+    pub fn focus_dist(&self) -> f64 { self.focus_dist }
+    pub fn sample_num(&self) -> u16 { self.sample_num }
+    pub fn reflect_depth(&self) -> u8 { self.reflect_depth }
+    pub fn shutter_interval(&self) -> (f64, f64) { (self.shutter_open, self.shutter_close) }
+    pub fn thread_num(&self) -> usize { self.threads }
+    pub fn max_time(&self) -> Option<Duration> { self.time_limit }
+    pub fn max_noise(&self) -> Option<f64> { self.noise_target }
+
+    pub fn render(&self, environment: Arc<World>) -> Image {
+        self.render_with(environment, &mut (), &CancelToken::new())
+    }
+
+    // like `render`, reporting to `observer` and stopping early if `cancel` is triggered
+    pub fn render_with(&self, environment: Arc<World>, observer: &mut dyn RenderObserver, cancel: &CancelToken) -> Image {
+        let mut film = Film::new(self.width as usize, self.height as usize);
+        self.render_progressive(environment, &mut film, rand::thread_rng().gen(), observer, cancel);
+        film.image()
+    }
+
+    // Adds passes to `film` until every pixel has `sample_num` samples. With a time limit
+    // or a noise target it may stop sooner, though never before one pass, and a cancelled
+    // render stops within a tile, leaving the pixels of that pass unevenly sampled. The
+    // camera rays follow from `seed` and the samples the pixels already have, so a render
+    // picked up from a saved film carries on where it stopped instead of repeating itself.
+    pub fn render_progressive(
+        &self,
+        environment: Arc<World>,
+        film: &mut Film,
+        seed: u64,
+        observer: &mut dyn RenderObserver,
+        cancel: &CancelToken,
+    ) {
+        assert!(
+            film.width() == self.width as usize && film.height() == self.height as usize,
+            "film size does not match the camera resolution",
+        );
+        let target = self.sample_num as u32;
+        let total: usize = film.sample_counts().iter().map(|n| target.saturating_sub(*n) as usize).sum();
+        let mut tracker = Tracker {
+            start: Instant::now(),
+            time_limit: self.time_limit,
+            total,
+            samples: 0,
+            noise: 0.0,
+            pass: 0,
+            tiles_done: 0,
+            tiles: 0,
         };
-        let header = format!("P3\n{} {}\n255\n", self.width, self.height);
-        let _ = photo.write_all(header.as_bytes());
-
-        let height = self.height as i64;
-        let width = self.width as i64;
-
-        let counter = Arc::new(AtomicUsize::new(0));
-        let total = (width * height) as usize;
-        // pixel buffer
-        let pixels = Arc::new(Mutex::new(vec![BLACK; total]));
-
-        let num_threads = THREADS_NUM;
-        let chunk_size = height / num_threads;
-        let mut handles = vec![];
-
-        for thread_id in 0..num_threads {
-            let environment = Arc::clone(&environment);
-            let pixels = Arc::clone(&pixels);
-            let eye = self.eye.clone();
-            let pixel_start = self.pixel_start.clone();
-            let delta_u = self.delta_u.clone();
-            let delta_v = self.delta_v.clone();
-            let sample_num = self.sample_num;
-            let reflect_depth = self.reflect_depth;
-            let defocus_angle = self.defocus_angle;
-            let disk_u = self.disk_u.clone();
-            let disk_v = self.disk_v.clone();
-            let counter = Arc::clone(&counter);
-
-            let handle = thread::spawn(move || {
-                let start_row = thread_id * chunk_size;
-                let end_row = if thread_id == num_threads - 1 {
-                    height
-                } else {
-                    (thread_id + 1) * chunk_size
-                };
-
-                let mut local_pixels = vec![BLACK; ((end_row - start_row) * width) as usize];
-
-                for i in start_row..end_row {
-                    let y = i as f64;
-                
-                    for j in 0..width {
-                        let x = j as f64;
-                        let mut color = BLACK;
-
-                        for _ in 0..sample_num {
-                            let offset = Vec3::random(-0.5, 0.5);
-                            let sample_pixel = pixel_start
-                                + (y + offset.y()) * delta_v
-                                + (x + offset.x()) * delta_u;
-                    
-                            let ray_org = if defocus_angle <= 0.0 {
-                                eye
-                            } else {
-                                defocus_sample(eye, disk_u, disk_v)
-                            };
-                            let ray = Ray::new(ray_org, sample_pixel - ray_org);
-                            color = color + ray_color(&ray, &*environment, reflect_depth);
-                        }
-                
-                        let samples_average_color = color / sample_num as f64;
-                        local_pixels[((i - start_row) * width + j) as usize] = samples_average_color;
+        let deadline = self.time_limit.map(|limit| tracker.start + limit);
 
-                        counter.fetch_add(1, Ordering::SeqCst);
-                    }
-                }
+        // a quick first pass to time the scene, then passes of about PASS_SECONDS each,
+        // shortened to end by the deadline
+        let mut pass_samples = 1;
+        while film.min_samples() < target && !cancel.is_cancelled() {
+            let pass_start = Instant::now();
+            self.render_pass(&environment, film, pass_samples, seed, &mut tracker, observer, cancel);
+            observer.pass_finished(film);
 
-                let mut pixels = pixels.lock().unwrap();
-                for i in start_row..end_row {
-                    for j in 0..width {
-                        pixels[(i * width + j) as usize] = local_pixels[((i - start_row) * width + j) as usize];
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                break;
+            }
+            // the samples each pixel should have before the noise is measured again
+            let mut noise_samples = u32::MAX;
+            if let Some(goal) = self.noise_target {
+                let taken = film.min_samples();
+                noise_samples = MIN_NOISE_SAMPLES;
+                if taken >= MIN_NOISE_SAMPLES {
+                    // noise falls with the square root of the samples taken
+                    let noise = film.noise();
+                    tracker.noise = (goal / noise).powi(2).min(1.0);
+                    if noise <= goal {
+                        break;
                     }
+                    noise_samples = (taken as f64 * (noise / goal).powi(2)).ceil().min(u32::MAX as f64) as u32;
                 }
-            });
-            handles.push(handle);
+                noise_samples = noise_samples.saturating_sub(taken).max(1);
+            }
+
+            let seconds_per_sample = (now - pass_start).as_secs_f64() / pass_samples as f64;
+            let mut seconds = PASS_SECONDS;
+            if let Some(deadline) = deadline {
+                seconds = seconds.min((deadline - now).as_secs_f64());
+            }
+            pass_samples = (seconds / seconds_per_sample.max(1e-9)).clamp(1.0, target as f64) as u32;
+            pass_samples = pass_samples.min(noise_samples);
         }
 
-        let counter = Arc::clone(&counter);
-        let progress = thread::spawn(move || {
-            loop {
-                let completed = counter.load(Ordering::SeqCst);
-                let percentage = (completed as f64 / total as f64) * 100.0;
-                print!("\rProgress: {:.2}%", percentage);
-                std::io::stdout().flush().unwrap();
+        if !cancel.is_cancelled() {
+            tracker.samples = tracker.total;
+            tracker.noise = 1.0;
+        }
+        observer.progress(&tracker.progress());
+    }
 
-                if completed >= total { break; }
-                thread::sleep(std::time::Duration::from_secs(1));
+    // Adds up to `samples` samples to every pixel without going past `sample_num`. Workers
+    // take tiles in turn and send back what they rendered; this thread adds it to the film
+    // and keeps the observer posted.
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(
+        &self,
+        environment: &World,
+        film: &mut Film,
+        samples: u32,
+        seed: u64,
+        tracker: &mut Tracker,
+        observer: &mut dyn RenderObserver,
+        cancel: &CancelToken,
+    ) {
+        let (width, height) = (film.width(), film.height());
+        let target = self.sample_num as u32;
+        let counts = film.sample_counts().to_vec();
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(TILE_SIZE) {
+            for x in (0..width).step_by(TILE_SIZE) {
+                tiles.push(Tile { x, y, width: TILE_SIZE.min(width - x), height: TILE_SIZE.min(height - y) });
+            }
+        }
+        let next = AtomicUsize::new(0);
+        tracker.pass += 1;
+        tracker.tiles_done = 0;
+        tracker.tiles = tiles.len();
+
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..self.threads.min(tiles.len()) {
+                let sender = sender.clone();
+                let (tiles, counts, next) = (&tiles, &counts, &next);
+                scope.spawn(move || {
+                    while !cancel.is_cancelled() {
+                        let Some(tile) = tiles.get(next.fetch_add(1, Ordering::SeqCst)) else { break };
+                        let rendered = self.render_tile(environment, tile, counts, width, samples, target, seed);
+                        if sender.send((tile, rendered)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            loop {
+                match receiver.recv_timeout(Duration::from_secs(1)) {
+                    Ok((tile, rendered)) => {
+                        tracker.samples += film.add_tile(tile.x, tile.y, tile.width, &rendered);
+                        tracker.tiles_done += 1;
+                    },
+                    Err(mpsc::RecvTimeoutError::Timeout) => {},
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                observer.progress(&tracker.progress());
             }
         });
+    }
 
-        for handle in handles {
-            handle.join().unwrap();
+    #[allow(clippy::too_many_arguments)]
+    fn render_tile(
+        &self,
+        environment: &World,
+        tile: &Tile,
+        counts: &[u32],
+        width: usize,
+        samples: u32,
+        target: u32,
+        seed: u64,
+    ) -> TileSamples {
+        let mut rendered = TileSamples {
+            sums: vec![BLACK; tile.width * tile.height],
+            squares: vec![0.0; tile.width * tile.height],
+            counts: vec![0; tile.width * tile.height],
+        };
+        for i in tile.y..tile.y + tile.height {
+            let mut rng = StdRng::seed_from_u64(stream_seed(seed, i, tile.x, counts[i * width + tile.x]));
+            for j in tile.x..tile.x + tile.width {
+                let k = (i - tile.y) * tile.width + j - tile.x;
+                let n = samples.min(target.saturating_sub(counts[i * width + j]));
+                for _ in 0..n {
+                    let color = self.sample(&mut rng, environment, i, j);
+                    rendered.sums[k] = rendered.sums[k] + color;
+                    rendered.squares[k] += luminance(&color).powi(2);
+                }
+                rendered.counts[k] = n;
+            }
         }
-        progress.join().unwrap();
+        rendered
+    }
 
-        println!("\nRendering time: {}s", now.elapsed().as_secs());
-        let pixels = pixels.lock().unwrap();
-        for color in pixels.iter() {
-            write_color(&mut photo, color);
-        }
+    // the light arriving through a random point of pixel (i, j)
+    fn sample(&self, rng: &mut StdRng, environment: &World, i: usize, j: usize) -> Color {
+        let sample_pixel = self.pixel_start
+            + (i as f64 + rng.gen_range(-0.5..0.5)) * self.delta_v
+            + (j as f64 + rng.gen_range(-0.5..0.5)) * self.delta_u;
+
+        let ray_org = if self.defocus_angle <= 0.0 {
+            self.eye
+        } else {
+            defocus_sample(rng, self.eye, self.disk_u, self.disk_v)
+        };
+        let time = self.shutter_open + (self.shutter_close - self.shutter_open) * rng.gen::<f64>();
+        let ray = Ray::with_time(ray_org, sample_pixel - ray_org, time);
+        ray_color(&ray, environment, self.reflect_depth)
+    }
+}
+
+// a seed for the camera rays of one row of a tile in one pass
+fn stream_seed(seed: u64, row: usize, column: usize, samples_before: u32) -> u64 {
+    [row as u64, column as u64, samples_before as u64].iter().fold(seed, |hash, value| {
+        let hash = (hash ^ value).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        hash ^ hash >> 32
+    })
+}
 
-        drop(photo);
-        if cfg!(target_os = "linux") {
-            println!("Convert ppm to png");
-            convert_ppm_to_png();
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+// The progress of a render so far: samples taken out of the `total` to take, how close the
+// noise is to its target and how far the current pass has got.
+struct Tracker {
+    start: Instant,
+    time_limit: Option<Duration>,
+    total: usize,
+    samples: usize,
+    noise: f64,
+    pass: u32,
+    tiles_done: usize,
+    tiles: usize,
+}
+
+impl Tracker {
+    fn progress(&self) -> Progress {
+        let elapsed = self.start.elapsed();
+        let mut fraction = self.samples as f64 / self.total.max(1) as f64;
+        if let Some(limit) = self.time_limit {
+            fraction = fraction.max(elapsed.as_secs_f64() / limit.as_secs_f64().max(1e-9));
         }
-        println!("Completed!");
+        let fraction = fraction.max(self.noise).min(1.0);
+        let eta = (fraction > 0.0).then(|| elapsed.mul_f64((1.0 - fraction) / fraction));
+        Progress { fraction, pass: self.pass, tiles_done: self.tiles_done, tiles: self.tiles, elapsed, eta }
     }
-    
 }
-    
-fn defocus_sample(eye: Point, disk_u: Vec3, disk_v: Vec3) -> Point {
-    let p = Vec3::random_in_unit_disk();
-    eye + p.x() * disk_u + p.y() * disk_v
+
+fn defocus_sample(rng: &mut StdRng, eye: Point, disk_u: Vec3, disk_v: Vec3) -> Point {
+    loop {
+        let (x, y) = (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+        if x * x + y * y < 1.0 {
+            return eye + x * disk_u + y * disk_v;
+        }
+    }
 }
 
-fn convert_ppm_to_png() {
-    let output = std::process::Command::new("pnmtopng")
-        .arg("out.ppm")
-        .output()
-        .expect("Failed to execute command");
-
-    if output.status.success() {
-        println!("Conversion successful!");
-        let mut out_file = File::create("out.png")
-            .expect("Failed to create output file");
-        std::io::copy(&mut output.stdout.as_slice(), &mut out_file)
-            .expect("Failed to write output to file");
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        println!("Conversion failed:\n{}", stderr);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_is_kept_exactly() {
+        for (width, height) in [(320, 29), (1920, 1080), (333, 1000), (1, 7), (7, 1)] {
+            let camera = Camera::new(Point::new([0.0, 0.0, 1.0]), Point::new([0.0; 3])).resolution(width, height);
+            assert_eq!((camera.width(), camera.height()), (width, height));
+        }
     }
 }
@@ -0,0 +1,364 @@
+// Built-in scenes, most of them from the Ray Tracing in One Weekend books. They are scene
+// descriptions rather than worlds so they can be tweaked, saved and rendered like any scene
+// file; the seed drives everything placed at random.
+use crate::scene::{Scene, CameraDesc, TextureDesc, ColorSource, MaterialDesc, ShapeDesc, TransformDesc, ObjectDesc};
+use crate::world::{Background, ORIGIN};
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color, BLACK, WHITE};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+pub struct BuiltInScene {
+    pub name: &'static str,
+    pub description: &'static str,
+    build: fn(u64) -> Scene,
+}
+
+impl BuiltInScene {
+    pub fn build(&self, seed: u64) -> Scene {
+        (self.build)(seed)
+    }
+}
+
+pub const BUILT_IN_SCENES: [BuiltInScene; 9] = [
+    BuiltInScene {
+        name: "random",
+        description: "spheres of random materials, the diffuse ones bouncing, around three big balls",
+        build: random,
+    },
+    BuiltInScene {
+        name: "weekend",
+        description: "the final scene of Ray Tracing in One Weekend",
+        build: weekend,
+    },
+    BuiltInScene {
+        name: "next-week",
+        description: "the final scene of Ray Tracing: The Next Week; needs earthmap.png in the working directory",
+        build: next_week,
+    },
+    BuiltInScene {
+        name: "cornell",
+        description: "the Cornell box with two rotated blocks",
+        build: cornell,
+    },
+    BuiltInScene {
+        name: "cornell-smoke",
+        description: "the Cornell box with blocks of black and white smoke",
+        build: cornell_smoke,
+    },
+    BuiltInScene {
+        name: "checkered-spheres",
+        description: "two large spheres with a checker texture",
+        build: checkered_spheres,
+    },
+    BuiltInScene {
+        name: "perlin-spheres",
+        description: "a marble sphere on a marble ground, textured with Perlin noise",
+        build: perlin_spheres,
+    },
+    BuiltInScene {
+        name: "earth",
+        description: "a globe with an image texture; needs earthmap.png in the working directory",
+        build: earth,
+    },
+    BuiltInScene {
+        name: "furnace",
+        description: "white spheres under a uniform white sky; energy conserving materials vanish into it",
+        build: furnace,
+    },
+];
+
+pub fn built_in_scene(name: &str) -> Option<&'static BuiltInScene> {
+    BUILT_IN_SCENES.iter().find(|scene| scene.name == name)
+}
+
+// adds an object with a material of its own
+fn add(scene: &mut Scene, shape: ShapeDesc, material: MaterialDesc) -> &mut ObjectDesc {
+    let name = format!("m{}", scene.materials.len());
+    scene.materials.push((name.clone(), material));
+    scene.objects.push(ObjectDesc::new(shape, &name));
+    scene.objects.last_mut().unwrap()
+}
+
+// adds an object with a material shared by name
+fn add_shared<'a>(scene: &'a mut Scene, shape: ShapeDesc, material: &str) -> &'a mut ObjectDesc {
+    scene.objects.push(ObjectDesc::new(shape, material));
+    scene.objects.last_mut().unwrap()
+}
+
+fn sphere(center: [f64; 3], radius: f64) -> ShapeDesc {
+    ShapeDesc::Sphere { center: Point::new(center), radius, to: None }
+}
+
+fn quad(corner: [f64; 3], u: [f64; 3], v: [f64; 3]) -> ShapeDesc {
+    ShapeDesc::Quad { corner: Point::new(corner), u: Vec3::new(u), v: Vec3::new(v) }
+}
+
+fn lambertian(r: f64, g: f64, b: f64) -> MaterialDesc {
+    MaterialDesc::Lambertian { albedo: ColorSource::Value(Color::new([r, g, b])) }
+}
+
+fn random_color(rng: &mut StdRng, min: f64, max: f64) -> Color {
+    Color::new([rng.gen_range(min..max), rng.gen_range(min..max), rng.gen_range(min..max)])
+}
+
+fn random(seed: u64) -> Scene {
+    let mut scene = Scene::new(CameraDesc::new(Point::new([13.0, 2.0, 3.0]), ORIGIN));
+    add(&mut scene, sphere([0.0, -1000.0, 0.0], 1000.0), lambertian(0.5, 0.5, 0.5));
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    for i in -8..8 {
+        for j in -8..8 {
+            let choose_mat = rng.gen_range(0.0..1.0);
+            let radius = rng.gen_range(0.1..0.2);
+            let center = Point::new([
+                i as f64 + 0.9 * rng.gen_range(0.0..1.0),
+                radius,
+                j as f64 + 0.9 * rng.gen_range(0.0..1.0)
+            ]);
+
+            if (center - Point::new([4.0, radius, 0.0])).length() > 0.9 {
+                if choose_mat < 0.3 {
+                    // diffuse balls bounce while the shutter is open
+                    let albedo = ColorSource::Value(random_color(&mut rng, 0.0, 0.6));
+                    let bounce = center + Vec3::new([0.0, rng.gen_range(0.0..0.3), 0.0]);
+                    let ball = ShapeDesc::Sphere { center, radius, to: Some(bounce) };
+                    add(&mut scene, ball, MaterialDesc::Lambertian { albedo });
+                    continue;
+                }
+                let sphere_mat = if choose_mat < 0.9 {
+                    let albedo = ColorSource::Value(random_color(&mut rng, 0.5, 1.0));
+                    let fuzz = rng.gen_range(0.0..0.4);
+                    MaterialDesc::Metal { albedo, fuzz }
+                } else {
+                    MaterialDesc::Dielectric { ior: rng.gen_range(0.5..2.0) }
+                };
+
+                add(&mut scene, ShapeDesc::Sphere { center, radius, to: None }, sphere_mat);
+            }
+        }
+    }
+
+    add(&mut scene, sphere([-150.0, 69.0, -30.0], 80.0), lambertian(0.8, 0.65, 0.3));
+    add(&mut scene, sphere([-4.0, 1.0, 0.0], 1.0), MaterialDesc::Dielectric { ior: 1.5 });
+    let albedo = ColorSource::Value(Color::new([0.5, 0.6, 0.7]));
+    add(&mut scene, sphere([4.0, 1.0, 0.0], 1.0), MaterialDesc::Metal { albedo, fuzz: 0.0 });
+
+    scene
+}
+
+fn weekend(seed: u64) -> Scene {
+    let mut camera = CameraDesc::new(Point::new([13.0, 2.0, 3.0]), ORIGIN);
+    camera.fov = 20.0;
+    camera.defocus_angle = 0.6;
+    camera.focus_dist = 10.0;
+    camera.width = 1200;
+    camera.height = 675;
+    camera.max_depth = 50;
+    let mut scene = Scene::new(camera);
+    add(&mut scene, sphere([0.0, -1000.0, 0.0], 1000.0), lambertian(0.5, 0.5, 0.5));
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.gen_range(0.0..1.0);
+            let center = [a as f64 + 0.9 * rng.gen_range(0.0..1.0), 0.2, b as f64 + 0.9 * rng.gen_range(0.0..1.0)];
+            if (Point::new(center) - Point::new([4.0, 0.2, 0.0])).length() <= 0.9 {
+                continue;
+            }
+            let material = if choose_mat < 0.8 {
+                let albedo = random_color(&mut rng, 0.0, 1.0) * random_color(&mut rng, 0.0, 1.0);
+                MaterialDesc::Lambertian { albedo: ColorSource::Value(albedo) }
+            } else if choose_mat < 0.95 {
+                let albedo = ColorSource::Value(random_color(&mut rng, 0.5, 1.0));
+                MaterialDesc::Metal { albedo, fuzz: rng.gen_range(0.0..0.5) }
+            } else {
+                MaterialDesc::Dielectric { ior: 1.5 }
+            };
+            add(&mut scene, sphere(center, 0.2), material);
+        }
+    }
+
+    add(&mut scene, sphere([0.0, 1.0, 0.0], 1.0), MaterialDesc::Dielectric { ior: 1.5 });
+    add(&mut scene, sphere([-4.0, 1.0, 0.0], 1.0), lambertian(0.4, 0.2, 0.1));
+    let albedo = ColorSource::Value(Color::new([0.7, 0.6, 0.5]));
+    add(&mut scene, sphere([4.0, 1.0, 0.0], 1.0), MaterialDesc::Metal { albedo, fuzz: 0.0 });
+
+    scene
+}
+
+fn next_week(seed: u64) -> Scene {
+    let mut camera = CameraDesc::new(Point::new([478.0, 278.0, -600.0]), Point::new([278.0, 278.0, 0.0]));
+    camera.fov = 40.0;
+    camera.defocus_angle = 0.0;
+    camera.width = 800;
+    camera.height = 800;
+    camera.max_depth = 40;
+    let mut scene = Scene::new(camera);
+    scene.background = Background::Solid(BLACK);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // a floor of boxes of random heights
+    scene.materials.push(("ground".to_string(), lambertian(0.48, 0.83, 0.53)));
+    for i in 0..20 {
+        for j in 0..20 {
+            let w = 100.0;
+            let (x, z) = (-1000.0 + i as f64 * w, -1000.0 + j as f64 * w);
+            let min = Point::new([x, 0.0, z]);
+            let max = Point::new([x + w, rng.gen_range(1.0..101.0), z + w]);
+            add_shared(&mut scene, ShapeDesc::Box { min, max }, "ground");
+        }
+    }
+
+    let lamp = quad([123.0, 554.0, 147.0], [300.0, 0.0, 0.0], [0.0, 0.0, 265.0]);
+    let emit = ColorSource::Value(Color::new([7.0, 7.0, 7.0]));
+    add(&mut scene, lamp, MaterialDesc::Light { emit }).light = true;
+
+    let center = Point::new([400.0, 400.0, 200.0]);
+    let moving = ShapeDesc::Sphere { center, radius: 50.0, to: Some(center + Vec3::new([30.0, 0.0, 0.0])) };
+    add(&mut scene, moving, lambertian(0.7, 0.3, 0.1));
+    add(&mut scene, sphere([260.0, 150.0, 45.0], 50.0), MaterialDesc::Dielectric { ior: 1.5 });
+    let albedo = ColorSource::Value(Color::new([0.8, 0.8, 0.9]));
+    add(&mut scene, sphere([0.0, 150.0, 145.0], 50.0), MaterialDesc::Metal { albedo, fuzz: 1.0 });
+
+    // a glass ball filled with blue smoke, and a thin mist over everything
+    add(&mut scene, sphere([360.0, 150.0, 145.0], 70.0), MaterialDesc::Dielectric { ior: 1.5 });
+    let albedo = ColorSource::Value(Color::new([0.2, 0.4, 0.9]));
+    add(&mut scene, sphere([360.0, 150.0, 145.0], 70.0), MaterialDesc::Isotropic { albedo }).medium = Some(0.2);
+    let albedo = ColorSource::Value(WHITE);
+    add(&mut scene, sphere([0.0, 0.0, 0.0], 5000.0), MaterialDesc::Isotropic { albedo }).medium = Some(0.0001);
+
+    scene.textures.push(("earth".to_string(), TextureDesc::Image { path: "earthmap.png".to_string() }));
+    let albedo = ColorSource::Texture("earth".to_string());
+    add(&mut scene, sphere([400.0, 200.0, 400.0], 100.0), MaterialDesc::Lambertian { albedo });
+    scene.textures.push(("marble".to_string(), TextureDesc::Marble { seed, scale: 0.2, colors: None }));
+    let albedo = ColorSource::Texture("marble".to_string());
+    add(&mut scene, sphere([220.0, 280.0, 300.0], 80.0), MaterialDesc::Lambertian { albedo });
+
+    // a rotated cube of small white balls
+    scene.materials.push(("white".to_string(), lambertian(0.73, 0.73, 0.73)));
+    for _ in 0..1000 {
+        let center = [rng.gen_range(0.0..165.0), rng.gen_range(0.0..165.0), rng.gen_range(0.0..165.0)];
+        let ball = add_shared(&mut scene, sphere(center, 10.0), "white");
+        ball.transforms.push(TransformDesc::Rotate(Vec3::new([0.0, 1.0, 0.0]), 15.0));
+        ball.transforms.push(TransformDesc::Translate(Vec3::new([-100.0, 270.0, 395.0])));
+    }
+
+    scene
+}
+
+// the empty box both Cornell scenes start from, with `white` defined for their contents
+fn cornell_box() -> Scene {
+    let mut camera = CameraDesc::new(Point::new([278.0, 278.0, -800.0]), Point::new([278.0, 278.0, 0.0]));
+    camera.fov = 40.0;
+    camera.defocus_angle = 0.0;
+    camera.width = 600;
+    camera.height = 600;
+    camera.samples = 200;
+    camera.max_depth = 50;
+    let mut scene = Scene::new(camera);
+    scene.background = Background::Solid(BLACK);
+
+    scene.materials.push(("red".to_string(), lambertian(0.65, 0.05, 0.05)));
+    scene.materials.push(("white".to_string(), lambertian(0.73, 0.73, 0.73)));
+    scene.materials.push(("green".to_string(), lambertian(0.12, 0.45, 0.15)));
+    add_shared(&mut scene, quad([555.0, 0.0, 0.0], [0.0, 555.0, 0.0], [0.0, 0.0, 555.0]), "green");
+    add_shared(&mut scene, quad([0.0, 0.0, 0.0], [0.0, 555.0, 0.0], [0.0, 0.0, 555.0]), "red");
+    add_shared(&mut scene, quad([0.0, 0.0, 0.0], [555.0, 0.0, 0.0], [0.0, 0.0, 555.0]), "white");
+    add_shared(&mut scene, quad([555.0, 555.0, 555.0], [-555.0, 0.0, 0.0], [0.0, 0.0, -555.0]), "white");
+    add_shared(&mut scene, quad([0.0, 0.0, 555.0], [555.0, 0.0, 0.0], [0.0, 555.0, 0.0]), "white");
+    scene
+}
+
+// the tall and the short block, as (size, angle, offset)
+const CORNELL_BLOCKS: [([f64; 3], f64, [f64; 3]); 2] = [
+    ([165.0, 330.0, 165.0], 15.0, [265.0, 0.0, 295.0]),
+    ([165.0, 165.0, 165.0], -18.0, [130.0, 0.0, 65.0]),
+];
+
+fn cornell_block<'a>(scene: &'a mut Scene, index: usize, material: &str) -> &'a mut ObjectDesc {
+    let (size, angle, offset) = CORNELL_BLOCKS[index];
+    let block = add_shared(scene, ShapeDesc::Box { min: ORIGIN, max: Point::new(size) }, material);
+    block.transforms.push(TransformDesc::Rotate(Vec3::new([0.0, 1.0, 0.0]), angle));
+    block.transforms.push(TransformDesc::Translate(Vec3::new(offset)));
+    block
+}
+
+fn cornell(_seed: u64) -> Scene {
+    let mut scene = cornell_box();
+    let lamp = quad([343.0, 554.0, 332.0], [-130.0, 0.0, 0.0], [0.0, 0.0, -105.0]);
+    let emit = ColorSource::Value(Color::new([15.0, 15.0, 15.0]));
+    add(&mut scene, lamp, MaterialDesc::Light { emit }).light = true;
+    cornell_block(&mut scene, 0, "white");
+    cornell_block(&mut scene, 1, "white");
+    scene
+}
+
+fn cornell_smoke(_seed: u64) -> Scene {
+    let mut scene = cornell_box();
+    let lamp = quad([113.0, 554.0, 127.0], [330.0, 0.0, 0.0], [0.0, 0.0, 305.0]);
+    let emit = ColorSource::Value(Color::new([7.0, 7.0, 7.0]));
+    add(&mut scene, lamp, MaterialDesc::Light { emit }).light = true;
+    scene.materials.push(("smoke".to_string(), MaterialDesc::Isotropic { albedo: ColorSource::Value(BLACK) }));
+    scene.materials.push(("fog".to_string(), MaterialDesc::Isotropic { albedo: ColorSource::Value(WHITE) }));
+    cornell_block(&mut scene, 0, "smoke").medium = Some(0.01);
+    cornell_block(&mut scene, 1, "fog").medium = Some(0.01);
+    scene
+}
+
+// the camera the small texture demos share
+fn texture_camera(look_from: [f64; 3]) -> CameraDesc {
+    let mut camera = CameraDesc::new(Point::new(look_from), ORIGIN);
+    camera.fov = 20.0;
+    camera.defocus_angle = 0.0;
+    camera.width = 400;
+    camera.height = 225;
+    camera.samples = 100;
+    camera.max_depth = 50;
+    camera
+}
+
+fn checkered_spheres(_seed: u64) -> Scene {
+    let mut scene = Scene::new(texture_camera([13.0, 2.0, 3.0]));
+    scene.textures.push(("checker".to_string(), TextureDesc::Checker { scale: 0.32, colors: None }));
+    scene.materials.push(("checker".to_string(), MaterialDesc::Lambertian {
+        albedo: ColorSource::Texture("checker".to_string()),
+    }));
+    add_shared(&mut scene, sphere([0.0, -10.0, 0.0], 10.0), "checker");
+    add_shared(&mut scene, sphere([0.0, 10.0, 0.0], 10.0), "checker");
+    scene
+}
+
+fn perlin_spheres(seed: u64) -> Scene {
+    let mut scene = Scene::new(texture_camera([13.0, 2.0, 3.0]));
+    scene.textures.push(("marble".to_string(), TextureDesc::Marble { seed, scale: 4.0, colors: None }));
+    scene.materials.push(("marble".to_string(), MaterialDesc::Lambertian {
+        albedo: ColorSource::Texture("marble".to_string()),
+    }));
+    add_shared(&mut scene, sphere([0.0, -1000.0, 0.0], 1000.0), "marble");
+    add_shared(&mut scene, sphere([0.0, 2.0, 0.0], 2.0), "marble");
+    scene
+}
+
+fn earth(_seed: u64) -> Scene {
+    let mut scene = Scene::new(texture_camera([0.0, 0.0, 12.0]));
+    scene.textures.push(("earth".to_string(), TextureDesc::Image { path: "earthmap.png".to_string() }));
+    let albedo = ColorSource::Texture("earth".to_string());
+    add(&mut scene, sphere([0.0, 0.0, 0.0], 2.0), MaterialDesc::Lambertian { albedo });
+    scene
+}
+
+// Under a uniform white sky a white object that neither loses nor gains energy comes out
+// exactly as bright as the background, so any visible sphere is a bug or a lossy model.
+fn furnace(_seed: u64) -> Scene {
+    let mut camera = texture_camera([0.0, 0.0, 12.0]);
+    camera.max_depth = 64;
+    let mut scene = Scene::new(camera);
+    scene.background = Background::Solid(WHITE);
+
+    let white = ColorSource::Value(WHITE);
+    add(&mut scene, sphere([-2.5, 0.0, 0.0], 1.0), MaterialDesc::Lambertian { albedo: white.clone() });
+    add(&mut scene, sphere([0.0, 0.0, 0.0], 1.0), MaterialDesc::Dielectric { ior: 1.5 });
+    add(&mut scene, sphere([2.5, 0.0, 0.0], 1.0), MaterialDesc::Metal { albedo: white, fuzz: 0.5 });
+    scene
+}
@@ -0,0 +1,127 @@
+// A render in progress saved to disk: the film, the seed its camera rays come from and a
+// fingerprint of the scene and settings it was made with, so it is only ever resumed with
+// the same ones.
+use crate::film::{Film};
+use crate::color::{Color};
+use std::fmt;
+use std::fs;
+use std::path::{Path};
+
+const MAGIC: &[u8; 7] = b"RTCKPT\0";
+const VERSION: u8 = 2;
+const HEADER_SIZE: usize = 8 + 4 + 4 + 8 + 8;
+// three f64 sums, the f64 sum of squares and a u32 count
+const PIXEL_SIZE: usize = 4 * 8 + 4;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Unsupported(String),
+    Corrupt(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "{}", e),
+            CheckpointError::Unsupported(what) => write!(f, "unsupported checkpoint: {}", what),
+            CheckpointError::Corrupt(what) => write!(f, "corrupt checkpoint: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(e: std::io::Error) -> CheckpointError {
+        CheckpointError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub fingerprint: u64,
+    pub seed: u64,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    pub fn encode(&self) -> Vec<u8> {
+        let pixels = self.film.sums().len();
+        let mut out = Vec::with_capacity(HEADER_SIZE + pixels * PIXEL_SIZE);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&(self.film.width() as u32).to_le_bytes());
+        out.extend_from_slice(&(self.film.height() as u32).to_le_bytes());
+        out.extend_from_slice(&self.fingerprint.to_le_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        let film = &self.film;
+        for ((sum, square), count) in film.sums().iter().zip(film.squares()).zip(film.sample_counts()) {
+            for c in 0..3 {
+                out.extend_from_slice(&sum[c].to_le_bytes());
+            }
+            out.extend_from_slice(&square.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Checkpoint, CheckpointError> {
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(MAGIC) {
+            return Err(CheckpointError::Corrupt("not a checkpoint file".to_string()));
+        }
+        if bytes[7] != VERSION {
+            return Err(CheckpointError::Unsupported(format!("version {}, expected {}", bytes[7], VERSION)));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        let (width, height) = (u32_at(8) as usize, u32_at(12) as usize);
+        let (fingerprint, seed) = (u64_at(16), u64_at(24));
+
+        let expected = width.checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(PIXEL_SIZE))
+            .and_then(|size| size.checked_add(HEADER_SIZE));
+        if expected != Some(bytes.len()) {
+            return Err(CheckpointError::Corrupt(format!("wrong size for a {}x{} film", width, height)));
+        }
+
+        let mut sums = Vec::with_capacity(width * height);
+        let mut squares = Vec::with_capacity(width * height);
+        let mut samples = Vec::with_capacity(width * height);
+        for pixel in bytes[HEADER_SIZE..].chunks_exact(PIXEL_SIZE) {
+            let f64_at = |i: usize| f64::from_le_bytes(pixel[i..i + 8].try_into().unwrap());
+            sums.push(Color::new([f64_at(0), f64_at(8), f64_at(16)]));
+            squares.push(f64_at(24));
+            samples.push(u32::from_le_bytes(pixel[32..36].try_into().unwrap()));
+        }
+        let film = Film::from_parts(width, height, sums, squares, samples);
+        Ok(Checkpoint { fingerprint, seed, film })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Checkpoint, CheckpointError> {
+        Checkpoint::decode(&fs::read(path)?)
+    }
+
+    // written next to the target first and then moved over it, so a crash while saving
+    // leaves the previous checkpoint intact
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.encode())?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+// FNV-1a, stable across platforms and compiler versions unlike the std hashers
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3))
+}
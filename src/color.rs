@@ -1,44 +1,93 @@
-use crate::ray::{Ray, Hittable};
-use crate::material::{scatter};
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
 use crate::vec3::{Vec3};
-use crate::world::{INF};
-use std::fs::File;
-use std::io::{Write, BufWriter};
+use crate::world::{World, INF};
 
 pub type Color = Vec3;
 
-const RGB_MAX: f64 = 255.999;
 pub const WHITE: Color = Color::new([1.0, 1.0, 1.0]);
 pub const BLACK: Color = Color::new([0.0, 0.0, 0.0]);
-const SKY_BLUE: Color = Color::new([0.5, 0.7, 1.0]);
-
-pub fn ray_color(r: &Ray, environment: &impl Hittable, depth: u8) -> Color {
-    if depth <= 0 { return BLACK; }
-    match environment.intersect(r, 0.001, INF) {
-        Some(rec) => {
-            if let Some((scattered, attenuation)) = scatter(rec.mat(), r, &rec) {
-                return attenuation * ray_color(&scattered, environment, depth - 1);
-            }
-            BLACK
-        },
-        None => {
-            let alpha = (r.direct().unit().y() + 1.0) / 2.0;
-            (1.0 - alpha) * WHITE + alpha * SKY_BLUE
+
+// relative luminance of a linear Rec. 709 color
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+pub fn ray_color(r: &Ray, world: &World, depth: u8) -> Color {
+    trace(r, world, depth, None)
+}
+
+// `bsdf_pdf` is the solid angle density with which the previous bounce chose this ray,
+// or None when it came from the camera or a specular lobe and cannot be light sampled
+fn trace(r: &Ray, world: &World, depth: u8, bsdf_pdf: Option<f64>) -> Color {
+    if depth == 0 { return BLACK; }
+    let rec = match world.intersect(r, 0.001, INF) {
+        Some(rec) => rec,
+        None => return world.background().value(r.direct()),
+    };
+
+    let wo = r.direct().unit().reverse();
+    let mat = rec.mat();
+
+    // emission found by bsdf sampling competes with the light sample taken one bounce earlier,
+    // but only registered lights can be light sampled; anything else glowing keeps full weight
+    let mut color = mat.emitted(&wo, &rec);
+    if let Some(pdf) = bsdf_pdf {
+        if color != BLACK && is_light_hit(world, r, &rec) {
+            let light_pdf = world.light_pdf(r.org(), r.direct(), r.time());
+            color = color * power_heuristic(pdf, light_pdf);
         }
     }
+
+    color = color + sample_lights(world, mat, &wo, &rec, r.time());
+
+    if let Some(sample) = mat.sample(&wo, &rec) {
+        let scattered = Ray::with_time(*rec.pos(), sample.direction, r.time());
+        let next_pdf = if sample.specular { None } else { Some(sample.pdf) };
+        color = color + sample.weight * trace(&scattered, world, depth - 1, next_pdf);
+    }
+    color
 }
 
-fn linear_to_gamma(val: f64) -> f64 {
-    if val > 0.0 { 
-        return val.sqrt();
+// next event estimation: direct light from one sampled emitter, weighted against bsdf sampling
+fn sample_lights(world: &World, mat: &dyn Bsdf, wo: &Vec3, rec: &HitRecord, time: f64) -> Color {
+    let wi = match world.sample_light(rec.pos(), time) {
+        Some(direction) if !direction.near_zero() => direction.unit(),
+        _ => return BLACK,
+    };
+
+    let f = mat.eval(wo, &wi, rec);
+    let light_pdf = world.light_pdf(rec.pos(), &wi, time);
+    if f == BLACK || light_pdf <= 0.0 {
+        return BLACK;
+    }
+
+    // the light is reached through whatever lies in between, media letting part of it through
+    let shadow = Ray::with_time(*rec.pos(), wi, time);
+    let light = match world.nearest_light(&shadow, 0.001) {
+        Some(light) => light,
+        None => return BLACK,
+    };
+    let emitted = light.mat().emitted(&wi.reverse(), &light);
+    if emitted == BLACK {
+        return BLACK;
     }
-    0.0
+    let transmittance = world.transmittance(&shadow, 0.001, light.t() * (1.0 - 1e-6));
+    if transmittance == 0.0 {
+        return BLACK;
+    }
+
+    let bsdf_pdf = mat.pdf(wo, &wi, rec);
+    f * emitted * (transmittance * power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+}
+
+fn is_light_hit(world: &World, r: &Ray, rec: &HitRecord) -> bool {
+    world.nearest_light(r, 0.001)
+        .is_some_and(|light| (light.t() - rec.t()).abs() <= 1e-9 * rec.t().abs().max(1.0))
 }
 
-pub fn write_color(file: &mut BufWriter<File>, c: &Color) {
-    let r_byte = (linear_to_gamma(c.x()) * RGB_MAX) as i32;
-    let g_byte = (linear_to_gamma(c.y()) * RGB_MAX) as i32;
-    let b_byte = (linear_to_gamma(c.z()) * RGB_MAX) as i32; 
-    let color = format!("{} {} {}\n", r_byte, g_byte, b_byte);
-    let _ = file.write_all(color.as_bytes());
+fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other * other;
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
 }
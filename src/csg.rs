@@ -0,0 +1,130 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::aabb::{Aabb};
+use std::sync::{Arc};
+
+// The stretch of a ray inside a closed shape, from the surface hit where it enters to the one
+// where it leaves. Spans cover the whole line, including negative t, so a boolean combination
+// still knows which volumes contain the ray origin.
+pub struct Span {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
+// Closed shapes with a well defined inside. `intersect_all` returns disjoint spans sorted by t.
+pub trait Solid: Hittable {
+    fn intersect_all(&self, ray: &Ray) -> Vec<Span>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// Boolean combination of two solids. Surface hits keep the material of the operand they came
+// from; where the ray leaves a subtracted volume it enters the result, so that boundary is
+// reported as a front face with the normal pointing into the hole.
+pub struct Csg {
+    op: CsgOp,
+    left: Arc<dyn Solid>,
+    right: Arc<dyn Solid>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, left: Arc<dyn Solid>, right: Arc<dyn Solid>) -> Csg {
+        Csg { op, left, right }
+    }
+
+    pub fn union(left: Arc<dyn Solid>, right: Arc<dyn Solid>) -> Csg {
+        Csg::new(CsgOp::Union, left, right)
+    }
+
+    pub fn intersection(left: Arc<dyn Solid>, right: Arc<dyn Solid>) -> Csg {
+        Csg::new(CsgOp::Intersection, left, right)
+    }
+
+    // `left` with `right` carved out of it
+    pub fn difference(left: Arc<dyn Solid>, right: Arc<dyn Solid>) -> Csg {
+        Csg::new(CsgOp::Difference, left, right)
+    }
+}
+
+struct Event {
+    rec: HitRecord,
+    right: bool,
+    entering: bool,
+}
+
+fn events(spans: Vec<Span>, right: bool) -> impl Iterator<Item = Event> {
+    spans.into_iter().flat_map(move |span| [
+        Event { rec: span.enter, right, entering: true },
+        Event { rec: span.exit, right, entering: false },
+    ])
+}
+
+impl Solid for Csg {
+    fn intersect_all(&self, ray: &Ray) -> Vec<Span> {
+        let left = self.left.intersect_all(ray);
+        let right = self.right.intersect_all(ray);
+        if left.is_empty() && (right.is_empty() || self.op != CsgOp::Union) {
+            return Vec::new();
+        }
+
+        let mut all: Vec<Event> = events(left, false).chain(events(right, true)).collect();
+        all.sort_by(|a, b| a.rec.t().total_cmp(&b.rec.t()));
+
+        // sweep along the ray, emitting a boundary whenever the combined inside state flips
+        let mut spans = Vec::new();
+        let mut in_left = false;
+        let mut in_right = false;
+        let mut enter: Option<HitRecord> = None;
+        for event in all {
+            let before = self.op.inside(in_left, in_right);
+            if event.right { in_right = event.entering; } else { in_left = event.entering; }
+            let after = self.op.inside(in_left, in_right);
+            if before == after {
+                continue;
+            }
+            // hit normals always face the ray, so only the side needs fixing
+            let rec = event.rec.with_front_face(after);
+            if after {
+                enter = Some(rec);
+            } else if let Some(enter) = enter.take() {
+                spans.push(Span { enter, exit: rec });
+            }
+        }
+        spans
+    }
+}
+
+impl Hittable for Csg {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if let Some(b) = self.bounding_box() {
+            if !b.hit(ray, t_min, t_max) {
+                return None;
+            }
+        }
+        self.intersect_all(ray).into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|rec| t_min <= rec.t() && rec.t() <= t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box();
+        match self.op {
+            CsgOp::Union => Some(left?.union(&self.right.bounding_box()?)),
+            CsgOp::Intersection | CsgOp::Difference => left,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::quad::{Quad};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use crate::csg::{Solid, Span};
+use std::sync::{Arc};
+
+// a box, named to stay clear of std's Box; the axes are orthonormal and right handed
+pub struct Cuboid {
+    center: Point,
+    half: Vec3,
+    axes: [Vec3; 3],
+    mat: Arc<dyn Bsdf>,
+}
+
+impl Cuboid {
+    // axis aligned box spanning two opposite corners
+    pub fn new(a: Point, b: Point, m: impl Into<Arc<dyn Bsdf>>) -> Cuboid {
+        let min = Point::new([a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())]);
+        let max = Point::new([a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())]);
+        Cuboid {
+            center: (min + max) / 2.0,
+            half: (max - min) / 2.0,
+            axes: [
+                Vec3::new([1.0, 0.0, 0.0]),
+                Vec3::new([0.0, 1.0, 0.0]),
+                Vec3::new([0.0, 0.0, 1.0]),
+            ],
+            mat: m.into(),
+        }
+    }
+
+    // box rotated so its local x axis follows `axis_u` and its y axis lies in the plane of `axis_u` and `axis_v`
+    pub fn oriented(
+        center: Point,
+        half_extents: Vec3,
+        axis_u: Vec3,
+        axis_v: Vec3,
+        m: impl Into<Arc<dyn Bsdf>>,
+    ) -> Cuboid {
+        let x = axis_u.unit();
+        let z = x.cross(&axis_v).unit();
+        let y = z.cross(&x);
+        Cuboid {
+            center,
+            half: half_extents,
+            axes: [x, y, z],
+            mat: m.into(),
+        }
+    }
+
+    // the six faces as outward facing quads, e.g. to register the box as an area light
+    pub fn quads(&self) -> Vec<Quad> {
+        let mut faces = Vec::with_capacity(6);
+        for i in 0..3 {
+            let j = (i + 1) % 3;
+            let k = (i + 2) % 3;
+            let edge_j = 2.0 * self.half[j] * self.axes[j];
+            let edge_k = 2.0 * self.half[k] * self.axes[k];
+            let base = self.center - self.half[j] * self.axes[j] - self.half[k] * self.axes[k];
+
+            let front = base + self.half[i] * self.axes[i];
+            faces.push(Quad::new(front, edge_j, edge_k, Arc::clone(&self.mat)));
+            let back = base - self.half[i] * self.axes[i];
+            faces.push(Quad::new(back, edge_k, edge_j, Arc::clone(&self.mat)));
+        }
+        faces
+    }
+
+    fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new([v.dot(&self.axes[0]), v.dot(&self.axes[1]), v.dot(&self.axes[2])])
+    }
+}
+
+impl Cuboid {
+    // slab test over the whole line: entry and exit t with the axis of the face bounding each
+    fn slabs(&self, ray: &Ray) -> Option<((f64, usize), (f64, usize))> {
+        let origin = self.to_local(&(*ray.org() - self.center));
+        let direction = self.to_local(ray.direct());
+
+        let mut t_near = f64::NEG_INFINITY;
+        let mut t_far = f64::INFINITY;
+        let mut near_axis = 0;
+        let mut far_axis = 0;
+        for i in 0..3 {
+            let inv = 1.0 / direction[i];
+            let mut t0 = (-self.half[i] - origin[i]) * inv;
+            let mut t1 = (self.half[i] - origin[i]) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = i;
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = i;
+            }
+        }
+        if t_near > t_far {
+            return None;
+        }
+        Some(((t_near, near_axis), (t_far, far_axis)))
+    }
+
+    // `exiting` picks the face the ray leaves through rather than the one it enters by
+    fn record(&self, ray: &Ray, t: f64, axis: usize, exiting: bool) -> HitRecord {
+        let direction = self.to_local(ray.direct());
+        let sign = if exiting { direction[axis].signum() } else { -direction[axis].signum() };
+
+        // face uv follows the edge order of the matching quad from `quads`
+        let local = self.to_local(&(ray.range(t) - self.center));
+        let j = (axis + 1) % 3;
+        let k = (axis + 2) % 3;
+        let s = (local[j] + self.half[j]) / (2.0 * self.half[j]);
+        let r = (local[k] + self.half[k]) / (2.0 * self.half[k]);
+        let uv = if sign > 0.0 { (s, r) } else { (r, s) };
+
+        let outward = sign * self.axes[axis];
+        let front_face = ray.direct().dot(&outward) < 0.0;
+        let normal = if front_face { outward } else { outward.reverse() };
+        HitRecord::new(t, ray.range(t), normal, front_face, uv, Arc::clone(&self.mat))
+    }
+}
+
+impl Hittable for Cuboid {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let ((t_near, near_axis), (t_far, far_axis)) = self.slabs(ray)?;
+        if t_min <= t_near && t_near <= t_max {
+            Some(self.record(ray, t_near, near_axis, false))
+        } else if t_min <= t_far && t_far <= t_max {
+            Some(self.record(ray, t_far, far_axis, true))
+        } else {
+            None
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut extent = Vec3::new([0.0; 3]);
+        for (axis, half) in self.axes.iter().zip([self.half.x(), self.half.y(), self.half.z()]) {
+            for i in 0..3 {
+                extent[i] += axis[i].abs() * half;
+            }
+        }
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+impl Solid for Cuboid {
+    fn intersect_all(&self, ray: &Ray) -> Vec<Span> {
+        match self.slabs(ray) {
+            Some(((t_near, near_axis), (t_far, far_axis))) => vec![Span {
+                enter: self.record(ray, t_near, near_axis, false),
+                exit: self.record(ray, t_far, far_axis, true),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
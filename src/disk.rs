@@ -0,0 +1,90 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::vec3::{Point, Vec3, Onb};
+use crate::world::{INF};
+use crate::aabb::{Aabb};
+use std::f64::consts::PI;
+use std::sync::{Arc};
+use rand::Rng;
+
+pub struct Disk {
+    center: Point,
+    radius: f64,
+    frame: Onb,
+    mat: Arc<dyn Bsdf>,
+}
+
+impl Disk {
+    pub fn new(center: Point, normal: Vec3, radius: f64, m: impl Into<Arc<dyn Bsdf>>) -> Disk {
+        Disk {
+            center,
+            radius,
+            frame: Onb::new(&normal.unit()),
+            mat: m.into(),
+        }
+    }
+
+    fn normal(&self) -> &Vec3 {
+        self.frame.w()
+    }
+}
+
+impl Hittable for Disk {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = self.normal().dot(ray.direct());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = self.normal().dot(&(self.center - *ray.org())) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let position = ray.range(t);
+        let local = self.frame.to_local(&(position - self.center));
+        let r = (local.x() * local.x() + local.y() * local.y()).sqrt();
+        if r > self.radius {
+            return None;
+        }
+
+        // u runs around the rim, v from the center outwards
+        let phi = local.y().atan2(local.x());
+        let u = phi.rem_euclid(2.0 * PI) / (2.0 * PI);
+        let v = r / self.radius;
+
+        let front_face = denom < 0.0;
+        let normal = if front_face { *self.normal() } else { self.normal().reverse() };
+        Some(HitRecord::new(t, position, normal, front_face, (u, v), Arc::clone(&self.mat)))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = self.normal();
+        let extent = Vec3::new([
+            self.radius * (1.0 - n.x() * n.x()).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y() * n.y()).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z() * n.z()).max(0.0).sqrt(),
+        ]);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, time: f64) -> f64 {
+        match self.intersect(&Ray::with_time(*origin, *direction, time), 0.001, INF) {
+            Some(rec) => {
+                let distance_squared = rec.t() * rec.t() * direction.square();
+                let cosine = (direction.dot(self.normal()) / direction.length()).abs();
+                if cosine < 1e-8 { return 0.0; }
+                distance_squared / (cosine * PI * self.radius * self.radius)
+            },
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point, _time: f64) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let r = self.radius * rng.gen_range(0.0..1.0_f64).sqrt();
+        let phi = 2.0 * PI * rng.gen_range(0.0..1.0);
+        let p = self.center + self.frame.to_world(&Vec3::new([r * phi.cos(), r * phi.sin(), 0.0]));
+        p - *origin
+    }
+}
@@ -0,0 +1,102 @@
+use crate::color::{Color, BLACK, luminance};
+use crate::image::{Image};
+
+// luminance below this counts as this much when judging noise, so the noise of near black
+// pixels, invisible in the image, does not dominate
+const NOISE_FLOOR: f64 = 0.1;
+
+// the samples one pass took over a rectangle of pixels, row by row
+pub(crate) struct TileSamples {
+    pub sums: Vec<Color>,
+    pub squares: Vec<f64>,
+    pub counts: Vec<u32>,
+}
+
+// Running sums of the radiance samples taken for each pixel, plus the sums of their squared
+// luminance to estimate noise, so a render can be built up over several passes and picked
+// up again later.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    sums: Vec<Color>,
+    squares: Vec<f64>,
+    samples: Vec<u32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        let pixels = width * height;
+        Film { width, height, sums: vec![BLACK; pixels], squares: vec![0.0; pixels], samples: vec![0; pixels] }
+    }
+
+    pub fn from_parts(width: usize, height: usize, sums: Vec<Color>, squares: Vec<f64>, samples: Vec<u32>) -> Film {
+        assert_eq!(width * height, sums.len(), "pixel count does not match film size");
+        assert!(sums.len() == squares.len() && sums.len() == samples.len(), "pixel data of different lengths");
+        Film { width, height, sums, squares, samples }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn sums(&self) -> &[Color] {
+        &self.sums
+    }
+
+    pub fn squares(&self) -> &[f64] {
+        &self.squares
+    }
+
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.samples
+    }
+
+    // adds the samples of a rectangle `width` pixels wide with its corner at (x, y), and
+    // returns how many there were
+    pub(crate) fn add_tile(&mut self, x: usize, y: usize, width: usize, tile: &TileSamples) -> usize {
+        let mut added = 0;
+        for (k, count) in tile.counts.iter().enumerate() {
+            let index = (y + k / width) * self.width + x + k % width;
+            self.sums[index] = self.sums[index] + tile.sums[k];
+            self.squares[index] += tile.squares[k];
+            self.samples[index] += count;
+            added += *count as usize;
+        }
+        added
+    }
+
+    // the fewest samples any pixel has
+    pub fn min_samples(&self) -> u32 {
+        self.samples.iter().copied().min().unwrap_or(0)
+    }
+
+    // The estimated relative error of the image: the standard error of each pixel's mean
+    // luminance over that luminance, averaged over the pixels. Infinite until every pixel
+    // has two samples.
+    pub fn noise(&self) -> f64 {
+        let mut total = 0.0;
+        for ((sum, square), n) in self.sums.iter().zip(&self.squares).zip(&self.samples) {
+            if *n < 2 {
+                return f64::INFINITY;
+            }
+            let n = *n as f64;
+            let mean = luminance(sum) / n;
+            let variance = ((square / n - mean * mean) * n / (n - 1.0)).max(0.0);
+            total += (variance / n).sqrt() / mean.max(NOISE_FLOOR);
+        }
+        total / self.samples.len().max(1) as f64
+    }
+
+    // the mean of each pixel's samples, black where there are none yet
+    pub fn image(&self) -> Image {
+        let pixels = self.sums.iter().zip(&self.samples)
+            .map(|(sum, n)| if *n == 0 { BLACK } else { *sum / *n as f64 })
+            .collect();
+        Image::new(self.width, self.height, pixels)
+    }
+}
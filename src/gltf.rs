@@ -0,0 +1,829 @@
+// Importer for glTF 2.0 scenes, either a .gltf document whose buffers and images live in
+// separate files or data URIs, or a binary .glb container. The default scene's node
+// hierarchy is flattened into world space: triangle meshes with their normals and first set
+// of texture coordinates, metallic-roughness materials as principled materials, emissive
+// materials as area lights, the first perspective camera and KHR_lights_punctual lights.
+// Features the renderer has no counterpart for, such as skins, morph targets, animations,
+// alpha modes and the metallic-roughness and normal textures, are skipped as the glTF
+// specification allows for optional data; extensions a file marks as required and this
+// importer does not know fail the import instead.
+//
+// Punctual lights become small emitting spheres, so spot cones are not honored, and a
+// directional light becomes a distant disk about the size of the sun in the sky. Their
+// intensities are taken as they are in the renderer's units.
+use crate::json::{Json};
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color, BLACK};
+use crate::world::{World, Background, ORIGIN};
+use crate::camera::{Camera};
+use crate::ray::{Hittable};
+use crate::material::{Material, Bsdf};
+use crate::principled::{Principled};
+use crate::texture::{Texture};
+use crate::image::{Image, ImageTexture};
+use crate::transform::{Mat4, Transform};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::sphere::{Sphere};
+use crate::disk::{Disk};
+use crate::aabb::{Aabb};
+use crate::bvh::{Bvh};
+use crate::scene::{SceneError};
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc};
+
+const CHUNK_JSON: u32 = 0x4e4f534a;
+const CHUNK_BIN: u32 = 0x004e4942;
+
+const EXTENSIONS: [&str; 4] = [
+    "KHR_lights_punctual",
+    "KHR_materials_emissive_strength",
+    "KHR_materials_ior",
+    "KHR_materials_transmission",
+];
+
+// angular diameter of the disk standing in for a directional light
+const SUN_ANGLE: f64 = 0.53;
+
+// deeper node hierarchies are rejected rather than risk running out of stack
+const MAX_NODE_DEPTH: usize = 1000;
+
+// the most numbers one accessor may hold; an accessor without a buffer view is all zeros,
+// so nothing else bounds its size
+const MAX_ACCESSOR_VALUES: usize = 1 << 26;
+
+fn invalid<T>(what: impl Into<String>) -> Result<T, SceneError> {
+    Err(SceneError::Invalid(what.into()))
+}
+
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<(World, Camera), SceneError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+    decode_gltf(&bytes, path.parent().unwrap_or(Path::new("")))
+}
+
+// `directory` is where external buffers and images are looked up
+pub fn decode_gltf(bytes: &[u8], directory: impl AsRef<Path>) -> Result<(World, Camera), SceneError> {
+    let (text, binary) = if bytes.starts_with(b"glTF") {
+        split_glb(bytes)?
+    } else {
+        (bytes, None)
+    };
+    let Ok(text) = std::str::from_utf8(text) else {
+        return invalid("the glTF document is not UTF-8 text");
+    };
+    let root = match Json::parse(text.trim_start_matches('\u{feff}')) {
+        Ok(root) => root,
+        Err(e) => return Err(SceneError::Parse { line: e.line, column: e.column, message: e.message }),
+    };
+    let document = Document::new(root, binary, directory.as_ref().to_path_buf())?;
+    Importer::new(&document).run()
+}
+
+// the JSON chunk and the optional binary chunk of a .glb container
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), SceneError> {
+    let word = |at: usize| bytes.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+    if word(4) != Some(2) {
+        return invalid("only version 2 glb containers are supported");
+    }
+    let length = word(8).map_or(0, |n| n as usize).min(bytes.len());
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let (size, kind) = (word(offset).unwrap() as usize, word(offset + 4).unwrap());
+        let Some(data) = bytes.get(offset + 8..offset + 8 + size) else {
+            return invalid("glb chunk runs past the end of the file");
+        };
+        chunks.push((kind, data));
+        offset += 8 + size;
+    }
+    match chunks.as_slice() {
+        [(CHUNK_JSON, json), (CHUNK_BIN, binary), ..] => Ok((json, Some(binary))),
+        [(CHUNK_JSON, json), ..] => Ok((json, None)),
+        _ => invalid("glb container does not start with a JSON chunk"),
+    }
+}
+
+// the array under `key`, empty when absent
+fn list<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key).and_then(Json::as_array).unwrap_or(&[])
+}
+
+fn index(json: &Json, key: &str, context: &str) -> Result<Option<usize>, SceneError> {
+    match json.get(key) {
+        None => Ok(None),
+        Some(value) => match value.as_usize() {
+            Some(i) => Ok(Some(i)),
+            None => invalid(format!("{}: \"{}\" must be an index", context, key)),
+        },
+    }
+}
+
+fn number(json: &Json, key: &str, default: f64, context: &str) -> Result<f64, SceneError> {
+    match json.get(key) {
+        None => Ok(default),
+        Some(value) => match value.as_f64() {
+            Some(n) => Ok(n),
+            None => invalid(format!("{}: \"{}\" must be a number", context, key)),
+        },
+    }
+}
+
+fn numbers<const N: usize>(json: &Json, key: &str, default: [f64; N], context: &str) -> Result<[f64; N], SceneError> {
+    let Some(value) = json.get(key) else {
+        return Ok(default);
+    };
+    let items: Option<Vec<f64>> = value.as_array().and_then(|items| items.iter().map(Json::as_f64).collect());
+    match items.and_then(|items| items.try_into().ok()) {
+        Some(n) => Ok(n),
+        None => invalid(format!("{}: \"{}\" must be an array of {} numbers", context, key, N)),
+    }
+}
+
+fn base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// URIs escape spaces and other characters as %XX
+fn percent_decode(text: &str) -> String {
+    let raw = text.as_bytes();
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        let hex = raw.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (raw[i], hex) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// the parsed document with its buffers loaded
+struct Document {
+    root: Json,
+    directory: PathBuf,
+    buffers: Vec<Vec<u8>>,
+    // the external buffer files
+    files: Vec<PathBuf>,
+}
+
+impl Document {
+    fn new(root: Json, binary: Option<&[u8]>, directory: PathBuf) -> Result<Document, SceneError> {
+        let version = root.get("asset").and_then(|a| a.get("version")).and_then(Json::as_str);
+        match version {
+            Some(v) if v.starts_with("2.") => {},
+            Some(v) => return invalid(format!("glTF version {} is not supported", v)),
+            None => return invalid("the document has no asset version"),
+        }
+        for extension in list(&root, "extensionsRequired") {
+            let name = extension.as_str().unwrap_or("");
+            if !EXTENSIONS.contains(&name) {
+                return invalid(format!("required extension \"{}\" is not supported", name));
+            }
+        }
+
+        let mut document = Document { root, directory, buffers: Vec::new(), files: Vec::new() };
+        for (i, buffer) in list(&document.root, "buffers").iter().enumerate() {
+            let context = format!("buffers[{}]", i);
+            let data = match buffer.get("uri").map(Json::as_str) {
+                Some(Some(uri)) => {
+                    document.files.extend(document.uri_path(uri));
+                    document.read_uri(uri, &context)?
+                },
+                Some(None) => return invalid(format!("{}: \"uri\" must be a string", context)),
+                None if i == 0 && binary.is_some() => binary.unwrap().to_vec(),
+                None => return invalid(format!("{}: has no data", context)),
+            };
+            let length = index(buffer, "byteLength", &context)?.unwrap_or(0);
+            if data.len() < length {
+                return invalid(format!("{}: holds {} bytes instead of {}", context, data.len(), length));
+            }
+            document.buffers.push(data);
+        }
+        Ok(document)
+    }
+
+    // the file a uri names, none for data uris
+    fn uri_path(&self, uri: &str) -> Option<PathBuf> {
+        if uri.starts_with("data:") { None } else { Some(self.directory.join(percent_decode(uri))) }
+    }
+
+    fn read_uri(&self, uri: &str, context: &str) -> Result<Vec<u8>, SceneError> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let Some((media, data)) = data.split_once(',') else {
+                return invalid(format!("{}: malformed data URI", context));
+            };
+            if !media.ends_with(";base64") {
+                return Ok(percent_decode(data).into_bytes());
+            }
+            return match base64(data) {
+                Some(bytes) => Ok(bytes),
+                None => invalid(format!("{}: malformed base64 data", context)),
+            };
+        }
+        let path = self.directory.join(percent_decode(uri));
+        match fs::read(&path) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => invalid(format!("{}: could not read {}: {}", context, uri, e)),
+        }
+    }
+
+    fn item(&self, key: &str, i: usize) -> Result<&Json, SceneError> {
+        match list(&self.root, key).get(i) {
+            Some(item) => Ok(item),
+            None => invalid(format!("{}[{}] does not exist", key, i)),
+        }
+    }
+
+    // the elements of an accessor flattened into numbers, with the number of components per
+    // element; normalized integers are mapped to [0, 1] or [-1, 1]
+    fn accessor(&self, i: usize) -> Result<(Vec<f64>, usize), SceneError> {
+        let context = format!("accessors[{}]", i);
+        let accessor = self.item("accessors", i)?;
+        if accessor.get("sparse").is_some() {
+            return invalid(format!("{}: sparse accessors are not supported", context));
+        }
+        let count = index(accessor, "count", &context)?.unwrap_or(0);
+        let width = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return invalid(format!("{}: unknown \"type\"", context)),
+        };
+        let normalized = accessor.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+        let kind = index(accessor, "componentType", &context)?.unwrap_or(0);
+        let (size, scale) = match kind {
+            5120 => (1, i8::MAX as f64),
+            5121 => (1, u8::MAX as f64),
+            5122 => (2, i16::MAX as f64),
+            5123 => (2, u16::MAX as f64),
+            5125 => (4, u32::MAX as f64),
+            5126 => (4, 1.0),
+            _ => return invalid(format!("{}: unknown \"componentType\"", context)),
+        };
+        let Some(total) = count.checked_mul(width).filter(|total| *total <= MAX_ACCESSOR_VALUES) else {
+            return invalid(format!("{}: \"count\" is too large", context));
+        };
+
+        let Some(view) = index(accessor, "bufferView", &context)? else {
+            return Ok((vec![0.0; total], width));
+        };
+        let view_context = format!("bufferViews[{}]", view);
+        let view = self.item("bufferViews", view)?;
+        let buffer = index(view, "buffer", &view_context)?.unwrap_or(0);
+        let Some(buffer) = self.buffers.get(buffer) else {
+            return invalid(format!("{}: buffer {} does not exist", view_context, buffer));
+        };
+        let start = index(view, "byteOffset", &view_context)?.unwrap_or(0);
+        let length = index(view, "byteLength", &view_context)?.unwrap_or(0);
+        let Some(data) = buffer.get(start..start.saturating_add(length)) else {
+            return invalid(format!("{}: runs past the end of its buffer", view_context));
+        };
+        let stride = index(view, "byteStride", &view_context)?.unwrap_or(size * width);
+        let offset = index(accessor, "byteOffset", &context)?.unwrap_or(0);
+
+        let mut values = Vec::with_capacity(total.min(data.len()));
+        for element in 0..count {
+            for component in 0..width {
+                let at = offset.saturating_add(element.saturating_mul(stride)).saturating_add(component * size);
+                let Some(raw) = data.get(at..at.saturating_add(size)) else {
+                    return invalid(format!("{}: reads past the end of its buffer view", context));
+                };
+                let value = match kind {
+                    5120 => raw[0] as i8 as f64,
+                    5121 => raw[0] as f64,
+                    5122 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    5123 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    5125 => u32::from_le_bytes(raw.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(raw.try_into().unwrap()) as f64,
+                };
+                values.push(if normalized && kind != 5126 { (value / scale).max(-1.0) } else { value });
+            }
+        }
+        Ok((values, width))
+    }
+
+    fn vectors(&self, i: usize, context: &str) -> Result<Vec<Vec3>, SceneError> {
+        match self.accessor(i)? {
+            (values, 3) => Ok(values.chunks_exact(3).map(|c| Vec3::new([c[0], c[1], c[2]])).collect()),
+            _ => invalid(format!("{}: accessor {} does not hold 3D vectors", context, i)),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl Wrap {
+    fn new(mode: Option<usize>) -> Wrap {
+        match mode {
+            Some(33071) => Wrap::Clamp,
+            Some(33648) => Wrap::Mirror,
+            _ => Wrap::Repeat,
+        }
+    }
+
+    fn apply(self, x: f64) -> f64 {
+        match self {
+            Wrap::Repeat => x.rem_euclid(1.0),
+            Wrap::Clamp => x.clamp(0.0, 1.0),
+            Wrap::Mirror => {
+                let x = x.rem_euclid(2.0);
+                if x > 1.0 { 2.0 - x } else { x }
+            },
+        }
+    }
+}
+
+// a material color: a constant factor, times an image when the material references one
+struct Factored {
+    factor: Color,
+    image: Option<(ImageTexture, Wrap, Wrap)>,
+}
+
+impl Texture for Factored {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color {
+        match &self.image {
+            None => self.factor,
+            // v was flipped on import; wrapping applies to glTF's own coordinates
+            Some((image, wrap_u, wrap_v)) => {
+                self.factor * image.value(wrap_u.apply(u), 1.0 - wrap_v.apply(1.0 - v), p)
+            },
+        }
+    }
+}
+
+struct Importer<'a> {
+    document: &'a Document,
+    images: HashMap<usize, Arc<Image>>,
+    // materials by index, with whether they emit light
+    materials: HashMap<Option<usize>, (Arc<dyn Bsdf>, bool)>,
+    objects: Vec<Arc<dyn Hittable>>,
+    lights: Vec<Arc<dyn Hittable>>,
+    punctual: Vec<(usize, Transform)>,
+    camera: Option<Camera>,
+    bounds: Option<Aabb>,
+    // the external image files
+    files: Vec<PathBuf>,
+    // nodes already placed; each may have only one parent
+    visited: HashSet<usize>,
+}
+
+impl<'a> Importer<'a> {
+    fn new(document: &'a Document) -> Importer<'a> {
+        Importer {
+            document,
+            images: HashMap::new(),
+            materials: HashMap::new(),
+            objects: Vec::new(),
+            lights: Vec::new(),
+            punctual: Vec::new(),
+            camera: None,
+            bounds: None,
+            files: Vec::new(),
+            visited: HashSet::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<(World, Camera), SceneError> {
+        let document = self.document;
+        let root = &document.root;
+        let scene = index(root, "scene", "document")?.unwrap_or(0);
+        let nodes: Vec<usize> = match list(root, "scenes").get(scene) {
+            Some(entry) => {
+                let context = format!("scenes[{}]", scene);
+                let nodes = list(entry, "nodes").iter().map(Json::as_usize).collect::<Option<_>>();
+                match nodes {
+                    Some(nodes) => nodes,
+                    None => return invalid(format!("{}: \"nodes\" must hold indices", context)),
+                }
+            },
+            // without scenes every node that is nobody's child is a root
+            None => {
+                let children: Vec<usize> = list(root, "nodes").iter()
+                    .flat_map(|n| list(n, "children").iter().filter_map(Json::as_usize))
+                    .collect();
+                (0..list(root, "nodes").len()).filter(|i| !children.contains(i)).collect()
+            },
+        };
+        for node in nodes {
+            self.node(node, &Transform::identity(), 0)?;
+        }
+        self.finish()
+    }
+
+    fn node(&mut self, i: usize, parent: &Transform, depth: usize) -> Result<(), SceneError> {
+        let document = self.document;
+        let context = format!("nodes[{}]", i);
+        if !self.visited.insert(i) {
+            return invalid(format!("{}: is reached more than once in the node hierarchy", context));
+        }
+        if depth > MAX_NODE_DEPTH {
+            return invalid(format!("{}: the node hierarchy is more than {} deep", context, MAX_NODE_DEPTH));
+        }
+        let node = document.item("nodes", i)?;
+
+        // a collapsed transform such as a zero scale hides the node and its children
+        let Some(local) = node_transform(node, &context)? else {
+            return Ok(());
+        };
+        let world = local.then(parent);
+
+        if let Some(mesh) = index(node, "mesh", &context)? {
+            self.mesh(mesh, &world)?;
+        }
+        if let Some(camera) = index(node, "camera", &context)? {
+            if self.camera.is_none() {
+                self.camera = camera_from(document, camera, &world)?;
+            }
+        }
+        let light = node.get("extensions").and_then(|e| e.get("KHR_lights_punctual"));
+        if let Some(light) = light {
+            match index(light, "light", &context)? {
+                Some(light) => self.punctual.push((light, world)),
+                None => return invalid(format!("{}: punctual light without \"light\"", context)),
+            }
+        }
+        for child in list(node, "children") {
+            let Some(child) = child.as_usize() else {
+                return invalid(format!("{}: \"children\" must hold indices", context));
+            };
+            self.node(child, &world, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn mesh(&mut self, i: usize, world: &Transform) -> Result<(), SceneError> {
+        let document = self.document;
+        let mesh = document.item("meshes", i)?;
+        for (p, primitive) in list(mesh, "primitives").iter().enumerate() {
+            let context = format!("meshes[{}].primitives[{}]", i, p);
+            let empty = Json::Object(Vec::new());
+            let attributes = primitive.get("attributes").unwrap_or(&empty);
+            let Some(positions) = index(attributes, "POSITION", &context)? else {
+                return invalid(format!("{}: has no \"POSITION\" attribute", context));
+            };
+            let positions = document.vectors(positions, &context)?;
+            let count = positions.len();
+
+            let order: Vec<usize> = match index(primitive, "indices", &context)? {
+                Some(indices) => {
+                    let (values, _) = document.accessor(indices)?;
+                    match values.iter().map(|v| Some(*v as usize).filter(|i| *i < count)).collect() {
+                        Some(order) => order,
+                        None => return invalid(format!("{}: vertex index out of range", context)),
+                    }
+                },
+                None => (0..count).collect(),
+            };
+            let triangles: Vec<[usize; 3]> = match number(primitive, "mode", 4.0, &context)? as usize {
+                4 => order.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+                // strips alternate their winding to keep every triangle facing the same way
+                5 => (2..order.len()).map(|k| {
+                    if k % 2 == 0 {
+                        [order[k - 2], order[k - 1], order[k]]
+                    } else {
+                        [order[k - 1], order[k - 2], order[k]]
+                    }
+                }).collect(),
+                6 => (2..order.len()).map(|k| [order[0], order[k - 1], order[k]]).collect(),
+                // points and lines have no surface to render
+                0..=3 => continue,
+                mode => return invalid(format!("{}: unknown \"mode\" {}", context, mode)),
+            };
+
+            let mut mesh = Mesh::new(positions, triangles);
+            if let Some(normals) = index(attributes, "NORMAL", &context)? {
+                mesh.normals = document.vectors(normals, &context)?;
+            }
+            if let Some(uvs) = index(attributes, "TEXCOORD_0", &context)? {
+                let (values, width) = document.accessor(uvs)?;
+                if width != 2 {
+                    return invalid(format!("{}: \"TEXCOORD_0\" must hold 2D vectors", context));
+                }
+                // glTF images start at the top while v runs upwards here
+                mesh.uvs = values.chunks_exact(2).map(|c| (c[0], 1.0 - c[1])).collect();
+            }
+            if let Err(e) = mesh.validate() {
+                return invalid(format!("{}: {}", context, e));
+            }
+            mesh.transform(world);
+            if mesh.positions.is_empty() || mesh.indices.is_empty() {
+                continue;
+            }
+
+            let mesh_bounds = Aabb::from_points(&mesh.positions);
+            self.bounds = Some(self.bounds.map_or(mesh_bounds, |b| b.union(&mesh_bounds)));
+            let (material, emissive) = self.material(index(primitive, "material", &context)?)?;
+            let object = Arc::new(TriangleMesh::new(mesh, material));
+            if emissive {
+                self.lights.push(object);
+            } else {
+                self.objects.push(object);
+            }
+        }
+        Ok(())
+    }
+
+    fn material(&mut self, i: Option<usize>) -> Result<(Arc<dyn Bsdf>, bool), SceneError> {
+        if let Some(material) = self.materials.get(&i) {
+            return Ok(material.clone());
+        }
+        let document = self.document;
+        let empty = Json::Object(Vec::new());
+        let (material, context) = match i {
+            Some(i) => (document.item("materials", i)?, format!("materials[{}]", i)),
+            None => (&empty, "default material".to_string()),
+        };
+        let extension = |name: &str| material.get("extensions").and_then(|e| e.get(name)).unwrap_or(&empty);
+
+        let emissive = numbers(material, "emissiveFactor", [0.0; 3], &context)?;
+        let strength = number(extension("KHR_materials_emissive_strength"), "emissiveStrength", 1.0, &context)?;
+        let emissive = strength * Color::new(emissive);
+        let result: (Arc<dyn Bsdf>, bool) = if emissive.square() > 0.0 {
+            let image = self.texture_ref(material.get("emissiveTexture"), &context)?;
+            (Arc::new(Material::DiffuseLight(Arc::new(Factored { factor: emissive, image }))), true)
+        } else {
+            let pbr = material.get("pbrMetallicRoughness").unwrap_or(&empty);
+            let [r, g, b, _] = numbers(pbr, "baseColorFactor", [1.0; 4], &context)?;
+            let image = self.texture_ref(pbr.get("baseColorTexture"), &context)?;
+            let base = Factored { factor: Color::new([r, g, b]), image };
+            let principled = Principled::new(Arc::new(base))
+                .metallic(number(pbr, "metallicFactor", 1.0, &context)?)
+                .roughness(number(pbr, "roughnessFactor", 1.0, &context)?)
+                .ior(number(extension("KHR_materials_ior"), "ior", 1.5, &context)?)
+                .transmission(number(extension("KHR_materials_transmission"), "transmissionFactor", 0.0, &context)?);
+            (Arc::new(Material::Principled(principled)), false)
+        };
+        self.materials.insert(i, result.clone());
+        Ok(result)
+    }
+
+    // the image and wrap modes of a material's texture reference
+    fn texture_ref(&mut self, reference: Option<&Json>, context: &str) -> Result<Option<(ImageTexture, Wrap, Wrap)>, SceneError> {
+        let Some(reference) = reference else {
+            return Ok(None);
+        };
+        let Some(texture) = index(reference, "index", context)? else {
+            return invalid(format!("{}: texture reference without \"index\"", context));
+        };
+        let document = self.document;
+        let texture_context = format!("textures[{}]", texture);
+        let texture = document.item("textures", texture)?;
+        let Some(source) = index(texture, "source", &texture_context)? else {
+            return invalid(format!("{}: has no image \"source\"", texture_context));
+        };
+
+        let (wrap_u, wrap_v) = match index(texture, "sampler", &texture_context)? {
+            Some(sampler) => {
+                let sampler_context = format!("samplers[{}]", sampler);
+                let sampler = document.item("samplers", sampler)?;
+                (
+                    Wrap::new(index(sampler, "wrapS", &sampler_context)?),
+                    Wrap::new(index(sampler, "wrapT", &sampler_context)?),
+                )
+            },
+            None => (Wrap::Repeat, Wrap::Repeat),
+        };
+        let image = match self.images.get(&source) {
+            Some(image) => Arc::clone(image),
+            None => {
+                let image = Arc::new(self.image(source)?);
+                self.images.insert(source, Arc::clone(&image));
+                image
+            },
+        };
+        Ok(Some((ImageTexture::new(image), wrap_u, wrap_v)))
+    }
+
+    fn image(&mut self, i: usize) -> Result<Image, SceneError> {
+        let document = self.document;
+        let context = format!("images[{}]", i);
+        let image = document.item("images", i)?;
+        let bytes = match (image.get("uri").and_then(Json::as_str), index(image, "bufferView", &context)?) {
+            (Some(uri), _) => {
+                self.files.extend(document.uri_path(uri));
+                document.read_uri(uri, &context)?
+            },
+            (None, Some(view)) => {
+                let view_context = format!("bufferViews[{}]", view);
+                let view = document.item("bufferViews", view)?;
+                let buffer = index(view, "buffer", &view_context)?.unwrap_or(0);
+                let start = index(view, "byteOffset", &view_context)?.unwrap_or(0);
+                let length = index(view, "byteLength", &view_context)?.unwrap_or(0);
+                match document.buffers.get(buffer).and_then(|b| b.get(start..start.saturating_add(length))) {
+                    Some(bytes) => bytes.to_vec(),
+                    None => return invalid(format!("{}: runs past the end of its buffer", view_context)),
+                }
+            },
+            (None, None) => return invalid(format!("{}: has no data", context)),
+        };
+        match Image::decode(&bytes) {
+            Ok(image) => Ok(image),
+            Err(e) => invalid(format!("{}: {}", context, e)),
+        }
+    }
+
+    fn punctual_light(&self, i: usize, world: &Transform, size: f64) -> Result<Arc<dyn Hittable>, SceneError> {
+        let root = &self.document.root;
+        let context = format!("KHR_lights_punctual.lights[{}]", i);
+        let lights = root.get("extensions").and_then(|e| e.get("KHR_lights_punctual"));
+        let Some(light) = lights.and_then(|l| list(l, "lights").get(i)) else {
+            return invalid(format!("{} does not exist", context));
+        };
+        let color = Color::new(numbers(light, "color", [1.0; 3], &context)?);
+        let intensity = number(light, "intensity", 1.0, &context)?;
+
+        match light.get("type").and_then(Json::as_str) {
+            // a sphere of radius r and radiance L has an intensity of L pi r^2
+            Some("point") | Some("spot") => {
+                let radius = 0.002 * size;
+                let radiance = intensity / (PI * radius * radius) * color;
+                let sphere = Sphere::new(world.point(&ORIGIN), radius, Material::DiffuseLight(Arc::new(radiance)));
+                Ok(Arc::new(sphere))
+            },
+            // a disk covering a solid angle of pi sin^2(theta) gives an irradiance of L times that
+            Some("directional") => {
+                let direction = world.vector(&Vec3::new([0.0, 0.0, -1.0])).unit();
+                let distance = 100.0 * size;
+                let half_angle = (SUN_ANGLE / 2.0).to_radians();
+                let radius = distance * half_angle.tan();
+                let radiance = intensity / (PI * half_angle.sin().powi(2)) * color;
+                let center = self.bounds.map_or(ORIGIN, |b| b.centroid()) - distance * direction;
+                Ok(Arc::new(Disk::new(center, direction, radius, Material::DiffuseLight(Arc::new(radiance)))))
+            },
+            _ => invalid(format!("{}: unknown light \"type\"", context)),
+        }
+    }
+
+    fn finish(mut self) -> Result<(World, Camera), SceneError> {
+        // punctual lights are sized after the scene they light
+        let size = self.bounds.map_or(1.0, |b| b.extent().length()).max(1e-3);
+        for (light, world) in std::mem::take(&mut self.punctual) {
+            let light = self.punctual_light(light, &world, size)?;
+            self.lights.push(light);
+        }
+
+        // without a camera the scene is framed from the front, the way glTF models face
+        let camera = match self.camera {
+            Some(camera) => camera,
+            None => {
+                let center = self.bounds.map_or(ORIGIN, |b| b.centroid());
+                let fov: f64 = 40.0;
+                let distance = 0.5 * size / (fov / 2.0).to_radians().sin();
+                Camera::new(center + Vec3::new([0.0, 0.0, distance]), center).v_fov(fov).defocus(0.0, distance)
+            },
+        };
+
+        let mut world = World::new();
+        for file in self.document.files.iter().chain(&self.files) {
+            world.add_source(file);
+        }
+        // files without any light keep the default sky so they still render
+        if !self.lights.is_empty() {
+            world.set_background(Background::Solid(BLACK));
+        }
+        for light in self.lights {
+            world.add_light(light);
+        }
+        if !self.objects.is_empty() {
+            world.add(Arc::new(Bvh::new(self.objects)));
+        }
+        Ok((world, camera))
+    }
+}
+
+// a node's matrix, or its translation, rotation and scale; None when it is not invertible
+fn node_transform(node: &Json, context: &str) -> Result<Option<Transform>, SceneError> {
+    let mut rows = [[0.0; 4]; 4];
+    if node.get("matrix").is_some() {
+        // listed column by column
+        let m = numbers::<16>(node, "matrix", [0.0; 16], context)?;
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = m[j * 4 + i];
+            }
+        }
+    } else {
+        let t = numbers(node, "translation", [0.0; 3], context)?;
+        let [x, y, z, w] = numbers(node, "rotation", [0.0, 0.0, 0.0, 1.0], context)?;
+        let s = numbers(node, "scale", [1.0; 3], context)?;
+        let length = (x * x + y * y + z * z + w * w).sqrt();
+        if length == 0.0 {
+            return invalid(format!("{}: \"rotation\" is not a unit quaternion", context));
+        }
+        let (x, y, z, w) = (x / length, y / length, z / length, w / length);
+        let r = [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+        ];
+        for i in 0..3 {
+            for j in 0..3 {
+                rows[i][j] = r[i][j] * s[j];
+            }
+            rows[i][3] = t[i];
+        }
+        rows[3][3] = 1.0;
+    }
+    Ok(Transform::from_matrix(Mat4::new(rows)))
+}
+
+// cameras look down their node's -z axis with +y up; orthographic ones are passed over
+fn camera_from(document: &Document, i: usize, world: &Transform) -> Result<Option<Camera>, SceneError> {
+    let context = format!("cameras[{}]", i);
+    let camera = document.item("cameras", i)?;
+    if camera.get("type").and_then(Json::as_str) != Some("perspective") {
+        return Ok(None);
+    }
+    let Some(perspective) = camera.get("perspective") else {
+        return invalid(format!("{}: has no \"perspective\" properties", context));
+    };
+    let Some(yfov) = perspective.get("yfov").and_then(Json::as_f64) else {
+        return invalid(format!("{}: has no \"yfov\"", context));
+    };
+
+    let eye = world.point(&ORIGIN);
+    let forward = world.vector(&Vec3::new([0.0, 0.0, -1.0])).unit();
+    let up = world.vector(&Vec3::new([0.0, 1.0, 0.0]));
+    let mut camera = Camera::new(eye, eye + forward).up(up).v_fov(yfov.to_degrees()).defocus(0.0, 1.0);
+    if let Some(aspect) = perspective.get("aspectRatio").and_then(Json::as_f64).filter(|a| *a > 0.0) {
+        let width = camera.width();
+        let height = (width as f64 / aspect).round().max(1.0) as u32;
+        camera = camera.resolution(width, height);
+    }
+    Ok(Some(camera))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_accessor_without_buffer_view_is_rejected() {
+        let source = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+            "accessors": [{ "count": 1000000000000, "type": "VEC3", "componentType": 5126 }]
+        }"#;
+        match decode_gltf(source.as_bytes(), "") {
+            Err(SceneError::Invalid(message)) => assert!(message.contains("count"), "{}", message),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("loaded a trillion vertices"),
+        }
+    }
+
+    fn node_error(nodes: &str) -> String {
+        let source = format!(r#"{{ "asset": {{ "version": "2.0" }}, "scenes": [{{ "nodes": [0] }}], "nodes": {} }}"#, nodes);
+        match decode_gltf(source.as_bytes(), "") {
+            Err(SceneError::Invalid(message)) => message,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("loaded {}", nodes),
+        }
+    }
+
+    #[test]
+    fn nodes_reached_twice_are_rejected() {
+        assert!(node_error(r#"[{ "children": [1] }, { "children": [0] }]"#).contains("more than once"));
+        // each node listing the next one twice would take 2^40 visits
+        let chain: Vec<String> = (0..40).map(|i| format!(r#"{{ "children": [{}, {}] }}"#, i + 1, i + 1)).collect();
+        let nodes = format!("[{}, {{}}]", chain.join(", "));
+        assert!(node_error(&nodes).contains("more than once"));
+    }
+}
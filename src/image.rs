@@ -0,0 +1,266 @@
+use crate::color::{Color, BLACK};
+use crate::texture::{Texture};
+use crate::vec3::{Point};
+use std::fmt;
+use std::fs;
+use std::path::{Path};
+use std::sync::{Arc};
+
+mod inflate;
+mod deflate;
+mod ppm;
+mod png;
+mod hdr;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    UnknownFormat,
+    Unsupported(String),
+    Corrupt(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "could not read image: {}", e),
+            ImageError::UnknownFormat => write!(f, "unrecognized image format"),
+            ImageError::Unsupported(what) => write!(f, "unsupported image: {}", what),
+            ImageError::Corrupt(what) => write!(f, "corrupt image: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(e: std::io::Error) -> ImageError {
+        ImageError::Io(e)
+    }
+}
+
+fn corrupt<T>(what: &str) -> Result<T, ImageError> {
+    Err(ImageError::Corrupt(what.to_string()))
+}
+
+// 8192x8192; the sizes in a header are checked against this before anything is allocated,
+// and so is each side on its own since a row can be allocated even when there are none
+const MAX_PIXELS: usize = 1 << 26;
+
+fn pixel_count(width: usize, height: usize) -> Result<usize, ImageError> {
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= MAX_PIXELS && width.max(height) <= MAX_PIXELS => Ok(pixels),
+        _ => Err(ImageError::Unsupported(format!("{}x{} image is too large", width, height))),
+    }
+}
+
+// the file formats images can be written in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Hdr,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Option<ImageFormat> {
+        ImageFormat::from_name(path.as_ref().extension()?.to_str()?)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+            ImageFormat::Hdr => "hdr",
+        }
+    }
+}
+
+// pixels are stored row by row from the top, always in linear color space
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+        assert_eq!(width * height, pixels.len(), "pixel count does not match image size");
+        Image { width, height, pixels }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Image, ImageError> {
+        let bytes = fs::read(path)?;
+        Image::decode(&bytes)
+    }
+
+    // the format is detected from the leading magic bytes
+    pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+        if bytes.starts_with(&png::SIGNATURE) {
+            png::decode(bytes)
+        } else if bytes.starts_with(b"P3") || bytes.starts_with(b"P6") {
+            ppm::decode(bytes)
+        } else if bytes.starts_with(b"#?") {
+            hdr::decode(bytes)
+        } else {
+            Err(ImageError::UnknownFormat)
+        }
+    }
+
+    // 8-bit formats clamp to [0, 1] and encode sRGB; hdr keeps the linear values
+    pub fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Ppm => ppm::encode(self),
+            ImageFormat::Png => png::encode(self),
+            ImageFormat::Hdr => hdr::encode(self),
+        }
+    }
+
+    // the format follows the file extension
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ImageError> {
+        let Some(format) = ImageFormat::from_path(&path) else {
+            return Err(ImageError::UnknownFormat);
+        };
+        self.save_as(path, format)
+    }
+
+    pub fn save_as(&self, path: impl AsRef<Path>, format: ImageFormat) -> Result<(), ImageError> {
+        fs::write(path, self.encode(format))?;
+        Ok(())
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        if self.pixels.is_empty() { return BLACK; }
+        let x = x.min(self.width - 1);
+        let y = y.min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+}
+
+pub fn srgb_to_linear(val: f64) -> f64 {
+    if val <= 0.04045 {
+        val / 12.92
+    } else {
+        ((val + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(val: f64) -> f64 {
+    if val <= 0.0031308 {
+        val * 12.92
+    } else {
+        1.055 * val.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// a linear channel as an sRGB byte; NaNs from broken samples come out black
+fn srgb_byte(val: f64) -> u8 {
+    let val = if val.is_nan() { 0.0 } else { val.clamp(0.0, 1.0) };
+    (linear_to_srgb(val) * 255.0).round() as u8
+}
+
+#[derive(Clone)]
+pub struct ImageTexture {
+    image: Arc<Image>,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> ImageTexture {
+        ImageTexture { image }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<ImageTexture, ImageError> {
+        Ok(ImageTexture::new(Arc::new(Image::load(path)?)))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point) -> Color {
+        if self.image.height == 0 { return Color::new([0.0, 1.0, 1.0]); }
+
+        // image rows run top to bottom while v runs bottom to top
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let x = (u * self.image.width as f64) as usize;
+        let y = (v * self.image.height as f64) as usize;
+        self.image.pixel(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_with_header(width: u32, height: u32, idat: &[u8]) -> Vec<u8> {
+        let mut bytes = png::SIGNATURE.to_vec();
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        for (kind, data) in [(b"IHDR", &ihdr[..]), (b"IDAT", idat), (b"IEND", &[])] {
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            let start = bytes.len();
+            bytes.extend_from_slice(kind);
+            bytes.extend_from_slice(data);
+            let crc = png::crc32(&bytes[start..]);
+            bytes.extend_from_slice(&crc.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected_before_allocating() {
+        let overflowing = format!("P6 {} {} 255\n", usize::MAX, usize::MAX);
+        assert!(matches!(Image::decode(overflowing.as_bytes()), Err(ImageError::Unsupported(_))));
+        assert!(matches!(Image::decode(b"P3 100000 100000 255\n"), Err(ImageError::Unsupported(_))));
+        assert!(matches!(Image::decode(b"P3 1000 1000 255\n0 0 0\n"), Err(ImageError::Corrupt(_))));
+        let hdr = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 4000000000 +X 4000000000\n";
+        assert!(matches!(Image::decode(hdr), Err(ImageError::Unsupported(_))));
+        let idat = deflate::zlib_compress(&[0; 16]);
+        let png = png_with_header(u32::MAX, u32::MAX, &idat);
+        assert!(matches!(Image::decode(&png), Err(ImageError::Unsupported(_))));
+    }
+
+    #[test]
+    fn hdr_repeat_markers_cannot_overflow() {
+        let mut hdr = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 4\n".to_vec();
+        hdr.extend_from_slice(&[10, 20, 30, 128]);
+        for _ in 0..9 {
+            hdr.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert!(matches!(Image::decode(&hdr), Err(ImageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn png_data_longer_than_the_image_is_rejected() {
+        // a 2x2 rgb image needs 2 rows of a filter byte and 6 samples
+        let idat = deflate::zlib_compress(&[0; 1 << 20]);
+        assert!(matches!(Image::decode(&png_with_header(2, 2, &idat)), Err(ImageError::Corrupt(_))));
+        let idat = deflate::zlib_compress(&[0; 14]);
+        assert!(Image::decode(&png_with_header(2, 2, &idat)).is_ok());
+    }
+}
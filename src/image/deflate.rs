@@ -0,0 +1,133 @@
+// A small DEFLATE encoder with the zlib wrapper for writing PNG: one block with the fixed
+// huffman codes and greedy LZ77 matches found through hash chains.
+use super::inflate::{LENGTH_BASE, LENGTH_EXTRA, DIST_BASE, DIST_EXTRA, adler32};
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// how many earlier positions with the same prefix are tried per match
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    // values are packed starting from the least significant bit
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // huffman codes are packed starting from their most significant bit
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+fn literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn length_and_distance(writer: &mut BitWriter, length: usize, distance: usize) {
+    let i = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+    literal(writer, 257 + i as u32);
+    writer.bits((length - LENGTH_BASE[i] as usize) as u32, LENGTH_EXTRA[i] as u32);
+
+    let i = DIST_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+    writer.code(i as u32, 5);
+    writer.bits((distance - DIST_BASE[i] as usize) as u32, DIST_EXTRA[i] as u32);
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let key = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+    (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+// the most recent position of each hashed prefix, and for every position the one before it
+struct Chains {
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl Chains {
+    fn insert(&mut self, data: &[u8], i: usize) {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(data, i);
+            self.previous[i % WINDOW] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+}
+
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { out: vec![0x78, 0x01], buffer: 0, count: 0 };
+    // final block with fixed codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let mut chains = Chains { head: vec![usize::MAX; 1 << HASH_BITS], previous: vec![usize::MAX; WINDOW] };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let limit = MAX_MATCH.min(data.len() - i);
+            let mut candidate = chains.head[hash(data, i)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || i - candidate > WINDOW - 1 {
+                    break;
+                }
+                let length = (0..limit).take_while(|k| data[candidate + k] == data[i + k]).count();
+                if length > best.0 {
+                    best = (length, i - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+                let next = chains.previous[candidate % WINDOW];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            length_and_distance(&mut writer, best.0, best.1);
+            for k in i..i + best.0 {
+                chains.insert(data, k);
+            }
+            i += best.0;
+        } else {
+            literal(&mut writer, data[i] as u32);
+            chains.insert(data, i);
+            i += 1;
+        }
+    }
+    literal(&mut writer, 256);
+
+    let mut out = writer.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
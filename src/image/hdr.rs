@@ -0,0 +1,194 @@
+// Radiance RGBE (.hdr) images, flat or run-length encoded. Values are already linear.
+use super::{Image, ImageError, corrupt, pixel_count};
+use crate::color::{Color};
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut pos = 0;
+    let mut format_ok = true;
+
+    // header lines end at the first empty line
+    loop {
+        let line = read_line(bytes, &mut pos)?;
+        if line.is_empty() { break; }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            format_ok = format == "32-bit_rle_rgbe";
+        }
+    }
+    if !format_ok {
+        return Err(ImageError::Unsupported("hdr pixel format other than 32-bit_rle_rgbe".to_string()));
+    }
+
+    let resolution = read_line(bytes, &mut pos)?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    if fields.len() != 4 || fields[0] != "-Y" || fields[2] != "+X" {
+        return Err(ImageError::Unsupported(format!("hdr orientation '{}'", resolution)));
+    }
+    let (height, width) = match (fields[1].parse::<usize>(), fields[3].parse::<usize>()) {
+        (Ok(h), Ok(w)) => (h, w),
+        _ => return corrupt("hdr resolution line is invalid"),
+    };
+
+    let mut pixels = Vec::with_capacity(pixel_count(width, height)?);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(bytes, &mut pos, &mut scanline)?;
+        pixels.extend(scanline.iter().map(rgbe_to_color));
+    }
+
+    Ok(Image::new(width, height, pixels))
+}
+
+fn read_line(bytes: &[u8], pos: &mut usize) -> Result<String, ImageError> {
+    let start = *pos;
+    while *pos < bytes.len() && bytes[*pos] != b'\n' {
+        *pos += 1;
+    }
+    if *pos >= bytes.len() {
+        return corrupt("hdr header ended early");
+    }
+    let line = String::from_utf8_lossy(&bytes[start..*pos]).trim().to_string();
+    *pos += 1;
+    Ok(line)
+}
+
+fn byte(bytes: &[u8], pos: &mut usize) -> Result<u8, ImageError> {
+    match bytes.get(*pos) {
+        Some(&b) => {
+            *pos += 1;
+            Ok(b)
+        },
+        None => corrupt("hdr pixel data too short"),
+    }
+}
+
+fn read_scanline(bytes: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), ImageError> {
+    let width = scanline.len();
+    let is_rle = (8..32768).contains(&width)
+        && bytes.len() >= *pos + 4
+        && bytes[*pos] == 2
+        && bytes[*pos + 1] == 2
+        && bytes[*pos + 2] & 0x80 == 0;
+    if !is_rle {
+        return read_flat(bytes, pos, scanline);
+    }
+
+    let encoded_width = ((bytes[*pos + 2] as usize) << 8) | bytes[*pos + 3] as usize;
+    if encoded_width != width {
+        return corrupt("hdr scanline width mismatch");
+    }
+    *pos += 4;
+
+    // each of the four components is run-length encoded separately
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = byte(bytes, pos)? as usize;
+            if count > 128 {
+                let run = count - 128;
+                let value = byte(bytes, pos)?;
+                if x + run > width {
+                    return corrupt("hdr run overflows scanline");
+                }
+                for pixel in scanline.iter_mut().skip(x).take(run) {
+                    pixel[component] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || x + count > width {
+                    return corrupt("hdr run overflows scanline");
+                }
+                for pixel in scanline.iter_mut().skip(x).take(count) {
+                    pixel[component] = byte(bytes, pos)?;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+// uncompressed pixels, possibly using the old style (1, 1, 1, n) repeat markers
+fn read_flat(bytes: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), ImageError> {
+    let mut x = 0;
+    let mut shift = 0;
+    while x < scanline.len() {
+        let rgbe = [byte(bytes, pos)?, byte(bytes, pos)?, byte(bytes, pos)?, byte(bytes, pos)?];
+        if rgbe[0] == 1 && rgbe[1] == 1 && rgbe[2] == 1 {
+            if x == 0 {
+                return corrupt("hdr repeat marker at start of scanline");
+            }
+            // consecutive markers build up larger counts; more than three exceeds any width
+            if shift > 16 {
+                return corrupt("hdr has too many repeat markers in a row");
+            }
+            let run = (rgbe[3] as usize) << shift;
+            if x + run > scanline.len() {
+                return corrupt("hdr run overflows scanline");
+            }
+            let previous = scanline[x - 1];
+            for pixel in scanline.iter_mut().skip(x).take(run) {
+                *pixel = previous;
+            }
+            x += run;
+            shift += 8;
+        } else {
+            scanline[x] = rgbe;
+            x += 1;
+            shift = 0;
+        }
+    }
+    Ok(())
+}
+
+fn rgbe_to_color(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new([0.0; 3]);
+    }
+    let scale = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new([
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    ])
+}
+
+// scanlines in the run-length layout where readers expect it, without actual runs; flat
+// pixels that would read as a repeat marker are nudged by one step of blue
+pub fn encode(image: &Image) -> Vec<u8> {
+    let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height, image.width);
+    let mut bytes = header.into_bytes();
+    let width = image.width;
+    for row in image.pixels.chunks(width.max(1)) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(color_to_rgbe).collect();
+        if (8..32768).contains(&width) {
+            bytes.extend([2, 2, (width >> 8) as u8, width as u8]);
+            for component in 0..4 {
+                for chunk in rgbe.chunks(128) {
+                    bytes.push(chunk.len() as u8);
+                    bytes.extend(chunk.iter().map(|pixel| pixel[component]));
+                }
+            }
+        } else {
+            for mut pixel in rgbe {
+                if pixel[..3] == [1, 1, 1] {
+                    pixel[2] = 2;
+                }
+                bytes.extend(pixel);
+            }
+        }
+    }
+    bytes
+}
+
+// the shared exponent is that of the brightest channel
+fn color_to_rgbe(color: &Color) -> [u8; 4] {
+    let channels = [color.x(), color.y(), color.z()].map(|c| if c.is_nan() { 0.0 } else { c.clamp(0.0, 1e38) });
+    let brightest = channels.iter().cloned().fold(0.0, f64::max);
+    if brightest < 1e-32 {
+        return [0; 4];
+    }
+    let exponent = brightest.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(exponent);
+    let [r, g, b] = channels.map(|c| (c * scale).min(255.0) as u8);
+    [r, g, b, (exponent + 128).clamp(0, 255) as u8]
+}
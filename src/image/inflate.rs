@@ -0,0 +1,295 @@
+// A small DEFLATE (RFC 1951) decoder with the zlib (RFC 1950) wrapper, enough for PNG.
+use super::{ImageError, corrupt};
+
+pub const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+pub const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+// order in which code length code lengths are stored in a dynamic block header
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, pos: 0, bit: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, ImageError> {
+        let mut result = 0;
+        for i in 0..count {
+            if self.pos >= self.data.len() {
+                return corrupt("deflate stream ended early");
+            }
+            let b = (self.data[self.pos] >> self.bit) & 1;
+            result |= (b as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(result)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// canonical huffman decoding table: symbol counts per length and symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, ImageError> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ImageError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        corrupt("invalid huffman code")
+    }
+}
+
+// fails rather than produce more than `limit` bytes
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, ImageError> {
+    if data.len() < 6 {
+        return corrupt("zlib stream too short");
+    }
+    let cmf = data[0];
+    let flg = data[1];
+    if cmf & 0x0f != 8 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) {
+        return corrupt("bad zlib header");
+    }
+    if flg & 0x20 != 0 {
+        return Err(ImageError::Unsupported("zlib preset dictionary".to_string()));
+    }
+
+    let (out, consumed) = inflate(&data[2..], limit)?;
+    let trailer = &data[2 + consumed..];
+    if trailer.len() >= 4 {
+        let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        if adler32(&out) != expected {
+            return corrupt("zlib checksum mismatch");
+        }
+    }
+    Ok(out)
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// returns the decompressed bytes and how many input bytes were consumed
+fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), ImageError> {
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)?;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut out, limit)?,
+            1 => {
+                let (lit, dist) = fixed_tables()?;
+                compressed_block(&mut reader, &mut out, &lit, &dist, limit)?;
+            },
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut reader)?;
+                compressed_block(&mut reader, &mut out, &lit, &dist, limit)?;
+            },
+            _ => return corrupt("invalid deflate block type"),
+        }
+        if last == 1 { break; }
+    }
+
+    reader.align();
+    Ok((out, reader.pos))
+}
+
+fn stored_block(reader: &mut BitReader, out: &mut Vec<u8>, limit: usize) -> Result<(), ImageError> {
+    reader.align();
+    let data = reader.data;
+    let pos = reader.pos;
+    if pos + 4 > data.len() {
+        return corrupt("deflate stream ended early");
+    }
+    let len = u16::from_le_bytes([data[pos], data[pos + 1]]);
+    let nlen = u16::from_le_bytes([data[pos + 2], data[pos + 3]]);
+    if len != !nlen {
+        return corrupt("stored block length mismatch");
+    }
+    let start = pos + 4;
+    let end = start + len as usize;
+    if end > data.len() {
+        return corrupt("deflate stream ended early");
+    }
+    if out.len() + len as usize > limit {
+        return corrupt("deflate stream is longer than expected");
+    }
+    out.extend_from_slice(&data[start..end]);
+    reader.pos = end;
+    Ok(())
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman), ImageError> {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5u8; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), ImageError> {
+    let hlit = reader.bits(5)? as usize + 257;
+    let hdist = reader.bits(5)? as usize + 1;
+    let hclen = reader.bits(4)? as usize + 4;
+
+    let mut clen_lengths = [0u8; 19];
+    for &index in CLEN_ORDER.iter().take(hclen) {
+        clen_lengths[index] = reader.bits(3)? as u8;
+    }
+    let clen = Huffman::new(&clen_lengths)?;
+
+    let mut lengths = vec![0u8; hlit + hdist];
+    let mut i = 0;
+    while i < hlit + hdist {
+        let symbol = clen.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return corrupt("length repeat with no previous length");
+                }
+                (lengths[i - 1], 3 + reader.bits(2)? as usize)
+            },
+            17 => (0, 3 + reader.bits(3)? as usize),
+            18 => (0, 11 + reader.bits(7)? as usize),
+            _ => return corrupt("invalid code length symbol"),
+        };
+        if i + repeat > lengths.len() {
+            return corrupt("code lengths overflow");
+        }
+        for len in lengths.iter_mut().skip(i).take(repeat) {
+            *len = value;
+        }
+        i += repeat;
+    }
+
+    Ok((Huffman::new(&lengths[..hlit])?, Huffman::new(&lengths[hlit..])?))
+}
+
+fn compressed_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    lit: &Huffman,
+    dist: &Huffman,
+    limit: usize,
+) -> Result<(), ImageError> {
+    loop {
+        let symbol = lit.decode(reader)? as usize;
+        if symbol < 256 {
+            if out.len() >= limit {
+                return corrupt("deflate stream is longer than expected");
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return corrupt("invalid length symbol");
+        }
+        let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+        let dist_symbol = dist.decode(reader)? as usize;
+        if dist_symbol >= DIST_BASE.len() {
+            return corrupt("invalid distance symbol");
+        }
+        let distance = DIST_BASE[dist_symbol] as usize
+            + reader.bits(DIST_EXTRA[dist_symbol] as u32)? as usize;
+        if distance > out.len() {
+            return corrupt("distance reaches before start of output");
+        }
+        if out.len() + length > limit {
+            return corrupt("deflate stream is longer than expected");
+        }
+
+        // copies may overlap their own output, so go byte by byte
+        let start = out.len() - distance;
+        for k in 0..length {
+            let byte = out[start + k];
+            out.push(byte);
+        }
+    }
+}
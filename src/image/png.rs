@@ -0,0 +1,331 @@
+use super::{Image, ImageError, corrupt, pixel_count, srgb_to_linear, srgb_byte};
+use super::inflate::{zlib_decompress};
+use super::deflate::{zlib_compress};
+use crate::color::{Color};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+// (x start, y start, x step, y step) of each Adam7 pass
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4),
+    (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2),
+];
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            GRAY | PALETTE => 1,
+            GRAY_ALPHA => 2,
+            RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    // filters work on whole bytes, so sub-byte pixels count as one
+    fn filter_stride(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    // the size of the decompressed image data: each row of each pass and its filter byte
+    fn data_size(&self) -> usize {
+        if !self.interlaced {
+            return self.height * (self.row_bytes(self.width) + 1);
+        }
+        let mut size = 0;
+        for &(x0, y0, dx, dy) in ADAM7.iter() {
+            if x0 >= self.width || y0 >= self.height { continue; }
+            let pass_width = (self.width - x0).div_ceil(dx);
+            let pass_height = (self.height - y0).div_ceil(dy);
+            size += pass_height * (self.row_bytes(pass_width) + 1);
+        }
+        size
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut pos = SIGNATURE.len();
+    let mut header = None;
+    let mut palette: Vec<Color> = Vec::new();
+    let mut idat = Vec::new();
+
+    loop {
+        if pos + 8 > bytes.len() {
+            return corrupt("png ended before IEND");
+        }
+        let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        let data_start = pos + 8;
+        let data_end = data_start + len;
+        if data_end + 4 > bytes.len() {
+            return corrupt("png chunk runs past end of file");
+        }
+        let data = &bytes[data_start..data_end];
+        let crc = u32::from_be_bytes([
+            bytes[data_end], bytes[data_end + 1], bytes[data_end + 2], bytes[data_end + 3],
+        ]);
+        if crc32(&bytes[pos + 4..data_end]) != crc {
+            return corrupt("png chunk checksum mismatch");
+        }
+        pos = data_end + 4;
+
+        match kind {
+            b"IHDR" => header = Some(parse_header(data)?),
+            b"PLTE" => {
+                if !len.is_multiple_of(3) {
+                    return corrupt("png palette length is not a multiple of 3");
+                }
+                palette = data.chunks(3)
+                    .map(|rgb| Color::new([
+                        srgb_to_linear(rgb[0] as f64 / 255.0),
+                        srgb_to_linear(rgb[1] as f64 / 255.0),
+                        srgb_to_linear(rgb[2] as f64 / 255.0),
+                    ]))
+                    .collect();
+            },
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {
+                // an unknown critical chunk means we cannot decode the image correctly
+                if kind[0] & 0x20 == 0 {
+                    let name = String::from_utf8_lossy(kind);
+                    return Err(ImageError::Unsupported(format!("png chunk {}", name)));
+                }
+            },
+        }
+    }
+
+    let header = match header {
+        Some(h) => h,
+        None => return corrupt("png has no IHDR chunk"),
+    };
+    if header.color_type == PALETTE && palette.is_empty() {
+        return corrupt("indexed png has no palette");
+    }
+
+    let pixels = pixel_count(header.width, header.height)?;
+    let raw = zlib_decompress(&idat, header.data_size())?;
+    let mut pixels = vec![Color::new([0.0; 3]); pixels];
+
+    if header.interlaced {
+        let mut offset = 0;
+        for &(x0, y0, dx, dy) in ADAM7.iter() {
+            if x0 >= header.width || y0 >= header.height { continue; }
+            let pass_width = (header.width - x0).div_ceil(dx);
+            let pass_height = (header.height - y0).div_ceil(dy);
+            let size = pass_height * (header.row_bytes(pass_width) + 1);
+            if offset + size > raw.len() {
+                return corrupt("png image data too short");
+            }
+            let rows = unfilter(&header, &raw[offset..offset + size], pass_width, pass_height)?;
+            offset += size;
+            for (py, row) in rows.iter().enumerate() {
+                for px in 0..pass_width {
+                    let color = sample(&header, &palette, row, px)?;
+                    pixels[(y0 + py * dy) * header.width + x0 + px * dx] = color;
+                }
+            }
+        }
+    } else {
+        let size = header.height * (header.row_bytes(header.width) + 1);
+        if size > raw.len() {
+            return corrupt("png image data too short");
+        }
+        let rows = unfilter(&header, &raw[..size], header.width, header.height)?;
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..header.width {
+                pixels[y * header.width + x] = sample(&header, &palette, row, x)?;
+            }
+        }
+    }
+
+    Ok(Image::new(header.width, header.height, pixels))
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, ImageError> {
+    if data.len() != 13 {
+        return corrupt("png IHDR has wrong length");
+    }
+    let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let bit_depth = data[8];
+    let color_type = data[9];
+
+    let valid_depth = match color_type {
+        GRAY => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        PALETTE => matches!(bit_depth, 1 | 2 | 4 | 8),
+        RGB | GRAY_ALPHA | RGBA => matches!(bit_depth, 8 | 16),
+        _ => return corrupt("png has an invalid color type"),
+    };
+    if !valid_depth {
+        return corrupt("png bit depth is invalid for its color type");
+    }
+    if data[10] != 0 || data[11] != 0 {
+        return Err(ImageError::Unsupported("png compression or filter method".to_string()));
+    }
+    if width == 0 || height == 0 {
+        return corrupt("png has zero size");
+    }
+
+    Ok(Header {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        interlaced: match data[12] {
+            0 => false,
+            1 => true,
+            _ => return corrupt("png has an invalid interlace method"),
+        },
+    })
+}
+
+fn unfilter(header: &Header, data: &[u8], width: usize, height: usize) -> Result<Vec<Vec<u8>>, ImageError> {
+    let stride = header.filter_stride();
+    let row_bytes = header.row_bytes(width);
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height);
+    let mut prev = vec![0u8; row_bytes];
+
+    for y in 0..height {
+        let line = &data[y * (row_bytes + 1)..(y + 1) * (row_bytes + 1)];
+        let filter = line[0];
+        let mut row = line[1..].to_vec();
+
+        for i in 0..row_bytes {
+            let a = if i >= stride { row[i - stride] } else { 0 };
+            let b = prev[i];
+            let c = if i >= stride { prev[i - stride] } else { 0 };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return corrupt("png uses an invalid row filter"),
+            };
+            row[i] = row[i].wrapping_add(predictor);
+        }
+
+        prev.clone_from(&row);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// channel `index` of pixel `x`, scaled to [0, 1]
+fn channel(header: &Header, row: &[u8], x: usize, index: usize) -> f64 {
+    let depth = header.bit_depth as usize;
+    let sample = x * header.channels() + index;
+    match depth {
+        16 => u16::from_be_bytes([row[sample * 2], row[sample * 2 + 1]]) as f64 / 65535.0,
+        8 => row[sample] as f64 / 255.0,
+        _ => {
+            let bit = sample * depth;
+            let shift = 8 - depth - bit % 8;
+            let max = (1u16 << depth) - 1;
+            ((row[bit / 8] >> shift) as u16 & max) as f64 / max as f64
+        },
+    }
+}
+
+fn sample(header: &Header, palette: &[Color], row: &[u8], x: usize) -> Result<Color, ImageError> {
+    let color = match header.color_type {
+        PALETTE => {
+            let max = ((1u16 << header.bit_depth) - 1) as f64;
+            let index = (channel(header, row, x, 0) * max).round() as usize;
+            return match palette.get(index) {
+                Some(c) => Ok(*c),
+                None => corrupt("png palette index out of range"),
+            };
+        },
+        GRAY | GRAY_ALPHA => {
+            let g = channel(header, row, x, 0);
+            Color::new([g, g, g])
+        },
+        _ => Color::new([
+            channel(header, row, x, 0),
+            channel(header, row, x, 1),
+            channel(header, row, x, 2),
+        ]),
+    };
+    Ok(Color::new([
+        srgb_to_linear(color.x()),
+        srgb_to_linear(color.y()),
+        srgb_to_linear(color.z()),
+    ]))
+}
+
+// 8-bit RGB, rows unfiltered
+pub fn encode(image: &Image) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(image.height * (1 + 3 * image.width));
+    for row in image.pixels.chunks(image.width.max(1)) {
+        raw.push(0);
+        for color in row {
+            raw.extend([srgb_byte(color.x()), srgb_byte(color.y()), srgb_byte(color.z())]);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    header.extend([8, RGB, 0, 0, 0]);
+
+    let mut bytes = SIGNATURE.to_vec();
+    for (kind, data) in [(b"IHDR", header), (b"IDAT", zlib_compress(&raw)), (b"IEND", Vec::new())] {
+        bytes.extend((data.len() as u32).to_be_bytes());
+        let start = bytes.len();
+        bytes.extend(kind);
+        bytes.extend(&data);
+        let crc = crc32(&bytes[start..]);
+        bytes.extend(crc.to_be_bytes());
+    }
+    bytes
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,98 @@
+use super::{Image, ImageError, corrupt, pixel_count, srgb_to_linear, srgb_byte};
+use crate::color::{Color};
+
+struct Tokens<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    // whitespace separated ascii fields, `#` starts a comment running to end of line
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if self.pos < self.data.len() && self.data[self.pos] == b'#' {
+                while self.pos < self.data.len() && self.data[self.pos] != b'\n' {
+                    self.pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = self.pos;
+        while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos { None } else { Some(&self.data[start..self.pos]) }
+    }
+
+    fn number(&mut self, what: &str) -> Result<usize, ImageError> {
+        let token = match self.next() {
+            Some(t) => t,
+            None => return corrupt(&format!("ppm ended while reading {}", what)),
+        };
+        match std::str::from_utf8(token).ok().and_then(|s| s.parse().ok()) {
+            Some(n) => Ok(n),
+            None => corrupt(&format!("ppm has an invalid {}", what)),
+        }
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut tokens = Tokens { data: bytes, pos: 0 };
+    let binary = tokens.next() == Some(b"P6");
+    let width = tokens.number("width")?;
+    let height = tokens.number("height")?;
+    let max = tokens.number("maximum value")?;
+    if max == 0 || max > 65535 {
+        return corrupt("ppm maximum value out of range");
+    }
+
+    // every sample takes at least a byte, so a file too short for its size is rejected
+    // before the samples are allocated
+    let count = pixel_count(width, height)? * 3;
+    if count > bytes.len() - tokens.pos {
+        return corrupt("ppm raster data too short");
+    }
+    let mut values = Vec::with_capacity(count);
+    if binary {
+        // exactly one whitespace byte separates the header from the raster
+        let start = tokens.pos + 1;
+        let size = if max < 256 { 1 } else { 2 };
+        if start + count * size > bytes.len() {
+            return corrupt("ppm raster data too short");
+        }
+        let raster = &bytes[start..start + count * size];
+        if size == 1 {
+            values.extend(raster.iter().map(|&b| b as usize));
+        } else {
+            values.extend(raster.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize));
+        }
+    } else {
+        for _ in 0..count {
+            values.push(tokens.number("sample")?);
+        }
+    }
+
+    let scale = max as f64;
+    let pixels = values.chunks(3)
+        .map(|rgb| Color::new([
+            srgb_to_linear(rgb[0].min(max) as f64 / scale),
+            srgb_to_linear(rgb[1].min(max) as f64 / scale),
+            srgb_to_linear(rgb[2].min(max) as f64 / scale),
+        ]))
+        .collect();
+
+    Ok(Image::new(width, height, pixels))
+}
+
+// binary P6 with one byte per sample
+pub fn encode(image: &Image) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", image.width, image.height).into_bytes();
+    for color in &image.pixels {
+        bytes.extend([srgb_byte(color.x()), srgb_byte(color.y()), srgb_byte(color.z())]);
+    }
+    bytes
+}
@@ -0,0 +1,125 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::vec3::{Point, Vec3};
+use crate::transform::{Transform, AnimatedTransform};
+use crate::aabb::{Aabb};
+use std::sync::{Arc};
+
+// Places shared geometry in the world through an affine transform, optionally changing over
+// the shutter interval. Rays are carried into object space unnormalized, so the hit distance
+// t means the same thing in both spaces.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: AnimatedTransform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Instance {
+        Instance {
+            object,
+            transform: AnimatedTransform::fixed(transform),
+        }
+    }
+
+    // moves from `start` at time 0 to `end` at time 1
+    pub fn animated(object: Arc<dyn Hittable>, start: Transform, end: Transform) -> Instance {
+        Instance {
+            object,
+            transform: AnimatedTransform::new(start, end),
+        }
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.object
+    }
+
+    pub fn transform(&self) -> &AnimatedTransform {
+        &self.transform
+    }
+}
+
+impl Instance {
+    fn to_object(&self, ray: &Ray) -> (Transform, Ray) {
+        let transform = self.transform.at(ray.time());
+        let inverse = transform.inverse();
+        let local = Ray::with_time(inverse.point(ray.org()), inverse.vector(ray.direct()), ray.time());
+        (transform, local)
+    }
+}
+
+impl Hittable for Instance {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (transform, local) = self.to_object(ray);
+        let rec = self.object.intersect(&local, t_min, t_max)?;
+        // the inverse transpose keeps the side the normal faces, so front_face carries over
+        let normal = transform.normal(rec.normal()).unit();
+        let geometric = transform.normal(rec.geometric_normal()).unit();
+        let pos = ray.range(rec.t());
+        Some(rec.with_geometry(pos, normal).with_geometric_normal(geometric))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.object.transmittance(&self.to_object(ray).1, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box().map(|b| self.transform.bounding_box(&b))
+    }
+
+    // Light samples are drawn in object space and carried out by the transform. A linear map
+    // A stretches solid angle around a unit direction d by |A d|^3 / |det A|, so the object
+    // space density is divided by that; it is 1 for rotations and uniform scaling.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, time: f64) -> f64 {
+        let inverse = self.transform.at(time).inverse();
+        let local = inverse.vector(direction);
+        let pdf = self.object.pdf_value(&inverse.point(origin), &local, time);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+        let stretch = (local.length() / direction.length()).powi(3);
+        pdf * inverse.linear_determinant().abs() / stretch
+    }
+
+    fn random(&self, origin: &Point, time: f64) -> Vec3 {
+        let transform = self.transform.at(time);
+        let local = self.object.random(&transform.inverse().point(origin), time);
+        transform.vector(&local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material};
+    use crate::quad::{Quad};
+
+    fn quad(q: [f64; 3], u: [f64; 3], v: [f64; 3]) -> Arc<dyn Hittable> {
+        Arc::new(Quad::new(Point::new(q), Vec3::new(u), Vec3::new(v), Material::Dielectric(1.5)))
+    }
+
+    // the instanced light must have the same density as the same quad built in world space
+    fn assert_same_pdf(instance: &Instance, world: &dyn Hittable, time: f64) {
+        let origin = Point::new([0.3, -2.0, 0.1]);
+        for direction in [[0.05, 1.0, 0.05], [0.2, 1.0, 0.25], [-0.05, 1.0, 0.1]] {
+            let direction = Vec3::new(direction);
+            let expected = world.pdf_value(&origin, &direction, time);
+            let pdf = instance.pdf_value(&origin, &direction, time);
+            assert!(expected > 0.0 && (pdf - expected).abs() < 1e-9 * expected, "{} vs {}", pdf, expected);
+        }
+    }
+
+    #[test]
+    fn light_pdf_under_non_uniform_scaling() {
+        let unit = quad([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        let scaled = Instance::new(unit, Transform::scale(Vec3::new([1.0, 2.0, 3.0])));
+        let expected = quad([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 3.0]);
+        assert_same_pdf(&scaled, expected.as_ref(), 0.0);
+    }
+
+    #[test]
+    fn animated_light_pdf_follows_the_time() {
+        let unit = quad([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        let moving = Instance::animated(unit, Transform::identity(), Transform::translate(Vec3::new([0.0, 1.0, 0.0])));
+        let expected = quad([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        assert_same_pdf(&moving, expected.as_ref(), 1.0);
+    }
+}
@@ -0,0 +1,278 @@
+// A small JSON reader, enough for glTF documents. Objects keep their members in file order;
+// lookups return the first member with a given key.
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+// deeper nesting than this is rejected instead of risking the stack
+const MAX_DEPTH: usize = 256;
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut reader = Reader { chars: text.chars().collect(), index: 0, line: 1, column: 1 };
+        let value = reader.value(0)?;
+        reader.skip_whitespace();
+        if reader.peek().is_some() {
+            return reader.error("unexpected text after the document");
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    // non-negative whole numbers only
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= usize::MAX as f64 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Reader {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, JsonError> {
+        Err(JsonError { line: self.line, column: self.column, message: message.into() })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, wanted: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == wanted => {
+                self.bump();
+                Ok(())
+            },
+            Some(c) => self.error(format!("expected `{}`, found `{}`", wanted, c)),
+            None => self.error(format!("expected `{}`, found the end of the document", wanted)),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, JsonError> {
+        if depth > MAX_DEPTH {
+            return self.error("nested too deeply");
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            None => self.error("expected a value, found the end of the document"),
+            Some('{') => {
+                self.bump();
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.bump();
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return self.error("expected a member name");
+                    }
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    members.push((key, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.bump() {
+                        Some(',') => {},
+                        Some('}') => return Ok(Json::Object(members)),
+                        _ => return self.error("expected `,` or `}` after an object member"),
+                    }
+                }
+            },
+            Some('[') => {
+                self.bump();
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.bump();
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.bump() {
+                        Some(',') => {},
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return self.error("expected `,` or `]` after an array item"),
+                    }
+                }
+            },
+            Some('"') => Ok(Json::Str(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let (line, column) = (self.line, self.column);
+                let mut word = String::new();
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_alphabetic()) {
+                    word.push(c);
+                    self.bump();
+                }
+                match word.as_str() {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => Err(JsonError { line, column, message: format!("unexpected word `{}`", word) }),
+                }
+            },
+            Some(c) => self.error(format!("unexpected character `{}`", c)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+            text.push(c);
+            self.bump();
+        }
+        // JSON has no leading zeros, bare dots or infinities
+        let digits = text.trim_start_matches('-');
+        let valid = digits.starts_with(|c: char| c.is_ascii_digit())
+            && !(digits.len() > 1 && digits.starts_with('0') && digits.as_bytes()[1].is_ascii_digit())
+            && !text.ends_with('.');
+        match text.parse::<f64>() {
+            Ok(n) if valid && n.is_finite() => Ok(Json::Number(n)),
+            _ => Err(JsonError { line, column, message: format!("invalid number `{}`", text) }),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.bump();
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None => return self.error("unterminated string"),
+                Some('"') => return Ok(text),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.escaped_char()?,
+                        _ => return self.error("invalid escape sequence"),
+                    };
+                    text.push(c);
+                },
+                Some(c) if (c as u32) < 0x20 => return self.error("control character in string"),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    // the code after `\u`, combining surrogate pairs
+    fn escaped_char(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if self.bump() != Some('\\') || self.bump() != Some('u') {
+                return self.error("unpaired surrogate in string");
+            }
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return self.error("unpaired surrogate in string");
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        match char::from_u32(code) {
+            Some(c) => Ok(c),
+            None => self.error("unpaired surrogate in string"),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            match self.bump().and_then(|c| c.to_digit(16)) {
+                Some(digit) => code = code * 16 + digit,
+                None => return self.error("invalid `\\u` escape"),
+            }
+        }
+        Ok(code)
+    }
+}
@@ -1,19 +1,98 @@
 
 mod vec3;
-pub use vec3::{Point};
+pub use vec3::{Point, Vec3, Onb};
+mod transform;
+pub use transform::{Mat4, Transform, AnimatedTransform};
 
 mod ray;
+pub use ray::{Ray, HitRecord, Hittable};
+
+mod aabb;
+pub use aabb::{Aabb};
+
 mod color;
 pub use color::{Color};
 
 mod sphere;
 pub use sphere::{Sphere};
 
+mod quad;
+pub use quad::{Quad};
+
+mod disk;
+pub use disk::{Disk};
+
+mod plane;
+pub use plane::{Plane};
+
+mod cuboid;
+pub use cuboid::{Cuboid};
+
+mod roots;
+
+mod quadric;
+pub use quadric::{Quadric};
+mod torus;
+pub use torus::{Torus};
+mod instance;
+pub use instance::{Instance};
+mod bvh;
+pub use bvh::{Bvh};
+mod mesh;
+pub use mesh::{Mesh, TriangleMesh};
+mod ply;
+pub use ply::{load_ply, decode_ply, PlyError};
+mod csg;
+pub use csg::{Solid, Span, Csg, CsgOp};
+mod sdf;
+pub use sdf::{Sdf, SdfSphere, SdfBox, SdfTorus, Rounded, SmoothUnion, SmoothSubtraction, Repeat, RayMarched};
+mod medium;
+pub use medium::{ConstantMedium};
+mod volume;
+pub use volume::{VoxelGrid, GridMedium};
+
 mod world;
-pub use world::{World, INF, ORIGIN};
+pub use world::{World, Background, INF, ORIGIN};
 
 mod camera;
 pub use camera::Camera;
+mod film;
+pub use film::{Film};
+mod observer;
+pub use observer::{RenderObserver, Progress, CancelToken};
+mod checkpoint;
+pub use checkpoint::{Checkpoint, CheckpointError, fingerprint};
 
 mod material;
-pub use material::{Material};
\ No newline at end of file
+pub use material::{Material, Bsdf, BsdfSample};
+
+mod microfacet;
+pub use microfacet::{Conductor, RoughDielectric, TrowbridgeReitz, measured_metal};
+
+mod principled;
+pub use principled::{Principled};
+
+mod perlin;
+pub use perlin::{Perlin};
+
+mod texture;
+pub use texture::{Texture, NoiseTexture, Marble, Wood, Clouds, Checker};
+
+mod image;
+pub use image::{Image, ImageTexture, ImageError, ImageFormat};
+
+mod scene;
+pub use scene::{
+    Scene, SceneError, CameraDesc, TextureDesc, ColorSource, PrincipledDesc, MaterialDesc,
+    ShapeDesc, TransformDesc, ObjectDesc,
+};
+
+mod pbrt;
+pub use pbrt::{load_pbrt, parse_pbrt};
+
+mod catalog;
+pub use catalog::{BuiltInScene, BUILT_IN_SCENES, built_in_scene};
+
+mod json;
+mod gltf;
+pub use gltf::{load_gltf, decode_gltf};
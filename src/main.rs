@@ -1,55 +1,519 @@
-use lib::{Material, Camera, World, Sphere, Point, Color, ORIGIN};
+use lib::{
+    Scene, CameraDesc, TextureDesc, Camera, World, Film, Checkpoint, RenderObserver, Progress, CancelToken, ImageFormat,
+    load_pbrt, load_gltf, fingerprint, Hittable, BUILT_IN_SCENES, built_in_scene,
+};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path};
+use std::process;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use rand::Rng;
 
+const USAGE: &str = "\
+usage: ray-tracing [command] [scene file] [options]
+
+commands:
+  render   render the scene and write the image (the default)
+  info     describe the scene and its settings without rendering
+  bench    render the scene without writing it and report the speed
+  help     show this message
+
+The scene file is a .scene, .pbrt, .gltf or .glb file. Without one the built-in scene
+named by --scene is used, the random scene by default; see the list below.
+
+With --time or --noise the render stops adding samples once the time is up or the image is
+clean enough, and writes what it has; --spp then only caps the samples per pixel.
+
+Long renders save their progress to a checkpoint file every so often, removed again once
+the image is written. After an interrupted render, run the same command with --resume to
+carry on from the checkpoint.
+
+options:
+  -W, --width <pixels>          image width; the height keeps the aspect ratio unless given
+  -H, --height <pixels>         image height; the width keeps the aspect ratio unless given
+  -s, --spp <count>             samples per pixel
+  -d, --max-depth <count>       maximum number of bounces per path
+  -t, --threads <count>         worker threads, one per core by default
+      --seed <number>           seed for scenes generated at random
+  -o, --output <path>           where to write the image, out.<format> by default
+  -f, --format <format>         ppm, png or hdr; by default taken from the output path, else png
+      --scene <name>            built-in scene to use without a scene file
+      --time <duration>         time to render for, in seconds or such as 90s, 10m or 2h
+      --noise <fraction>        estimated noise to render down to, such as 0.01 for 1%
+      --resume                  continue the render saved in the checkpoint file
+      --checkpoint <path>       the checkpoint file, the output path with a .checkpoint extension
+                                by default
+      --checkpoint-every <s>    seconds between checkpoints, 60 by default; 0 turns them off
+";
+
+const DEFAULT_SCENE: &str = "random";
+const CHECKPOINT_SECONDS: u64 = 60;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// bench renders small unless told otherwise
+const BENCH_WIDTH: u32 = 400;
+const BENCH_SAMPLES: u16 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Command {
+    Render,
+    Info,
+    Bench,
+    Help,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Options {
+    file: Option<String>,
+    scene: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    samples: Option<u16>,
+    max_depth: Option<u8>,
+    threads: Option<usize>,
+    seed: Option<u64>,
+    output: Option<String>,
+    format: Option<ImageFormat>,
+    resume: bool,
+    checkpoint: Option<String>,
+    checkpoint_every: Option<u64>,
+    time: Option<Duration>,
+    noise: Option<f64>,
+}
+
 fn main() {
-    let mut world = World::new();
-
-    let material_ground = Material::Lambertian(Color::new([0.5, 0.5, 0.5]));
-    let earth = Sphere::new(Point::new([0.0, -1000.0, 0.0]), 1000.0, material_ground);
-    world.add(Arc::new(earth));
-
-    let mut rng = rand::thread_rng();
-    for i in -8..8 {
-        for j in -8..8 {
-            let choose_mat = rng.gen_range(0.0..1.0);
-            let radius = rng.gen_range(0.1..0.2);
-            let center = Point::new([
-                i as f64 + 0.9 * rng.gen_range(0.0..1.0), 
-                radius,
-                j as f64 + 0.9 * rng.gen_range(0.0..1.0)
-            ]);
-
-            if (center - Point::new([4.0, radius, 0.0])).length() > 0.9 {
-                let sphere_mat = if choose_mat < 0.3 {
-                    let albedo = Color::random(0.0, 0.6);
-                    Material::Lambertian(albedo)
-                } else if choose_mat < 0.9 {
-                    let albedo = Color::random(0.5, 1.0);
-                    let fuzz = rng.gen_range(0.0..0.4);
-                    Material::Metal(albedo, fuzz)
-                } else {
-                    Material::Dielectric(rng.gen_range(0.5..2.0))
-                };
-
-                world.add(Arc::new(Sphere::new(center, radius, sphere_mat)));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, options) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("run `ray-tracing help` for usage");
+            process::exit(2);
+        },
+    };
+    let result = match command {
+        Command::Render => render(&options),
+        Command::Info => info(&options),
+        Command::Bench => bench(&options),
+        Command::Help => {
+            print!("{}", USAGE);
+            println!("\nbuilt-in scenes:");
+            for scene in BUILT_IN_SCENES.iter() {
+                println!("  {:<18} {}", scene.name, scene.description);
+            }
+            Ok(())
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(Command, Options), String> {
+    let mut args = args.iter().peekable();
+    let command = match args.peek().map(|a| a.as_str()) {
+        Some("render") => Command::Render,
+        Some("info") => Command::Info,
+        Some("bench") => Command::Bench,
+        Some("help") | Some("-h") | Some("--help") => return Ok((Command::Help, Options::default())),
+        Some(word) if !word.starts_with('-') && !word.contains('.') => {
+            return Err(format!("unknown command `{}`; expected render, info, bench or help", word));
+        },
+        // options or a scene file alone mean render
+        _ => Command::Render,
+    };
+    if matches!(args.peek().map(|a| a.as_str()), Some("render" | "info" | "bench")) {
+        args.next();
+    }
+
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            if let Some(file) = &options.file {
+                return Err(format!("more than one scene file given: `{}` and `{}`", file, arg));
+            }
+            options.file = Some(arg.clone());
+            continue;
+        }
+
+        // both `--flag value` and `--flag=value` are accepted
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        if flag == "-h" || flag == "--help" {
+            return Ok((Command::Help, Options::default()));
+        }
+        if flag == "--resume" {
+            if inline.is_some() {
+                return Err("`--resume` takes no value".to_string());
+            }
+            options.resume = true;
+            continue;
+        }
+        let mut value = || match inline.clone().or_else(|| args.next().cloned()) {
+            Some(value) => Ok(value),
+            None => Err(format!("`{}` needs a value", flag)),
+        };
+        match flag {
+            "-W" | "--width" => options.width = Some(number(flag, &value()?, 1, u32::MAX as u64)? as u32),
+            "-H" | "--height" => options.height = Some(number(flag, &value()?, 1, u32::MAX as u64)? as u32),
+            "-s" | "--spp" => options.samples = Some(number(flag, &value()?, 1, u16::MAX as u64)? as u16),
+            "-d" | "--max-depth" => options.max_depth = Some(number(flag, &value()?, 1, u8::MAX as u64)? as u8),
+            "-t" | "--threads" => options.threads = Some(number(flag, &value()?, 1, 4096)? as usize),
+            "--seed" => options.seed = Some(number(flag, &value()?, 0, u64::MAX)?),
+            "-o" | "--output" => options.output = Some(value()?),
+            "-f" | "--format" => {
+                let name = value()?;
+                match ImageFormat::from_name(&name) {
+                    Some(format) => options.format = Some(format),
+                    None => return Err(format!("unknown image format `{}`; expected ppm, png or hdr", name)),
+                }
+            },
+            "--scene" => {
+                let name = value()?;
+                if built_in_scene(&name).is_none() {
+                    let names: Vec<&str> = BUILT_IN_SCENES.iter().map(|scene| scene.name).collect();
+                    return Err(format!("unknown scene `{}`; the built-in scenes are: {}", name, names.join(", ")));
+                }
+                options.scene = Some(name);
+            },
+            "--time" => options.time = Some(duration(flag, &value()?)?),
+            "--noise" => {
+                let text = value()?;
+                match text.parse::<f64>() {
+                    Ok(noise) if noise > 0.0 && noise.is_finite() => options.noise = Some(noise),
+                    _ => return Err(format!("invalid value `{}` for `{}`: expected a positive number", text, flag)),
+                }
+            },
+            "--checkpoint" => options.checkpoint = Some(value()?),
+            "--checkpoint-every" => options.checkpoint_every = Some(number(flag, &value()?, 0, u32::MAX as u64)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+
+    if options.file.is_some() && options.scene.is_some() {
+        return Err("give either a scene file or --scene, not both".to_string());
+    }
+    if let (Some(output), Some(format)) = (&options.output, options.format) {
+        if ImageFormat::from_path(output).is_some_and(|f| f != format) {
+            return Err(format!("output `{}` does not match format {}", output, format.extension()));
+        }
+    }
+    if command != Command::Render
+        && (options.output.is_some() || options.format.is_some() || options.time.is_some()
+            || options.noise.is_some() || options.resume || options.checkpoint.is_some()
+            || options.checkpoint_every.is_some())
+    {
+        return Err("--output, --format, --time, --noise, --resume and the checkpoint options only apply to render".to_string());
+    }
+    Ok((command, options))
+}
+
+fn number(flag: &str, text: &str, min: u64, max: u64) -> Result<u64, String> {
+    match text.parse::<u64>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n),
+        _ => Err(format!("invalid value `{}` for `{}`: expected a whole number from {} to {}", text, flag, min, max)),
+    }
+}
+
+// seconds, or a number with an s, m or h suffix
+fn duration(flag: &str, text: &str) -> Result<Duration, String> {
+    let (number, unit) = match text.char_indices().last() {
+        Some((i, 's')) => (&text[..i], 1.0),
+        Some((i, 'm')) => (&text[..i], 60.0),
+        Some((i, 'h')) => (&text[..i], 3600.0),
+        _ => (text, 1.0),
+    };
+    match number.parse::<f64>().ok().filter(|n| *n > 0.0).map(|n| Duration::try_from_secs_f64(n * unit)) {
+        Some(Ok(duration)) => Ok(duration),
+        _ => Err(format!("invalid value `{}` for `{}`: expected a duration such as 90, 90s, 10m or 2h", text, flag)),
+    }
+}
+
+// the world and camera with the command line settings applied, plus the scene description
+// of built-in scenes so the exact render can be reproduced
+fn load(options: &Options) -> Result<(World, Camera, Option<Scene>), String> {
+    let (world, camera, scene) = match &options.file {
+        Some(path) => {
+            let loaded = if path.ends_with(".pbrt") {
+                load_pbrt(path)
+            } else if path.ends_with(".gltf") || path.ends_with(".glb") {
+                load_gltf(path)
+            } else {
+                let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+                Scene::load(path).and_then(|scene| scene.build_in(directory))
+            };
+            let (world, camera) = loaded.map_err(|e| format!("{}: {}", path, e))?;
+            (world, camera, None)
+        },
+        None => {
+            let name = options.scene.as_deref().unwrap_or(DEFAULT_SCENE);
+            let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+            let scene = built_in_scene(name).unwrap().build(seed);
+            let (world, camera) = scene.build().map_err(|e| format!("built-in scene {}: {}", name, e))?;
+            (world, camera, Some(scene))
+        },
+    };
+    Ok((world, configure(camera, options), scene))
+}
+
+fn configure(mut camera: Camera, options: &Options) -> Camera {
+    let (width, height) = (camera.width() as f64, camera.height() as f64);
+    camera = match (options.width, options.height) {
+        (Some(w), Some(h)) => camera.resolution(w, h),
+        (Some(w), None) => camera.resolution(w, (w as f64 * height / width).round() as u32),
+        (None, Some(h)) => camera.resolution((h as f64 * width / height).round() as u32, h),
+        (None, None) => camera,
+    };
+    if let Some(samples) = options.samples {
+        camera = camera.samples(samples);
+    }
+    if let Some(depth) = options.max_depth {
+        camera = camera.max_depth(depth);
+    }
+    if let Some(threads) = options.threads {
+        camera = camera.threads(threads);
+    }
+    if let Some(limit) = options.time {
+        camera = camera.time_limit(limit);
+    }
+    if let Some(noise) = options.noise {
+        camera = camera.noise_target(noise);
+    }
+    // on a budget the samples per pixel are only a cap, and none unless given
+    if (options.time.is_some() || options.noise.is_some()) && options.samples.is_none() {
+        camera = camera.samples(u16::MAX);
+    }
+    camera
+}
+
+fn render(options: &Options) -> Result<(), String> {
+    let format = options.format
+        .or_else(|| options.output.as_ref().and_then(ImageFormat::from_path))
+        .unwrap_or(ImageFormat::Png);
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => format!("out.{}", format.extension()),
+    };
+    let checkpoint_path = match &options.checkpoint {
+        Some(path) => path.clone(),
+        None => Path::new(&output).with_extension("checkpoint").to_string_lossy().into_owned(),
+    };
+
+    // a checkpoint is only ever replaced by the render it belongs to
+    let resumed = if options.resume {
+        let checkpoint = Checkpoint::load(&checkpoint_path)
+            .map_err(|e| format!("could not resume from {}: {}", checkpoint_path, e))?;
+        Some(checkpoint)
+    } else if Path::new(&checkpoint_path).exists() {
+        return Err(format!(
+            "{} holds an unfinished render; continue it with --resume or delete it", checkpoint_path,
+        ));
+    } else {
+        None
+    };
+
+    // resuming without --seed rebuilds the scene the checkpoint was made with
+    let mut options = options.clone();
+    let seed = options.seed
+        .or(resumed.as_ref().map(|checkpoint| checkpoint.seed))
+        .unwrap_or_else(|| rand::thread_rng().gen());
+    options.seed = Some(seed);
+
+    let (world, camera, scene) = load(&options)?;
+    let settings = settings_fingerprint(&options, &world, &camera, scene.as_ref())?;
+    let mut film = match resumed {
+        Some(checkpoint) if checkpoint.fingerprint != settings => {
+            return Err(format!(
+                "{} was saved for a different scene or different settings; delete it to start over",
+                checkpoint_path,
+            ));
+        },
+        Some(checkpoint) => {
+            let taken = checkpoint.film.min_samples();
+            if camera.sample_num() == u16::MAX && options.samples.is_none() {
+                println!("Resuming {} at {} samples per pixel", checkpoint_path, taken);
+            } else {
+                println!("Resuming {} at {} of {} samples per pixel", checkpoint_path, taken, camera.sample_num());
+            }
+            checkpoint.film
+        },
+        None => Film::new(camera.width() as usize, camera.height() as usize),
+    };
+
+    // built-in scenes are saved next to the image, with image paths made absolute when that
+    // is not the working directory they were found in
+    if let Some(mut scene) = scene {
+        let path = Path::new(&output).with_extension("scene");
+        if path.parent().is_some_and(|dir| !dir.as_os_str().is_empty()) {
+            for (_, texture) in scene.textures.iter_mut() {
+                if let TextureDesc::Image { path } = texture {
+                    if let Ok(absolute) = std::path::absolute(&*path) {
+                        *path = absolute.to_string_lossy().into_owned();
+                    }
+                }
+            }
+        }
+        if let Err(e) = scene.save(&path) {
+            eprintln!("could not save {}: {}", path.display(), e);
+        }
+    }
+
+    let mut monitor = Monitor {
+        printed: None,
+        finished: false,
+        target: camera.sample_num() as u32,
+        fingerprint: settings,
+        seed,
+        checkpoint_path: &checkpoint_path,
+        interval: options.checkpoint_every.unwrap_or(CHECKPOINT_SECONDS),
+        last_saved: Instant::now(),
+    };
+    let start = Instant::now();
+    camera.render_progressive(Arc::new(world), &mut film, seed, &mut monitor, &CancelToken::new());
+    println!();
+    println!("Rendering time: {:.1}s", start.elapsed().as_secs_f64());
+    let noise = film.noise();
+    if noise.is_finite() {
+        println!("Samples per pixel: {}, estimated noise: {:.2}%", film.min_samples(), noise * 100.0);
+    } else {
+        println!("Samples per pixel: {}", film.min_samples());
+    }
+    film.image().save_as(&output, format).map_err(|e| format!("could not write {}: {}", output, e))?;
+    println!("Wrote {}", output);
+
+    match fs::remove_file(&checkpoint_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            eprintln!("could not remove {}: {}", checkpoint_path, e);
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+// Shows progress on the terminal and saves a checkpoint between passes every `interval`
+// seconds, unless that is zero.
+struct Monitor<'a> {
+    printed: Option<Instant>,
+    finished: bool,
+    target: u32,
+    fingerprint: u64,
+    seed: u64,
+    checkpoint_path: &'a str,
+    interval: u64,
+    last_saved: Instant,
+}
+
+impl RenderObserver for Monitor<'_> {
+    fn progress(&mut self, progress: &Progress) {
+        // the moment it is done is shown once, anything else at most every PROGRESS_INTERVAL
+        let finished = progress.fraction >= 1.0;
+        let waiting = self.printed.is_some_and(|t| t.elapsed() < PROGRESS_INTERVAL);
+        if (waiting && !finished) || (finished && self.finished) {
+            return;
+        }
+        self.finished = finished;
+        let eta = match progress.eta {
+            Some(eta) if !finished => format!(", {} left", clock(eta)),
+            _ => String::new(),
+        };
+        print!("\rProgress: {:.2}%{}    ", progress.fraction * 100.0, eta);
+        let _ = io::stdout().flush();
+        self.printed = Some(Instant::now());
+    }
+
+    fn pass_finished(&mut self, film: &Film) {
+        let unfinished = film.min_samples() < self.target;
+        if self.interval > 0 && unfinished && self.last_saved.elapsed().as_secs() >= self.interval {
+            let checkpoint = Checkpoint { fingerprint: self.fingerprint, seed: self.seed, film: film.clone() };
+            if let Err(e) = checkpoint.save(self.checkpoint_path) {
+                eprintln!("\ncould not save {}: {}", self.checkpoint_path, e);
             }
+            self.last_saved = Instant::now();
         }
     }
+}
 
-    let material_big_ball_1 = Material::Lambertian(Color::new([0.8, 0.65, 0.3]));
-    let big_ball_1 = Sphere::new(Point::new([-150.0, 69.0, -30.0]), 80.0, material_big_ball_1);
-    world.add(Arc::new(big_ball_1));
-    
-    let material_big_ball_2 = Material::Dielectric(1.5);
-    let big_ball_2 = Sphere::new(Point::new([-4.0, 1.0, 0.0]), 1.0, material_big_ball_2);
-    world.add(Arc::new(big_ball_2));
+// hours, minutes and seconds
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
 
-    let material_big_ball_3 = Material::Metal(Color::new([0.5, 0.6, 0.7]), 0.0);
-    let big_ball_3 = Sphere::new(Point::new([4.0, 1.0, 0.0]), 1.0, material_big_ball_3);
-    world.add(Arc::new(big_ball_3));
+// everything the image depends on apart from the sample count: the scene source, the files
+// it refers to and the camera settings
+fn settings_fingerprint(options: &Options, world: &World, camera: &Camera, scene: Option<&Scene>) -> Result<u64, String> {
+    let mut bytes = match (&options.file, scene) {
+        (Some(path), _) => fs::read(path).map_err(|e| format!("{}: {}", path, e))?,
+        (None, Some(scene)) => scene.to_string().into_bytes(),
+        (None, None) => Vec::new(),
+    };
+    for source in world.sources() {
+        bytes.extend_from_slice(source.to_string_lossy().as_bytes());
+        bytes.extend(fs::read(source).map_err(|e| format!("{}: {}", source.display(), e))?);
+    }
+    let mut settings = CameraDesc::from(camera);
+    settings.samples = 0;
+    bytes.extend_from_slice(format!("{:?}", settings).as_bytes());
+    Ok(fingerprint(&bytes))
+}
 
-    let c = Camera::new(Point::new([13.0, 2.0, 3.0]), ORIGIN);
-    c.render(Arc::new(world));
+fn info(options: &Options) -> Result<(), String> {
+    let (world, camera, scene) = load(options)?;
+    let source = match (&options.file, &options.scene) {
+        (Some(file), _) => file.clone(),
+        (None, name) => {
+            let scene = built_in_scene(name.as_deref().unwrap_or(DEFAULT_SCENE)).unwrap();
+            format!("built-in scene {}, {}", scene.name, scene.description)
+        },
+    };
+    println!("scene: {}", source);
+    if let Some(scene) = &scene {
+        println!(
+            "objects: {}, materials: {}, textures: {}",
+            scene.objects.len(), scene.materials.len(), scene.textures.len(),
+        );
+    }
+    println!("lights: {}", world.light_count());
+    match world.bounding_box() {
+        Some(bounds) => println!("bounds: {} to {}", bounds.min(), bounds.max()),
+        None => println!("bounds: unbounded"),
+    }
+    println!("camera: from {} towards {}, up {}", camera.look_from(), camera.look_at(), camera.vup());
+    println!("field of view: {} degrees", camera.fov());
+    if camera.defocus_angle() > 0.0 {
+        println!("defocus: {} degrees at distance {}", camera.defocus_angle(), camera.focus_dist());
+    }
+    println!("resolution: {}x{}", camera.width(), camera.height());
+    println!("samples per pixel: {}", camera.sample_num());
+    println!("max depth: {}", camera.reflect_depth());
+    println!("threads: {}", camera.thread_num());
+    Ok(())
 }
 
+fn bench(options: &Options) -> Result<(), String> {
+    let mut options = options.clone();
+    if options.width.is_none() && options.height.is_none() {
+        options.width = Some(BENCH_WIDTH);
+    }
+    options.samples.get_or_insert(BENCH_SAMPLES);
+    let (world, camera, _) = load(&options)?;
+
+    let start = Instant::now();
+    camera.render(Arc::new(world));
+    let seconds = start.elapsed().as_secs_f64();
+    let samples = camera.width() as f64 * camera.height() as f64 * camera.sample_num() as f64;
+    println!(
+        "{}x{} at {} spp on {} threads: {:.2}s, {:.3} million samples per second",
+        camera.width(), camera.height(), camera.sample_num(), camera.thread_num(),
+        seconds, samples / seconds / 1e6,
+    );
+    Ok(())
+}
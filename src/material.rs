@@ -1,82 +1,257 @@
-use crate::ray::{Ray, HitRecord};
-use crate::vec3::{Vec3};
-use crate::color::{Color};
+use crate::ray::{HitRecord};
+use crate::vec3::{Vec3, Onb};
+use crate::color::{Color, WHITE, BLACK};
+use crate::texture::{Texture};
+use crate::microfacet::{Conductor, RoughDielectric, LocalSample};
+use crate::principled::{Principled};
+use std::f64::consts::PI;
+use std::sync::{Arc};
 use rand::Rng;
 
 
-#[derive(PartialEq, Clone, Copy)]
+// `weight` is bsdf * |cos| / pdf; specular samples come from a delta lobe and carry no usable pdf
+pub struct BsdfSample {
+    pub direction: Vec3,
+    pub weight: Color,
+    pub pdf: f64,
+    pub specular: bool,
+}
+
+// Anything a surface can be made of. `wo` and `wi` are unit directions pointing away from the
+// hit point, `wo` back towards where the ray came from.
+pub trait Bsdf: Sync + Send {
+    fn sample(&self, wo: &Vec3, rec: &HitRecord) -> Option<BsdfSample>;
+
+    // bsdf * |cos theta_i|; purely specular materials keep the default
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, _rec: &HitRecord) -> Color {
+        BLACK
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _rec: &HitRecord) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, _wo: &Vec3, _rec: &HitRecord) -> Color {
+        BLACK
+    }
+}
+
+// Metal takes albedo and fuzz textures; the fuzz is the mean of the texture's channels.
+// DiffuseLight emits from the front face only and does not scatter. Isotropic and
+// HenyeyGreenstein are phase functions for scattering inside participating media; the
+// latter takes an asymmetry g in (-1, 1), positive for forward scattering.
+#[derive(Clone)]
 pub enum Material {
-    Lambertian(Color),
-    Metal(Color, f64),
+    Lambertian(Arc<dyn Texture>),
+    Metal(Arc<dyn Texture>, Arc<dyn Texture>),
     Dielectric(f64),
+    Conductor(Conductor),
+    RoughDielectric(RoughDielectric),
+    Principled(Principled),
+    DiffuseLight(Arc<dyn Texture>),
+    Isotropic(Arc<dyn Texture>),
+    HenyeyGreenstein(Arc<dyn Texture>, f64),
 }
 
-pub fn scatter(mat: &Material, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-    match mat {
-        Material::Lambertian(albedo) => {
-            if let Some(ray) = lambertian_scatter(rec) {
-                return Some((ray, *albedo));
-            }
-        },
-        Material::Metal(albedo, fuzz) => {
-            if let Some(ray) = metal_scatter(incident, rec, fuzz) {
-                return Some((ray, *albedo));
-            }
-        },
-        Material::Dielectric(refractive_index) => {
-            if let Some(ray) = dielectrics_scatter(incident, rec, refractive_index) {
-                return Some((ray, Color::new([1.0, 1.0, 1.0])));
-            }
-        },
+impl From<Material> for Arc<dyn Bsdf> {
+    fn from(mat: Material) -> Arc<dyn Bsdf> {
+        Arc::new(mat)
     }
-    None
 }
 
+impl Bsdf for Material {
+    fn sample(&self, wo: &Vec3, rec: &HitRecord) -> Option<BsdfSample> {
+        match self {
+            Material::Lambertian(albedo) => {
+                let direction = lambertian_scatter(rec);
+                let cos = direction.unit().dot(rec.normal());
+                Some(BsdfSample {
+                    direction,
+                    weight: albedo.value(rec.u(), rec.v(), rec.pos()),
+                    pdf: cos.max(0.0) / PI,
+                    specular: false,
+                })
+            },
+            Material::Metal(albedo, fuzz) => {
+                let fuzz = fuzz.value(rec.u(), rec.v(), rec.pos());
+                let fuzz = (fuzz.x() + fuzz.y() + fuzz.z()) / 3.0;
+                metal_scatter(wo, rec, fuzz).map(|direction| BsdfSample {
+                    direction,
+                    weight: albedo.value(rec.u(), rec.v(), rec.pos()),
+                    pdf: 0.0,
+                    specular: true,
+                })
+            },
+            Material::Dielectric(refractive_index) => {
+                Some(BsdfSample {
+                    direction: dielectrics_scatter(wo, rec, *refractive_index),
+                    weight: WHITE,
+                    pdf: 0.0,
+                    specular: true,
+                })
+            },
+            Material::Conductor(conductor) => {
+                let frame = shading_frame(rec);
+                conductor.sample(&frame.to_local(wo)).map(|s| to_world(&frame, s))
+            },
+            Material::RoughDielectric(dielectric) => {
+                let frame = shading_frame(rec);
+                dielectric.sample(&frame.to_local(wo)).map(|s| to_world(&frame, s))
+            },
+            Material::Principled(principled) => {
+                let frame = shading_frame(rec);
+                let base = principled.base_color().value(rec.u(), rec.v(), rec.pos());
+                principled.sample(&frame.to_local(wo), base).map(|s| to_world(&frame, s))
+            },
+            Material::DiffuseLight(_) => None,
+            Material::Isotropic(albedo) => Some(phase_sample(wo, 0.0, albedo.value(rec.u(), rec.v(), rec.pos()))),
+            Material::HenyeyGreenstein(albedo, g) => Some(phase_sample(wo, *g, albedo.value(rec.u(), rec.v(), rec.pos()))),
+        }
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        match self {
+            Material::Lambertian(albedo) => {
+                let cos = wi.dot(rec.normal());
+                if cos <= 0.0 { return BLACK; }
+                albedo.value(rec.u(), rec.v(), rec.pos()) * (cos / PI)
+            },
+            Material::Conductor(conductor) => {
+                let frame = shading_frame(rec);
+                conductor.eval(&frame.to_local(wo), &frame.to_local(wi))
+            },
+            Material::RoughDielectric(dielectric) => {
+                let frame = shading_frame(rec);
+                dielectric.eval(&frame.to_local(wo), &frame.to_local(wi))
+            },
+            Material::Principled(principled) => {
+                let frame = shading_frame(rec);
+                let base = principled.base_color().value(rec.u(), rec.v(), rec.pos());
+                principled.eval(&frame.to_local(wo), &frame.to_local(wi), base)
+            },
+            Material::Isotropic(albedo) => albedo.value(rec.u(), rec.v(), rec.pos()) * henyey_greenstein(wo, wi, 0.0),
+            Material::HenyeyGreenstein(albedo, g) => {
+                albedo.value(rec.u(), rec.v(), rec.pos()) * henyey_greenstein(wo, wi, *g)
+            },
+            Material::Metal(..) | Material::Dielectric(_) | Material::DiffuseLight(_) => BLACK,
+        }
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        match self {
+            Material::Lambertian(_) => wi.dot(rec.normal()).max(0.0) / PI,
+            Material::Conductor(conductor) => {
+                let frame = shading_frame(rec);
+                conductor.pdf(&frame.to_local(wo), &frame.to_local(wi))
+            },
+            Material::RoughDielectric(dielectric) => {
+                let frame = shading_frame(rec);
+                dielectric.pdf(&frame.to_local(wo), &frame.to_local(wi))
+            },
+            Material::Principled(principled) => {
+                let frame = shading_frame(rec);
+                principled.pdf(&frame.to_local(wo), &frame.to_local(wi))
+            },
+            Material::Isotropic(_) => henyey_greenstein(wo, wi, 0.0),
+            Material::HenyeyGreenstein(_, g) => henyey_greenstein(wo, wi, *g),
+            Material::Metal(..) | Material::Dielectric(_) | Material::DiffuseLight(_) => 0.0,
+        }
+    }
+
+    fn emitted(&self, _wo: &Vec3, rec: &HitRecord) -> Color {
+        match self {
+            Material::DiffuseLight(emit) if rec.front_face() => emit.value(rec.u(), rec.v(), rec.pos()),
+            _ => BLACK,
+        }
+    }
+}
 
-fn lambertian_scatter(rec: &HitRecord) -> Option<Ray> {
-    let mut scatter_direction = *rec.normal() + Vec3::random_unit_vec();
+// microfacet models expect +z to be the outward facing normal
+fn shading_frame(rec: &HitRecord) -> Onb {
+    if rec.front_face() {
+        Onb::new(rec.normal())
+    } else {
+        Onb::new(&rec.normal().reverse())
+    }
+}
+
+fn to_world(frame: &Onb, s: LocalSample) -> BsdfSample {
+    BsdfSample {
+        direction: frame.to_world(&s.wi),
+        weight: s.weight,
+        pdf: s.pdf,
+        specular: s.specular,
+    }
+}
+
+// Phase functions have no cosine term and are measured against the direction of travel -wo.
+fn henyey_greenstein(wo: &Vec3, wi: &Vec3, g: f64) -> f64 {
+    let g = g.clamp(-0.99, 0.99);
+    let cos = -wo.unit().dot(&wi.unit());
+    let denom = 1.0 + g * g - 2.0 * g * cos;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+}
+
+// samples the phase function exactly, so the weight is just the albedo
+fn phase_sample(wo: &Vec3, g: f64, albedo: Color) -> BsdfSample {
+    let g = g.clamp(-0.99, 0.99);
+    let mut rng = rand::thread_rng();
+    let u: f64 = rng.gen_range(0.0..1.0);
+    let cos = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        (1.0 + g * g - s * s) / (2.0 * g)
+    }.clamp(-1.0, 1.0);
+    let sin = (1.0 - cos * cos).sqrt();
+    let phi = 2.0 * PI * rng.gen_range(0.0..1.0);
+
+    let frame = Onb::new(&wo.unit().reverse());
+    let direction = frame.to_world(&Vec3::new([sin * phi.cos(), sin * phi.sin(), cos]));
+    BsdfSample {
+        pdf: henyey_greenstein(wo, &direction, g),
+        direction,
+        weight: albedo,
+        specular: false,
+    }
+}
+
+fn lambertian_scatter(rec: &HitRecord) -> Vec3 {
+    let scatter_direction = *rec.normal() + Vec3::random_unit_vec();
     if scatter_direction.near_zero() {
-        scatter_direction = *rec.normal();
+        return *rec.normal();
     }
-    
-    Some(
-        Ray::new(*rec.pos(), scatter_direction)
-    )
+    scatter_direction
 }
 
-fn metal_scatter(ray: &Ray, rec: &HitRecord, fuzz: &f64) -> Option<Ray> {
-    let mut scatter_direction = ray.direct().specular(rec.normal());
-    scatter_direction = scatter_direction.unit() + fuzz.min(1.0) * Vec3::random_unit_vec();
+fn metal_scatter(wo: &Vec3, rec: &HitRecord, fuzz: f64) -> Option<Vec3> {
+    let mut scatter_direction = wo.reverse().specular(rec.normal());
+    scatter_direction = scatter_direction.unit() + fuzz.clamp(0.0, 1.0) * Vec3::random_unit_vec();
     if scatter_direction.dot(rec.normal()) > 0.0 {
-        return Some(Ray::new(*rec.pos(), scatter_direction));
+        return Some(scatter_direction);
     }
     None
 }
 
-fn dielectrics_scatter(ray: &Ray, rec: &HitRecord, eta: &f64) -> Option<Ray> {
-    let ri: f64;
-    if rec.front_face() { ri = 1.0 / eta; } 
-    else { ri = *eta; }
+fn dielectrics_scatter(wo: &Vec3, rec: &HitRecord, eta: f64) -> Vec3 {
+    let ri = if rec.front_face() { 1.0 / eta } else { eta };
 
-    let ray_direct_unit = ray.direct().unit();
-    let cos_theta = rec.normal().dot(&ray_direct_unit.reverse()).min(1.0);
+    let ray_direct_unit = wo.reverse();
+    let cos_theta = rec.normal().dot(wo).min(1.0);
     let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-    
-    let direction;
+
     let cannot_refract = ri * sin_theta > 1.0;
     let mut rng = rand::thread_rng();
     let schlick = reflectance(cos_theta, ri) > rng.gen_range(0.0..1.0);
     if cannot_refract || schlick {
-        direction = ray_direct_unit.specular(rec.normal());
+        ray_direct_unit.specular(rec.normal())
     } else {
-        direction = ray_direct_unit.refract(rec.normal(), ri);
+        ray_direct_unit.refract(rec.normal(), ri)
     }
-
-    Some(Ray::new(*rec.pos(), direction))
 }
 
 fn reflectance(cos: f64, refractive_index: f64) -> f64 {
     let mut r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
     r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cos).powf(5.0)
-}
\ No newline at end of file
+}
@@ -0,0 +1,69 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::aabb::{Aabb};
+use crate::world::{INF};
+use std::sync::{Arc};
+use rand::Rng;
+
+// A homogeneous participating medium filling a convex boundary. Each ray crossing it draws an
+// exponential free-flight distance; if that lands before the exit, the ray scatters there with
+// the phase function material, otherwise it passes through untouched. Shadow rays are not
+// sampled this way; they take the exact transmittance over the stretch inside the boundary.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    density: f64,
+    phase: Arc<dyn Bsdf>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, phase: impl Into<Arc<dyn Bsdf>>) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            density: density.max(0.0),
+            phase: phase.into(),
+        }
+    }
+}
+
+impl ConstantMedium {
+    // the part of [t_min, t_max] inside the boundary
+    fn overlap(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let enter = self.boundary.intersect(ray, -INF, INF)?;
+        let exit = self.boundary.intersect(ray, enter.t() + 0.0001, INF)?;
+        let start = enter.t().max(t_min);
+        let end = exit.t().min(t_max);
+        if start >= end { None } else { Some((start, end)) }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.density == 0.0 {
+            return None;
+        }
+        let (start, end) = self.overlap(ray, t_min, t_max)?;
+
+        let length = ray.direct().length();
+        let inside = (end - start) * length;
+        let distance = -(1.0 - rand::thread_rng().gen_range(0.0..1.0_f64)).ln() / self.density;
+        if distance > inside {
+            return None;
+        }
+
+        // there is no surface; the normal just faces the ray so the hit counts as a front face
+        let t = start + distance / length;
+        let normal = ray.direct().reverse() / length;
+        Some(HitRecord::new(t, ray.range(t), normal, true, (0.0, 0.0), Arc::clone(&self.phase)))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.overlap(ray, t_min, t_max) {
+            Some((start, end)) => (-self.density * (end - start) * ray.direct().length()).exp(),
+            None => 1.0,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
@@ -0,0 +1,280 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material, Bsdf};
+use crate::texture::{Texture};
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color};
+use crate::aabb::{Aabb};
+use crate::bvh::{Bvh};
+use crate::transform::{Transform};
+use crate::world::{INF};
+use std::sync::{Arc};
+use rand::Rng;
+
+// Indexed triangles as read from a file. `normals`, `uvs` and `colors` are either empty or
+// hold one entry per position. The front of a triangle is the side its vertices wind counter
+// clockwise around, unless vertex normals say otherwise.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<Point>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Point>, indices: Vec<[usize; 3]>) -> Mesh {
+        Mesh { positions, normals: Vec::new(), uvs: Vec::new(), colors: Vec::new(), indices }
+    }
+
+    // moves the mesh into another space; the winding is reversed under mirroring so the
+    // front faces stay on the same side of the surface
+    pub fn transform(&mut self, t: &Transform) {
+        for p in self.positions.iter_mut() {
+            *p = t.point(p);
+        }
+        for n in self.normals.iter_mut() {
+            *n = t.normal(n).unit();
+        }
+        if t.swaps_handedness() {
+            self.flip_winding();
+        }
+    }
+
+    pub fn flip_winding(&mut self) {
+        for triangle in self.indices.iter_mut() {
+            triangle.swap(1, 2);
+        }
+    }
+
+    // describes the first attribute list or index that does not fit the positions, if any
+    pub fn validate(&self) -> Result<(), String> {
+        let count = self.positions.len();
+        if !self.normals.is_empty() && self.normals.len() != count {
+            return Err(format!("{} normals for {} vertices", self.normals.len(), count));
+        }
+        if !self.uvs.is_empty() && self.uvs.len() != count {
+            return Err(format!("{} uvs for {} vertices", self.uvs.len(), count));
+        }
+        if !self.colors.is_empty() && self.colors.len() != count {
+            return Err(format!("{} colors for {} vertices", self.colors.len(), count));
+        }
+        for triangle in &self.indices {
+            if let Some(i) = triangle.iter().find(|i| **i >= count) {
+                return Err(format!("vertex index {} out of range for {} vertices", i, count));
+            }
+        }
+        Ok(())
+    }
+
+    fn area(&self, triangle: usize) -> f64 {
+        let [a, b, c] = self.indices[triangle].map(|i| self.positions[i]);
+        (b - a).cross(&(c - a)).length() / 2.0
+    }
+}
+
+struct Triangle {
+    mesh: Arc<Mesh>,
+    index: usize,
+    mat: Arc<dyn Bsdf>,
+}
+
+impl Hittable for Triangle {
+    // Moller-Trumbore
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let p0 = self.mesh.positions[i0];
+        let e1 = self.mesh.positions[i1] - p0;
+        let e2 = self.mesh.positions[i2] - p0;
+
+        let pvec = ray.direct().cross(&e2);
+        let det = e1.dot(&pvec);
+        if det == 0.0 {
+            return None;
+        }
+        let inv = 1.0 / det;
+        let tvec = *ray.org() - p0;
+        let b1 = tvec.dot(&pvec) * inv;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = tvec.cross(&e1);
+        let b2 = ray.direct().dot(&qvec) * inv;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = e2.dot(&qvec) * inv;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let uv = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let [uv0, uv1, uv2] = [i0, i1, i2].map(|i| self.mesh.uvs[i]);
+            (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1)
+        };
+
+        // interpolated normals shade the surface and decide which side is the front
+        let mut geometric = e1.cross(&e2).unit();
+        let mut shading = geometric;
+        if !self.mesh.normals.is_empty() {
+            let [n0, n1, n2] = [i0, i1, i2].map(|i| self.mesh.normals[i]);
+            let n = b0 * n0 + b1 * n1 + b2 * n2;
+            if n.square() > 0.0 {
+                shading = n.unit();
+                if shading.dot(&geometric) < 0.0 {
+                    geometric = geometric.reverse();
+                }
+            }
+        }
+        let front_face = ray.direct().dot(&geometric) < 0.0;
+        let (normal, geometric) = if front_face {
+            (shading, geometric)
+        } else {
+            (shading.reverse(), geometric.reverse())
+        };
+        let rec = HitRecord::new(t, ray.range(t), normal, front_face, uv, Arc::clone(&self.mat));
+        Some(rec.with_geometric_normal(geometric))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = self.mesh.indices[self.index].map(|i| self.mesh.positions[i]);
+        Some(Aabb::from_points(&corners))
+    }
+}
+
+// blends the vertex colors of one triangle, with u and v as the barycentric weights of its
+// second and third corner
+struct FaceColors([Color; 3]);
+
+impl Texture for FaceColors {
+    fn value(&self, u: f64, v: f64, _p: &Point) -> Color {
+        let [c0, c1, c2] = self.0;
+        (1.0 - u - v) * c0 + u * c1 + v * c2
+    }
+}
+
+// A triangle mesh with one material, kept in its own BVH. As a light it is sampled
+// uniformly by area.
+pub struct TriangleMesh {
+    mesh: Arc<Mesh>,
+    bvh: Bvh,
+    // the triangles with a non-zero area and the running sum of their areas
+    faces: Vec<usize>,
+    cumulative_area: Vec<f64>,
+}
+
+impl TriangleMesh {
+    // panics on indices past the vertex data, see `Mesh::validate`
+    pub fn new(mesh: Mesh, m: impl Into<Arc<dyn Bsdf>>) -> TriangleMesh {
+        let mat = m.into();
+        TriangleMesh::build(mesh, |_, _| Arc::clone(&mat))
+    }
+
+    // a diffuse mesh colored by its vertex colors; texture coordinates are dropped since
+    // the blend across each face is looked up by barycentric coordinates instead
+    pub fn vertex_colored(mut mesh: Mesh) -> TriangleMesh {
+        assert!(!mesh.colors.is_empty(), "Mesh has no vertex colors");
+        mesh.uvs.clear();
+        TriangleMesh::build(mesh, |mesh, index| {
+            let face = mesh.indices[index].map(|i| mesh.colors[i]);
+            Arc::new(Material::Lambertian(Arc::new(FaceColors(face))))
+        })
+    }
+
+    fn build(mesh: Mesh, material: impl Fn(&Mesh, usize) -> Arc<dyn Bsdf>) -> TriangleMesh {
+        if let Err(e) = mesh.validate() {
+            panic!("Invalid mesh: {}", e);
+        }
+        let mesh = Arc::new(mesh);
+        let mut triangles: Vec<Arc<dyn Hittable>> = Vec::with_capacity(mesh.indices.len());
+        let mut faces = Vec::with_capacity(mesh.indices.len());
+        let mut cumulative_area = Vec::with_capacity(mesh.indices.len());
+        let mut total = 0.0;
+        for index in 0..mesh.indices.len() {
+            let area = mesh.area(index);
+            if area == 0.0 {
+                continue;
+            }
+            total += area;
+            faces.push(index);
+            cumulative_area.push(total);
+            triangles.push(Arc::new(Triangle { mesh: Arc::clone(&mesh), index, mat: material(&mesh, index) }));
+        }
+        TriangleMesh { mesh, bvh: Bvh::new(triangles), faces, cumulative_area }
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    pub fn len(&self) -> usize {
+        self.bvh.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bvh.is_empty()
+    }
+
+    fn total_area(&self) -> f64 {
+        self.cumulative_area.last().copied().unwrap_or(0.0)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bvh.intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, time: f64) -> f64 {
+        match self.intersect(&Ray::with_time(*origin, *direction, time), 0.001, INF) {
+            Some(rec) => {
+                let distance_squared = rec.t() * rec.t() * direction.square();
+                // the light's area is measured on the flat triangles, not their shading normals
+                let cosine = (direction.dot(rec.geometric_normal()) / direction.length()).abs();
+                if cosine < 1e-8 { return 0.0; }
+                distance_squared / (cosine * self.total_area())
+            },
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point, _time: f64) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let target = rng.gen_range(0.0..1.0) * self.total_area();
+        let picked = self.cumulative_area.partition_point(|a| *a < target);
+        let Some(&index) = self.faces.get(picked.min(self.faces.len().saturating_sub(1))) else {
+            return Vec3::new([0.0, 0.0, 0.0]);
+        };
+
+        // uniform over the triangle by folding the unit square
+        let (mut s, mut r) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+        if s + r > 1.0 {
+            (s, r) = (1.0 - s, 1.0 - r);
+        }
+        let [a, b, c] = self.mesh.indices[index].map(|i| self.mesh.positions[i]);
+        a + s * (b - a) + r * (c - a) - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_pdf_ignores_shading_normals() {
+        let positions = vec![Point::new([0.0, 0.0, 0.0]), Point::new([1.0, 0.0, 0.0]), Point::new([0.0, 1.0, 0.0])];
+        let mut mesh = Mesh::new(positions, vec![[0, 1, 2]]);
+        mesh.normals = vec![Vec3::new([1.0, 0.0, 1.0]).unit(); 3];
+        let light = TriangleMesh::new(mesh, Material::Dielectric(1.5));
+        // straight down onto the triangle of area 1/2 from a distance of 1
+        let pdf = light.pdf_value(&Point::new([0.25, 0.25, 1.0]), &Vec3::new([0.0, 0.0, -1.0]), 0.0);
+        assert!((pdf - 2.0).abs() < 1e-9, "pdf {}", pdf);
+    }
+}
@@ -0,0 +1,528 @@
+// Trowbridge-Reitz (GGX) microfacet models. Everything here works in a local shading
+// frame where the surface normal is +z; `wo` points away from the surface towards the viewer.
+use crate::vec3::{Vec3};
+use crate::color::{Color, WHITE, BLACK};
+use std::f64::consts::PI;
+use std::ops::{Add, Sub, Mul, Div};
+use rand::Rng;
+
+// below this alpha a surface is treated as a perfect mirror
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+pub struct LocalSample {
+    pub wi: Vec3,
+    pub weight: Color,
+    pub pdf: f64,
+    pub specular: bool,
+}
+
+pub fn cos_theta(w: &Vec3) -> f64 { w.z() }
+fn cos2_theta(w: &Vec3) -> f64 { w.z() * w.z() }
+fn sin2_theta(w: &Vec3) -> f64 { (1.0 - cos2_theta(w)).max(0.0) }
+fn tan2_theta(w: &Vec3) -> f64 { sin2_theta(w) / cos2_theta(w) }
+
+fn cos2_phi(w: &Vec3) -> f64 {
+    let sin2 = sin2_theta(w);
+    if sin2 == 0.0 { 1.0 } else { (w.x() * w.x() / sin2).clamp(0.0, 1.0) }
+}
+
+fn sin2_phi(w: &Vec3) -> f64 {
+    let sin2 = sin2_theta(w);
+    if sin2 == 0.0 { 0.0 } else { (w.y() * w.y() / sin2).clamp(0.0, 1.0) }
+}
+
+pub fn same_hemisphere(a: &Vec3, b: &Vec3) -> bool {
+    a.z() * b.z() > 0.0
+}
+
+pub fn reflect(wo: &Vec3, n: &Vec3) -> Vec3 {
+    -1.0 * *wo + 2.0 * wo.dot(n) * *n
+}
+
+// refracts `wi` through a surface with normal `n` and relative index `eta` (inside over outside);
+// returns the transmitted direction and the index ratio actually used, or None on total internal reflection
+pub fn refract(wi: &Vec3, n: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let mut cos_i = n.dot(wi);
+    let mut eta = eta;
+    let mut n = *n;
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+        n = n.reverse();
+    }
+
+    let sin2_i = (1.0 - cos_i * cos_i).max(0.0);
+    let sin2_t = sin2_i / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((wi.reverse() / eta + (cos_i / eta - cos_t) * n, eta))
+}
+
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let mut cos_i = cos_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+    }
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+#[derive(Copy, Clone)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn norm(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn sqrt(&self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, rhs: Complex) -> Complex {
+        let scale = 1.0 / rhs.norm();
+        Complex::new(
+            scale * (self.re * rhs.re + self.im * rhs.im),
+            scale * (self.im * rhs.re - self.re * rhs.im),
+        )
+    }
+}
+
+fn fresnel_complex_channel(cos_i: f64, eta: Complex) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let cos = Complex::new(cos_i, 0.0);
+    let sin2_i = Complex::new(1.0 - cos_i * cos_i, 0.0);
+    let sin2_t = sin2_i / (eta * eta);
+    let cos_t = (Complex::new(1.0, 0.0) - sin2_t).sqrt();
+
+    let r_parl = (eta * cos - cos_t) / (eta * cos + cos_t);
+    let r_perp = (cos - eta * cos_t) / (cos + eta * cos_t);
+    (r_parl.norm() + r_perp.norm()) / 2.0
+}
+
+// reflectance of a conductor with complex index of refraction eta + i k, per color channel
+pub fn fresnel_complex(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    Color::new([
+        fresnel_complex_channel(cos_i, Complex::new(eta.x(), k.x())),
+        fresnel_complex_channel(cos_i, Complex::new(eta.y(), k.y())),
+        fresnel_complex_channel(cos_i, Complex::new(eta.z(), k.z())),
+    ])
+}
+
+#[derive(Copy, Clone)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    // perceptual roughness in [0, 1] maps to alpha = roughness^2
+    pub fn from_roughness(roughness: f64) -> TrowbridgeReitz {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        TrowbridgeReitz::new(alpha, alpha)
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let tan2 = tan2_theta(wm);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let cos4 = cos2_theta(wm) * cos2_theta(wm);
+        if cos4 < 1e-16 {
+            return 0.0;
+        }
+        let e = tan2 * (cos2_phi(wm) / (self.alpha_x * self.alpha_x)
+            + sin2_phi(wm) / (self.alpha_y * self.alpha_y));
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos4 * (1.0 + e) * (1.0 + e))
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let tan2 = tan2_theta(w);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let alpha2 = cos2_phi(w) * self.alpha_x * self.alpha_x
+            + sin2_phi(w) * self.alpha_y * self.alpha_y;
+        ((1.0 + alpha2 * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of visible normals as seen from `wo`
+    pub fn pdf(&self, wo: &Vec3, wm: &Vec3) -> f64 {
+        let cos_o = cos_theta(wo).abs();
+        if cos_o == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) / cos_o * self.d(wm) * wo.dot(wm).abs()
+    }
+
+    // samples a visible normal (Heitz 2018), always in the upper hemisphere
+    pub fn sample_wm(&self, wo: &Vec3, u: (f64, f64)) -> Vec3 {
+        let mut wh = Vec3::new([self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()]).unit();
+        if wh.z() < 0.0 {
+            wh = wh.reverse();
+        }
+
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::new([0.0, 0.0, 1.0]).cross(&wh).unit()
+        } else {
+            Vec3::new([1.0, 0.0, 0.0])
+        };
+        let t2 = wh.cross(&t1);
+
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let px = r * phi.cos();
+        let mut py = r * phi.sin();
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z()) / 2.0;
+        py = (1.0 - s) * h + s * py;
+
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+        Vec3::new([self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)]).unit()
+    }
+}
+
+fn random_pair() -> (f64, f64) {
+    let mut rng = rand::thread_rng();
+    (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0))
+}
+
+// measured complex indices of refraction (eta, k), fitted to linear RGB
+const GOLD: (Color, Color) = (Color::new([0.143, 0.374, 1.442]), Color::new([3.983, 2.385, 1.603]));
+const COPPER: (Color, Color) = (Color::new([0.200, 0.924, 1.102]), Color::new([3.912, 2.452, 2.142]));
+const ALUMINUM: (Color, Color) = (Color::new([1.657, 0.880, 0.521]), Color::new([9.224, 6.270, 4.837]));
+const SILVER: (Color, Color) = (Color::new([0.155, 0.117, 0.138]), Color::new([4.828, 3.122, 2.147]));
+
+pub fn measured_metal(name: &str) -> Option<(Color, Color)> {
+    match name {
+        "gold" => Some(GOLD),
+        "copper" => Some(COPPER),
+        "aluminum" | "aluminium" => Some(ALUMINUM),
+        "silver" => Some(SILVER),
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
+
+    pub fn anisotropic(eta: Color, k: Color, roughness_u: f64, roughness_v: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(
+                roughness_u.clamp(0.0, 1.0).powi(2),
+                roughness_v.clamp(0.0, 1.0).powi(2),
+            ),
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Conductor {
+        Conductor::new(GOLD.0, GOLD.1, roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Conductor {
+        Conductor::new(COPPER.0, COPPER.1, roughness)
+    }
+
+    pub fn aluminum(roughness: f64) -> Conductor {
+        Conductor::new(ALUMINUM.0, ALUMINUM.1, roughness)
+    }
+
+    pub fn silver(roughness: f64) -> Conductor {
+        Conductor::new(SILVER.0, SILVER.1, roughness)
+    }
+
+    pub fn sample(&self, wo: &Vec3) -> Option<LocalSample> {
+        if cos_theta(wo) == 0.0 {
+            return None;
+        }
+        if self.distribution.is_smooth() {
+            let wi = Vec3::new([-wo.x(), -wo.y(), wo.z()]);
+            return Some(LocalSample {
+                wi,
+                weight: fresnel_complex(cos_theta(&wi).abs(), &self.eta, &self.k),
+                pdf: 0.0,
+                specular: true,
+            });
+        }
+
+        let wm = self.distribution.sample_wm(wo, random_pair());
+        let wi = reflect(wo, &wm);
+        if !same_hemisphere(wo, &wi) {
+            return None;
+        }
+        let pdf = self.pdf(wo, &wi);
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(LocalSample {
+            wi,
+            weight: self.eval(wo, &wi) / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+
+    // BRDF times |cos theta_i|
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if !same_hemisphere(wo, wi) || self.distribution.is_smooth() {
+            return BLACK;
+        }
+        let cos_o = cos_theta(wo).abs();
+        let cos_i = cos_theta(wi).abs();
+        if cos_o == 0.0 || cos_i == 0.0 {
+            return BLACK;
+        }
+        let wm = *wi + *wo;
+        if wm.square() == 0.0 {
+            return BLACK;
+        }
+        let wm = wm.unit();
+        let fresnel = fresnel_complex(wo.dot(&wm).abs(), &self.eta, &self.k);
+        self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * cos_o) * fresnel
+    }
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if !same_hemisphere(wo, wi) || self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let wm = *wo + *wi;
+        if wm.square() == 0.0 {
+            return 0.0;
+        }
+        let mut wm = wm.unit();
+        if wm.z() < 0.0 {
+            wm = wm.reverse();
+        }
+        self.distribution.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct RoughDielectric {
+    eta: f64,
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    // `eta` is the index of refraction inside the surface relative to outside
+    pub fn new(eta: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric {
+            eta,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
+
+    pub fn sample(&self, wo: &Vec3) -> Option<LocalSample> {
+        if self.eta == 1.0 {
+            return Some(LocalSample { wi: wo.reverse(), weight: WHITE, pdf: 0.0, specular: true });
+        }
+        let mut rng = rand::thread_rng();
+
+        if self.distribution.is_smooth() {
+            let r = fresnel_dielectric(cos_theta(wo), self.eta);
+            if rng.gen_range(0.0..1.0) < r {
+                let wi = Vec3::new([-wo.x(), -wo.y(), wo.z()]);
+                return Some(LocalSample { wi, weight: WHITE, pdf: 0.0, specular: true });
+            }
+            let (wi, etap) = refract(wo, &Vec3::new([0.0, 0.0, 1.0]), self.eta)?;
+            return Some(LocalSample {
+                wi,
+                weight: WHITE / (etap * etap),
+                pdf: 0.0,
+                specular: true,
+            });
+        }
+
+        let wm = self.distribution.sample_wm(wo, random_pair());
+        let r = fresnel_dielectric(wo.dot(&wm), self.eta);
+        let wi = if rng.gen_range(0.0..1.0) < r {
+            let wi = reflect(wo, &wm);
+            if !same_hemisphere(wo, &wi) {
+                return None;
+            }
+            wi
+        } else {
+            let (wi, _) = refract(wo, &wm, self.eta)?;
+            if same_hemisphere(wo, &wi) || wi.z() == 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let pdf = self.pdf(wo, &wi);
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(LocalSample {
+            wi,
+            weight: self.eval(wo, &wi) / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+
+    // generalized half vector for both reflection and refraction, facing +z;
+    // returns it with the relative index along the path, or None for back-facing microfacets
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, f64)> {
+        let cos_o = cos_theta(wo);
+        let cos_i = cos_theta(wi);
+        if cos_o == 0.0 || cos_i == 0.0 {
+            return None;
+        }
+        let reflect = cos_o * cos_i > 0.0;
+        let etap = if reflect {
+            1.0
+        } else if cos_o > 0.0 {
+            self.eta
+        } else {
+            1.0 / self.eta
+        };
+
+        let wm = *wi * etap + *wo;
+        if wm.square() == 0.0 {
+            return None;
+        }
+        let mut wm = wm.unit();
+        if wm.z() < 0.0 {
+            wm = wm.reverse();
+        }
+        if wm.dot(wi) * cos_i < 0.0 || wm.dot(wo) * cos_o < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+
+    // BSDF times |cos theta_i|, for radiance transport
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if self.eta == 1.0 || self.distribution.is_smooth() {
+            return BLACK;
+        }
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(h) => h,
+            None => return BLACK,
+        };
+
+        let cos_o = cos_theta(wo);
+        let fresnel = fresnel_dielectric(wo.dot(&wm), self.eta);
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+
+        let value = if etap == 1.0 {
+            d * g * fresnel / (4.0 * cos_o.abs())
+        } else {
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2) * cos_o;
+            d * (1.0 - fresnel) * g * (wi.dot(&wm) * wo.dot(&wm) / denom).abs() / (etap * etap)
+        };
+        value * WHITE
+    }
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if self.eta == 1.0 || self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+
+        let r = fresnel_dielectric(wo.dot(&wm), self.eta);
+        let t = 1.0 - r;
+        if etap == 1.0 {
+            self.distribution.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs()) * r / (r + t)
+        } else {
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2);
+            let dwm_dwi = wi.dot(&wm).abs() / denom;
+            self.distribution.pdf(wo, &wm) * dwm_dwi * t / (r + t)
+        }
+    }
+}
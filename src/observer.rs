@@ -0,0 +1,55 @@
+use crate::film::{Film};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::time::{Duration};
+
+// How far a render has got, as reported to a `RenderObserver`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    // of the whole render in [0, 1], by samples, time or noise, whichever is furthest along
+    pub fraction: f64,
+    // the pass under way, counting from 1, and its finished and total tiles
+    pub pass: u32,
+    pub tiles_done: usize,
+    pub tiles: usize,
+    pub elapsed: Duration,
+    // none until something has been rendered to estimate from
+    pub eta: Option<Duration>,
+}
+
+// Hooks into a running render. Both are called on the thread that started the render,
+// `progress` whenever a tile finishes and at least once a second otherwise.
+pub trait RenderObserver {
+    fn progress(&mut self, _progress: &Progress) {}
+
+    // everything rendered so far, after each pass
+    fn pass_finished(&mut self, _film: &Film) {}
+}
+
+// renders nothing is watching
+impl RenderObserver for () {}
+
+// a plain callback only sees progress
+impl<F: FnMut(&Progress)> RenderObserver for F {
+    fn progress(&mut self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+// Stops a render from another thread. The workers finish the tile they are on and the
+// render returns with what it has; clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
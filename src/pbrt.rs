@@ -0,0 +1,862 @@
+// Importer for the part of the pbrt-v3 and pbrt-v4 scene formats this renderer can express:
+// the perspective camera with its film, sampler and integrator settings, the transform
+// directives and attribute stack, named coordinate systems, sphere, disk, triangle mesh and PLY
+// shapes, diffuse, conductor and dielectric materials, diffuse area lights, a constant
+// infinite light as background, and included files. Anything else is reported as an error
+// rather than silently rendered differently.
+//
+// pbrt's world is left handed. The scene is mirrored on the way in, unless the camera itself
+// mirrors it, so images come out the way pbrt renders them.
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color, BLACK};
+use crate::world::{World, Background, ORIGIN};
+use crate::camera::{Camera};
+use crate::ray::{Hittable};
+use crate::material::{Material};
+use crate::microfacet::{Conductor, RoughDielectric, measured_metal};
+use crate::transform::{Mat4, Transform};
+use crate::sphere::{Sphere};
+use crate::disk::{Disk};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::ply::{load_ply};
+use crate::instance::{Instance};
+use crate::bvh::{Bvh};
+use crate::scene::{SceneError};
+use std::collections::{HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc};
+
+mod lexer;
+use lexer::{Lexer, Pos, Token};
+
+// how deeply included files may nest
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+pub fn load_pbrt(path: impl AsRef<Path>) -> Result<(World, Camera), SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut importer = Importer::new(&source, file_name(path), directory);
+    importer.sources[0].path = fs::canonicalize(path).ok();
+    importer.run()
+}
+
+// `directory` is where included files are looked up
+pub fn parse_pbrt(source: &str, directory: impl AsRef<Path>) -> Result<(World, Camera), SceneError> {
+    Importer::new(source, "<input>".to_string(), directory.as_ref().to_path_buf()).run()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+// `file` names the included file the error is in, empty for the main one
+fn error<T>(file: &str, at: Pos, message: impl Into<String>) -> Result<T, SceneError> {
+    let mut message = message.into();
+    if !file.is_empty() {
+        message = format!("{} (in {})", message, file);
+    }
+    Err(SceneError::Parse { line: at.line, column: at.column, message })
+}
+
+enum Value {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+}
+
+struct Param {
+    at: Pos,
+    ty: String,
+    name: String,
+    values: Vec<Value>,
+}
+
+// the parameter list of one directive, e.g. "float radius" [2]
+struct Params {
+    file: String,
+    list: Vec<Param>,
+}
+
+impl Params {
+    fn find(&self, name: &str) -> Option<&Param> {
+        self.list.iter().find(|p| p.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Result<Option<Vec<f64>>, SceneError> {
+        let Some(p) = self.find(name) else {
+            return Ok(None);
+        };
+        let mut numbers = Vec::with_capacity(p.values.len());
+        for value in &p.values {
+            match value {
+                Value::Number(n) => numbers.push(*n),
+                _ => return error(&self.file, p.at, format!("\"{}\" takes numbers", p.name)),
+            }
+        }
+        Ok(Some(numbers))
+    }
+
+    fn float(&self, name: &str, default: f64) -> Result<f64, SceneError> {
+        match self.numbers(name)? {
+            Some(numbers) if !numbers.is_empty() => Ok(numbers[0]),
+            Some(_) => error(&self.file, self.find(name).unwrap().at, format!("\"{}\" is empty", name)),
+            None => Ok(default),
+        }
+    }
+
+    fn indices(&self, name: &str) -> Result<Option<Vec<usize>>, SceneError> {
+        let Some(numbers) = self.numbers(name)? else {
+            return Ok(None);
+        };
+        if numbers.iter().any(|n| n.fract() != 0.0 || *n < 0.0) {
+            let at = self.find(name).unwrap().at;
+            return error(&self.file, at, format!("\"{}\" must hold non-negative integers", name));
+        }
+        Ok(Some(numbers.into_iter().map(|n| n as usize).collect()))
+    }
+
+    fn string(&self, name: &str) -> Result<Option<String>, SceneError> {
+        match self.find(name) {
+            Some(p) => match &p.values[..] {
+                [Value::Str(s)] => Ok(Some(s.clone())),
+                _ => error(&self.file, p.at, format!("\"{}\" takes one string", name)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn boolean(&self, name: &str, default: bool) -> Result<bool, SceneError> {
+        match self.find(name) {
+            Some(p) => match &p.values[..] {
+                [Value::Bool(b)] => Ok(*b),
+                [Value::Str(s)] if s == "true" || s == "false" => Ok(s == "true"),
+                _ => error(&self.file, p.at, format!("\"{}\" takes true or false", name)),
+            },
+            None => Ok(default),
+        }
+    }
+
+    // rgb values, named or sampled spectra, blackbody temperatures and plain floats
+    fn color(&self, name: &str) -> Result<Option<Color>, SceneError> {
+        let Some(p) = self.find(name) else {
+            return Ok(None);
+        };
+        let fail = |message: &str| error(&self.file, p.at, format!("\"{}\": {}", name, message));
+        let numbers = || -> Vec<f64> {
+            p.values.iter().filter_map(|v| if let Value::Number(n) = v { Some(*n) } else { None }).collect()
+        };
+        let color = match (p.ty.as_str(), &p.values[..]) {
+            ("rgb" | "color", [Value::Number(r), Value::Number(g), Value::Number(b)]) => Color::new([*r, *g, *b]),
+            ("float", [Value::Number(x)]) => Color::new([*x; 3]),
+            ("spectrum", [Value::Str(named)]) => match named_spectrum(named) {
+                Some(color) => color,
+                None => return fail(&format!("unknown named spectrum \"{}\"", named)),
+            },
+            ("spectrum", values) if values.len() >= 2 && values.len() % 2 == 0 => {
+                // (wavelength, value) pairs, reduced to their mean
+                let values: Vec<f64> = numbers().chunks(2).map(|pair| pair[1]).collect();
+                if values.len() * 2 != p.values.len() {
+                    return fail("expected wavelength and value pairs");
+                }
+                Color::new([values.iter().sum::<f64>() / values.len() as f64; 3])
+            },
+            ("blackbody", [Value::Number(kelvin), ..]) => blackbody(*kelvin),
+            ("texture", _) => return fail("textures are not supported"),
+            _ => return fail("expected a color"),
+        };
+        Ok(Some(color))
+    }
+}
+
+fn named_spectrum(name: &str) -> Option<Color> {
+    let glass = |ior: f64| Some(Color::new([ior; 3]));
+    match name {
+        "glass-BK7" => glass(1.5168),
+        "glass-BAF10" => glass(1.6700),
+        "glass-FK51A" => glass(1.4866),
+        "glass-LASF9" => glass(1.8503),
+        "glass-F11" | "glass-SF11" => glass(1.7847),
+        _ => {
+            let (metal, part) = name.strip_prefix("metal-")?.split_once('-')?;
+            let metal = match metal {
+                "Au" => "gold",
+                "Cu" => "copper",
+                "Al" => "aluminum",
+                "Ag" => "silver",
+                _ => return None,
+            };
+            let (eta, k) = measured_metal(metal)?;
+            match part {
+                "eta" => Some(eta),
+                "k" => Some(k),
+                _ => None,
+            }
+        },
+    }
+}
+
+// Planck's law at representative red, green and blue wavelengths, scaled so the largest
+// channel is one as pbrt-v4 normalizes blackbody emitters
+fn blackbody(kelvin: f64) -> Color {
+    let planck = |nm: f64| {
+        let l = nm * 1e-9;
+        let (c, h, kb) = (299792458.0, 6.62606957e-34, 1.3806488e-23);
+        2.0 * h * c * c / (l.powi(5) * ((h * c / (l * kb * kelvin)).exp() - 1.0))
+    };
+    let rgb = [planck(610.0), planck(550.0), planck(465.0)];
+    let max = rgb.iter().cloned().fold(0.0, f64::max);
+    if max > 0.0 { Color::new(rgb.map(|x| x / max)) } else { BLACK }
+}
+
+// pbrt roughness becomes the crate's perceptual roughness, the square root of GGX alpha;
+// remapped values go through pbrt-v4's alpha = sqrt(roughness)
+fn roughness(params: &Params, default: f64) -> Result<(f64, f64), SceneError> {
+    let r = params.float("roughness", default)?;
+    let u = params.float("uroughness", r)?;
+    let v = params.float("vroughness", r)?;
+    let remap = params.boolean("remaproughness", true)?;
+    let convert = |r: f64| if remap { r.sqrt().sqrt() } else { r.sqrt() };
+    Ok((convert(u), convert(v)))
+}
+
+fn material(at: Pos, kind: &str, params: &Params) -> Result<Material, SceneError> {
+    let gray = Color::new([0.5; 3]);
+    Ok(match kind {
+        "diffuse" | "matte" => {
+            let albedo = match params.color("reflectance")? {
+                Some(c) => c,
+                None => params.color("Kd")?.unwrap_or(gray),
+            };
+            Material::Lambertian(Arc::new(albedo))
+        },
+        "conductor" | "metal" => {
+            // pbrt-v3 metals default to a slightly rough surface
+            let (u, v) = roughness(params, if kind == "metal" { 0.01 } else { 0.0 })?;
+            match params.color("reflectance")? {
+                Some(albedo) => Material::Metal(Arc::new(albedo), Arc::new((u + v) / 2.0)),
+                None => {
+                    let (copper_eta, copper_k) = measured_metal("copper").unwrap();
+                    let eta = params.color("eta")?.unwrap_or(copper_eta);
+                    let k = params.color("k")?.unwrap_or(copper_k);
+                    Material::Conductor(Conductor::anisotropic(eta, k, u, v))
+                },
+            }
+        },
+        "dielectric" | "glass" => {
+            let eta = match params.color("eta")? {
+                Some(eta) => (eta.x() + eta.y() + eta.z()) / 3.0,
+                None => params.float("index", 1.5)?,
+            };
+            let (u, v) = roughness(params, 0.0)?;
+            if u == 0.0 && v == 0.0 {
+                Material::Dielectric(eta)
+            } else {
+                Material::RoughDielectric(RoughDielectric::new(eta, (u + v) / 2.0))
+            }
+        },
+        _ => return error(&params.file, at, format!("unsupported material \"{}\"", kind)),
+    })
+}
+
+// camera to world transform from a pbrt LookAt; None for a degenerate view
+fn look_at(eye: Point, target: Point, up: Vec3) -> Option<Transform> {
+    let dir = target - eye;
+    let right = up.unit().cross(&dir.unit());
+    if dir.square() == 0.0 || right.square() == 0.0 {
+        return None;
+    }
+    let dir = dir.unit();
+    let right = right.unit();
+    let new_up = dir.cross(&right);
+    Transform::from_matrix(Mat4::new([
+        [right.x(), new_up.x(), dir.x(), eye.x()],
+        [right.y(), new_up.y(), dir.y(), eye.y()],
+        [right.z(), new_up.z(), dir.z(), eye.z()],
+        [0.0, 0.0, 0.0, 1.0],
+    ]))
+}
+
+struct Source {
+    lexer: Lexer,
+    name: String,
+    directory: PathBuf,
+    // canonical, to recognize a file including itself; none for source given as a string
+    path: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+struct Attributes {
+    ctm: Transform,
+    material: Material,
+    area_light: Option<Color>,
+    reverse: bool,
+}
+
+// what AttributeEnd and TransformEnd restore
+enum Saved {
+    Attributes(Attributes),
+    Transform(Transform),
+}
+
+struct CameraSpec {
+    camera_to_world: Transform,
+    fov: f64,
+    lens_radius: f64,
+    focal_distance: f64,
+}
+
+struct Importer {
+    sources: Vec<Source>,
+    peeked: Option<(Pos, Token)>,
+    // the included file holding the current directive, empty for the main file; reading
+    // ahead may already have left it
+    directive_file: String,
+
+    attributes: Attributes,
+    stack: Vec<Saved>,
+    coordinate_systems: HashMap<String, Transform>,
+    named_materials: HashMap<String, Material>,
+
+    camera: Option<CameraSpec>,
+    resolution: (u32, u32),
+    samples: u16,
+    max_depth: u8,
+    // pbrt space to the crate's, fixed by the camera before the first shape
+    to_world: Option<Transform>,
+
+    background: Color,
+    objects: Vec<Arc<dyn Hittable>>,
+    lights: Vec<Arc<dyn Hittable>>,
+    // included and PLY files, in the order they were read
+    files: Vec<PathBuf>,
+}
+
+impl Importer {
+    fn new(source: &str, name: String, directory: PathBuf) -> Importer {
+        Importer {
+            sources: vec![Source { lexer: Lexer::new(source), name, directory, path: None }],
+            peeked: None,
+            directive_file: String::new(),
+            attributes: Attributes {
+                ctm: Transform::identity(),
+                material: Material::Lambertian(Arc::new(Color::new([0.5; 3]))),
+                area_light: None,
+                reverse: false,
+            },
+            stack: Vec::new(),
+            coordinate_systems: HashMap::new(),
+            named_materials: HashMap::new(),
+            camera: None,
+            resolution: (1280, 720),
+            samples: 16,
+            max_depth: 5,
+            to_world: None,
+            background: BLACK,
+            objects: Vec::new(),
+            lights: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    fn file(&self) -> &str {
+        &self.directive_file
+    }
+
+    // the included file the lexer is in, empty for the main file
+    fn reading(&self) -> &str {
+        match &self.sources[..] {
+            [_] => "",
+            [.., included] => &included.name,
+            [] => unreachable!(),
+        }
+    }
+
+    fn error<T>(&self, at: Pos, message: impl Into<String>) -> Result<T, SceneError> {
+        error(self.file(), at, message)
+    }
+
+    // included files end silently and hand back to the file that included them
+    fn next(&mut self) -> Result<(Pos, Token), SceneError> {
+        if let Some(token) = self.peeked.take() {
+            return Ok(token);
+        }
+        loop {
+            match self.sources.last_mut().unwrap().lexer.next_token() {
+                Ok((_, Token::Eof)) if self.sources.len() > 1 => {
+                    self.sources.pop();
+                },
+                Ok(token) => return Ok(token),
+                Err((at, message)) => return error(self.reading(), at, message),
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<&Token, SceneError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next()?);
+        }
+        Ok(&self.peeked.as_ref().unwrap().1)
+    }
+
+    fn string(&mut self, directive: &str) -> Result<String, SceneError> {
+        match self.next()? {
+            (_, Token::Str(s)) => Ok(s),
+            (at, other) => self.error(at, format!("`{}` expects a string, found {}", directive, other.describe())),
+        }
+    }
+
+    // `count` numbers, optionally inside brackets
+    fn numbers(&mut self, directive: &str, count: usize) -> Result<Vec<f64>, SceneError> {
+        let bracketed = *self.peek()? == Token::Open;
+        if bracketed {
+            self.next()?;
+        }
+        let mut numbers = Vec::with_capacity(count);
+        while numbers.len() < count {
+            match self.next()? {
+                (_, Token::Number(n)) => numbers.push(n),
+                (at, other) => {
+                    let message = format!("`{}` expects {} numbers, found {}", directive, count, other.describe());
+                    return self.error(at, message);
+                },
+            }
+        }
+        if bracketed {
+            match self.next()? {
+                (_, Token::Close) => {},
+                (at, other) => return self.error(at, format!("expected `]`, found {}", other.describe())),
+            }
+        }
+        Ok(numbers)
+    }
+
+    fn value(&self, at: Pos, token: Token) -> Result<Value, SceneError> {
+        match token {
+            Token::Number(n) => Ok(Value::Number(n)),
+            Token::Str(s) => Ok(Value::Str(s)),
+            Token::Word(w) if w == "true" || w == "false" => Ok(Value::Bool(w == "true")),
+            other => self.error(at, format!("expected a parameter value, found {}", other.describe())),
+        }
+    }
+
+    fn params(&mut self) -> Result<Params, SceneError> {
+        let mut list = Vec::new();
+        while let Token::Str(_) = self.peek()? {
+            let (at, Token::Str(declaration)) = self.next()? else {
+                unreachable!()
+            };
+            let parts: Vec<&str> = declaration.split_whitespace().collect();
+            let [ty, name] = parts[..] else {
+                return self.error(at, format!("malformed parameter \"{}\"", declaration));
+            };
+            let values = match self.next()? {
+                (_, Token::Open) => {
+                    let mut values = Vec::new();
+                    loop {
+                        match self.next()? {
+                            (_, Token::Close) => break,
+                            (at, token) => values.push(self.value(at, token)?),
+                        }
+                    }
+                    values
+                },
+                (at, token) => vec![self.value(at, token)?],
+            };
+            list.push(Param { at, ty: ty.to_string(), name: name.to_string(), values });
+        }
+        Ok(Params { file: self.file().to_string(), list })
+    }
+
+    fn apply(&mut self, t: Transform) {
+        self.attributes.ctm = self.attributes.ctm * t;
+    }
+
+    fn run(mut self) -> Result<(World, Camera), SceneError> {
+        loop {
+            match self.next()? {
+                (_, Token::Eof) => break,
+                (at, Token::Word(directive)) => {
+                    self.directive_file = self.reading().to_string();
+                    self.directive(at, &directive)?
+                },
+                (at, other) => return self.error(at, format!("expected a directive, found {}", other.describe())),
+            }
+        }
+        Ok(self.finish())
+    }
+
+    fn directive(&mut self, at: Pos, directive: &str) -> Result<(), SceneError> {
+        match directive {
+            "Identity" => self.attributes.ctm = Transform::identity(),
+            "Translate" => {
+                let v = self.numbers(directive, 3)?;
+                self.apply(Transform::translate(Vec3::new([v[0], v[1], v[2]])));
+            },
+            "Scale" => {
+                let v = self.numbers(directive, 3)?;
+                if v.contains(&0.0) {
+                    return self.error(at, "scale factors must be non-zero");
+                }
+                self.apply(Transform::scale(Vec3::new([v[0], v[1], v[2]])));
+            },
+            "Rotate" => {
+                let v = self.numbers(directive, 4)?;
+                let axis = Vec3::new([v[1], v[2], v[3]]);
+                if axis.square() == 0.0 {
+                    return self.error(at, "the rotation axis must not be a zero vector");
+                }
+                self.apply(Transform::rotate(axis, v[0]));
+            },
+            "LookAt" => {
+                let v = self.numbers(directive, 9)?;
+                let camera_to_world = look_at(
+                    Point::new([v[0], v[1], v[2]]),
+                    Point::new([v[3], v[4], v[5]]),
+                    Vec3::new([v[6], v[7], v[8]]),
+                );
+                match camera_to_world {
+                    Some(t) => self.apply(t.inverse()),
+                    None => return self.error(at, "degenerate `LookAt`"),
+                }
+            },
+            "Transform" | "ConcatTransform" => {
+                // the sixteen numbers list the matrix column by column
+                let v = self.numbers(directive, 16)?;
+                let mut rows = [[0.0; 4]; 4];
+                for (i, row) in rows.iter_mut().enumerate() {
+                    for (j, x) in row.iter_mut().enumerate() {
+                        *x = v[j * 4 + i];
+                    }
+                }
+                let Some(t) = Transform::from_matrix(Mat4::new(rows)) else {
+                    return self.error(at, format!("`{}` matrix is not invertible", directive));
+                };
+                if directive == "Transform" {
+                    self.attributes.ctm = t;
+                } else {
+                    self.apply(t);
+                }
+            },
+            "CoordinateSystem" => {
+                let name = self.string(directive)?;
+                self.coordinate_systems.insert(name, self.attributes.ctm);
+            },
+            "CoordSysTransform" => {
+                let name = self.string(directive)?;
+                match self.coordinate_systems.get(&name) {
+                    Some(t) => self.attributes.ctm = *t,
+                    None => return self.error(at, format!("unknown coordinate system \"{}\"", name)),
+                }
+            },
+            "ReverseOrientation" => self.attributes.reverse = !self.attributes.reverse,
+            "AttributeBegin" => self.stack.push(Saved::Attributes(self.attributes.clone())),
+            "TransformBegin" => self.stack.push(Saved::Transform(self.attributes.ctm)),
+            "AttributeEnd" | "TransformEnd" => match (self.stack.pop(), directive) {
+                (Some(Saved::Attributes(saved)), "AttributeEnd") => self.attributes = saved,
+                (Some(Saved::Transform(ctm)), "TransformEnd") => self.attributes.ctm = ctm,
+                _ => return self.error(at, format!("unmatched `{}`", directive)),
+            },
+            "Camera" => {
+                let kind = self.string(directive)?;
+                let params = self.params()?;
+                if kind != "perspective" {
+                    return self.error(at, format!("unsupported camera \"{}\"", kind));
+                }
+                let camera_to_world = self.attributes.ctm.inverse();
+                self.coordinate_systems.insert("camera".to_string(), camera_to_world);
+                self.camera = Some(CameraSpec {
+                    camera_to_world,
+                    fov: params.float("fov", 90.0)?,
+                    lens_radius: params.float("lensradius", 0.0)?,
+                    focal_distance: params.float("focaldistance", 1e6)?,
+                });
+            },
+            "Film" => {
+                self.string(directive)?;
+                let params = self.params()?;
+                let width = params.float("xresolution", 1280.0)?;
+                let height = params.float("yresolution", 720.0)?;
+                if width < 1.0 || height < 1.0 {
+                    return self.error(at, "the film resolution must be positive");
+                }
+                self.resolution = (width as u32, height as u32);
+            },
+            "Sampler" => {
+                self.string(directive)?;
+                let params = self.params()?;
+                self.samples = params.float("pixelsamples", 16.0)?.clamp(1.0, u16::MAX as f64) as u16;
+            },
+            "Integrator" => {
+                self.string(directive)?;
+                let params = self.params()?;
+                self.max_depth = params.float("maxdepth", 5.0)?.clamp(1.0, u8::MAX as f64) as u8;
+            },
+            // settings with no counterpart here
+            "PixelFilter" | "Accelerator" | "ColorSpace" => {
+                self.string(directive)?;
+                self.params()?;
+            },
+            "Option" => {
+                self.params()?;
+            },
+            "WorldBegin" => {
+                self.attributes.ctm = Transform::identity();
+                self.coordinate_systems.insert("world".to_string(), Transform::identity());
+            },
+            "WorldEnd" => {},
+            "Material" => {
+                let kind = self.string(directive)?;
+                let params = self.params()?;
+                self.attributes.material = material(at, &kind, &params)?;
+            },
+            "MakeNamedMaterial" => {
+                let name = self.string(directive)?;
+                let params = self.params()?;
+                let Some(kind) = params.string("type")? else {
+                    return self.error(at, format!("named material \"{}\" has no \"string type\"", name));
+                };
+                let made = material(at, &kind, &params)?;
+                self.named_materials.insert(name, made);
+            },
+            "NamedMaterial" => {
+                let name = self.string(directive)?;
+                match self.named_materials.get(&name) {
+                    Some(m) => self.attributes.material = m.clone(),
+                    None => return self.error(at, format!("unknown material \"{}\"", name)),
+                }
+            },
+            "AreaLightSource" => {
+                let kind = self.string(directive)?;
+                let params = self.params()?;
+                if kind != "diffuse" {
+                    return self.error(at, format!("unsupported area light \"{}\"", kind));
+                }
+                // emission is one sided; "twosided" lights only shine from their front
+                let radiance = params.color("L")?.unwrap_or(Color::new([1.0; 3]));
+                self.attributes.area_light = Some(params.float("scale", 1.0)? * radiance);
+            },
+            "LightSource" => {
+                let kind = self.string(directive)?;
+                let params = self.params()?;
+                if kind != "infinite" || params.find("filename").is_some() {
+                    return self.error(at, format!("unsupported light source \"{}\"", kind));
+                }
+                let radiance = params.color("L")?.unwrap_or(Color::new([1.0; 3]));
+                self.background = self.background + params.float("scale", 1.0)? * radiance;
+            },
+            "Shape" => {
+                let kind = self.string(directive)?;
+                let params = self.params()?;
+                self.shape(at, &kind, &params)?;
+            },
+            "Include" | "Import" => {
+                let name = self.string(directive)?;
+                let path = self.sources.last().unwrap().directory.join(&name);
+                if self.sources.len() > MAX_INCLUDE_DEPTH {
+                    let message = format!("\"{}\" is nested more than {} includes deep", name, MAX_INCLUDE_DEPTH);
+                    return self.error(at, message);
+                }
+                let canonical = fs::canonicalize(&path).ok();
+                if canonical.is_some() && self.sources.iter().any(|s| s.path == canonical) {
+                    return self.error(at, format!("\"{}\" includes itself", name));
+                }
+                let source = match fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(e) => return self.error(at, format!("could not include \"{}\": {}", name, e)),
+                };
+                let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                self.files.push(path.clone());
+                let name = file_name(&path);
+                self.sources.push(Source { lexer: Lexer::new(&source), name, directory, path: canonical });
+            },
+            _ => return self.error(at, format!("unsupported directive `{}`", directive)),
+        }
+        Ok(())
+    }
+
+    // pbrt's space into the crate's: a mirror, unless the camera mirrors already
+    fn world_space(&mut self) -> Transform {
+        *self.to_world.get_or_insert_with(|| {
+            let camera_mirrors = self.camera.as_ref().is_some_and(|c| c.camera_to_world.swaps_handedness());
+            if camera_mirrors {
+                Transform::identity()
+            } else {
+                Transform::scale(Vec3::new([-1.0, 1.0, 1.0]))
+            }
+        })
+    }
+
+    fn shape(&mut self, at: Pos, kind: &str, params: &Params) -> Result<(), SceneError> {
+        let to_world = self.world_space();
+        let to_world = self.attributes.ctm.then(&to_world);
+        let material = match self.attributes.area_light {
+            Some(radiance) => Material::DiffuseLight(Arc::new(radiance)),
+            None => self.attributes.material.clone(),
+        };
+
+        let object: Arc<dyn Hittable> = match kind {
+            "sphere" => {
+                let sphere = Sphere::new(ORIGIN, params.float("radius", 1.0)?, material);
+                Arc::new(Instance::new(Arc::new(sphere), to_world))
+            },
+            "disk" => {
+                let normal = if self.attributes.reverse { -1.0 } else { 1.0 };
+                let disk = Disk::new(
+                    Point::new([0.0, 0.0, params.float("height", 0.0)?]),
+                    Vec3::new([0.0, 0.0, normal]),
+                    params.float("radius", 1.0)?,
+                    material,
+                );
+                Arc::new(Instance::new(Arc::new(disk), to_world))
+            },
+            "trianglemesh" | "plymesh" => {
+                let mut mesh = match kind {
+                    "plymesh" => self.ply_mesh(at, params)?,
+                    _ => self.triangle_mesh(at, params)?,
+                };
+                mesh.transform(&to_world);
+                if self.attributes.reverse {
+                    mesh.flip_winding();
+                }
+                Arc::new(TriangleMesh::new(mesh, material))
+            },
+            _ => return self.error(at, format!("unsupported shape \"{}\"", kind)),
+        };
+
+        if self.attributes.area_light.is_some() {
+            self.lights.push(object);
+        } else {
+            self.objects.push(object);
+        }
+        Ok(())
+    }
+
+    fn triangle_mesh(&self, at: Pos, params: &Params) -> Result<Mesh, SceneError> {
+        let Some(p) = params.numbers("P")? else {
+            return self.error(at, "\"trianglemesh\" needs \"P\"");
+        };
+        if p.len() % 3 != 0 {
+            return self.error(at, "\"P\" must hold three numbers per vertex");
+        }
+        let positions: Vec<Point> = p.chunks(3).map(|c| Point::new([c[0], c[1], c[2]])).collect();
+
+        let indices = match params.indices("indices")? {
+            Some(indices) if indices.len() % 3 == 0 => indices,
+            Some(_) => return self.error(at, "\"indices\" must hold three entries per triangle"),
+            None if positions.len() == 3 => vec![0, 1, 2],
+            None => return self.error(at, "\"trianglemesh\" needs \"indices\""),
+        };
+        let mut mesh = Mesh::new(positions, indices.chunks(3).map(|c| [c[0], c[1], c[2]]).collect());
+
+        if let Some(n) = params.numbers("N")? {
+            mesh.normals = n.chunks_exact(3).map(|c| Vec3::new([c[0], c[1], c[2]])).collect();
+        }
+        let uv = match params.numbers("uv")? {
+            Some(uv) => Some(uv),
+            None => params.numbers("st")?,
+        };
+        if let Some(uv) = uv {
+            mesh.uvs = uv.chunks_exact(2).map(|c| (c[0], c[1])).collect();
+        }
+        if let Err(e) = mesh.validate() {
+            return self.error(at, e);
+        }
+        Ok(mesh)
+    }
+
+    fn ply_mesh(&mut self, at: Pos, params: &Params) -> Result<Mesh, SceneError> {
+        let Some(name) = params.string("filename")? else {
+            return self.error(at, "\"plymesh\" needs \"filename\"");
+        };
+        let path = self.sources.last().unwrap().directory.join(&name);
+        self.files.push(path.clone());
+        match load_ply(&path) {
+            Ok(mesh) => Ok(mesh),
+            Err(e) => self.error(at, format!("{}: {}", name, e)),
+        }
+    }
+
+    fn finish(mut self) -> (World, Camera) {
+        let camera_to_world = self.camera.as_ref().map_or(Transform::identity(), |c| c.camera_to_world);
+        let to_world = self.world_space();
+        let to_world = camera_to_world.then(&to_world);
+        let eye = to_world.point(&ORIGIN);
+        let forward = to_world.vector(&Vec3::new([0.0, 0.0, 1.0]));
+        let up = to_world.vector(&Vec3::new([0.0, 1.0, 0.0]));
+
+        // pbrt's field of view spans the shorter image side
+        let (width, height) = self.resolution;
+        let mut fov = self.camera.as_ref().map_or(90.0, |c| c.fov);
+        if width < height {
+            fov = 2.0 * ((fov.to_radians() / 2.0).tan() * height as f64 / width as f64).atan().to_degrees();
+        }
+        let (angle, focus_dist) = match &self.camera {
+            Some(c) if c.lens_radius > 0.0 => {
+                (2.0 * (c.lens_radius / c.focal_distance).atan().to_degrees(), c.focal_distance)
+            },
+            _ => (0.0, 1.0),
+        };
+        let camera = Camera::new(eye, eye + forward)
+            .up(up)
+            .v_fov(fov)
+            .resolution(width, height)
+            .defocus(angle, focus_dist)
+            .samples(self.samples)
+            .max_depth(self.max_depth);
+
+        let mut world = World::new();
+        world.set_background(Background::Solid(self.background));
+        for file in self.files {
+            world.add_source(file);
+        }
+        for light in self.lights {
+            world.add_light(light);
+        }
+        if !self.objects.is_empty() {
+            world.add(Arc::new(Bvh::new(self.objects)));
+        }
+        (world, camera)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("ray-tracing-pbrt-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn load_error(path: &Path) -> String {
+        match load_pbrt(path) {
+            Err(SceneError::Parse { message, .. }) => message,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("{} loaded", path.display()),
+        }
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let directory = scratch_directory("cycle");
+        fs::write(directory.join("loop.pbrt"), "Include \"loop.pbrt\"\n").unwrap();
+        assert!(load_error(&directory.join("loop.pbrt")).contains("includes itself"));
+        fs::write(directory.join("a.pbrt"), "Include \"b.pbrt\"\n").unwrap();
+        fs::write(directory.join("b.pbrt"), "Include \"./a.pbrt\"\n").unwrap();
+        assert!(load_error(&directory.join("a.pbrt")).contains("includes itself"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn include_depth_is_limited() {
+        let directory = scratch_directory("depth");
+        for i in 0..=MAX_INCLUDE_DEPTH + 1 {
+            fs::write(directory.join(format!("{}.pbrt", i)), format!("Include \"{}.pbrt\"\n", i + 1)).unwrap();
+        }
+        assert!(load_error(&directory.join("0.pbrt")).contains("nested"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
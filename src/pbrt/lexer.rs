@@ -0,0 +1,130 @@
+// Tokens of the pbrt format: bare words for directives, quoted strings, numbers and the
+// brackets around parameter arrays. `#` comments run to the end of the line.
+#[derive(Debug, Copy, Clone)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    Str(String),
+    Number(f64),
+    Open,
+    Close,
+    Eof,
+}
+
+impl Token {
+    pub fn describe(&self) -> String {
+        match self {
+            Token::Word(w) => format!("`{}`", w),
+            Token::Str(s) => format!("string \"{}\"", s),
+            Token::Number(n) => format!("number {}", n),
+            Token::Open => "`[`".to_string(),
+            Token::Close => "`]`".to_string(),
+            Token::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+pub struct Lexer {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer {
+    pub fn new(source: &str) -> Lexer {
+        Lexer { chars: source.chars().collect(), index: 0, line: 1, column: 1 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if !accept(c) {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+        text
+    }
+
+    pub fn next_token(&mut self) -> Result<(Pos, Token), (Pos, String)> {
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.take_while(|c| c != '\n');
+                },
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                },
+                _ => break,
+            }
+        }
+
+        let at = Pos { line: self.line, column: self.column };
+        let Some(c) = self.peek() else {
+            return Ok((at, Token::Eof));
+        };
+        let token = match c {
+            '[' => {
+                self.bump();
+                Token::Open
+            },
+            ']' => {
+                self.bump();
+                Token::Close
+            },
+            '"' => {
+                self.bump();
+                let mut text = String::new();
+                loop {
+                    match self.bump() {
+                        None | Some('\n') => return Err((at, "unterminated string".to_string())),
+                        Some('"') => break,
+                        Some('\\') => match self.bump() {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some(c) => text.push(c),
+                            None => return Err((at, "unterminated string".to_string())),
+                        },
+                        Some(c) => text.push(c),
+                    }
+                }
+                Token::Str(text)
+            },
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let text = self.take_while(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+                match text.parse::<f64>() {
+                    Ok(n) if n.is_finite() => Token::Number(n),
+                    _ => return Err((at, format!("invalid number `{}`", text))),
+                }
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                Token::Word(self.take_while(|c| c.is_alphanumeric() || c == '_'))
+            },
+            c => return Err((at, format!("unexpected character `{}`", c))),
+        };
+        Ok((at, token))
+    }
+}
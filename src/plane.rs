@@ -0,0 +1,44 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::vec3::{Point, Vec3, Onb};
+use std::sync::{Arc};
+
+// infinite plane through `point`; uv coordinates repeat every unit along the plane
+pub struct Plane {
+    point: Point,
+    frame: Onb,
+    mat: Arc<dyn Bsdf>,
+}
+
+impl Plane {
+    pub fn new(point: Point, normal: Vec3, m: impl Into<Arc<dyn Bsdf>>) -> Plane {
+        Plane {
+            point,
+            frame: Onb::new(&normal.unit()),
+            mat: m.into(),
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let normal = self.frame.w();
+        let denom = normal.dot(ray.direct());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = normal.dot(&(self.point - *ray.org())) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let position = ray.range(t);
+        let local = self.frame.to_local(&(position - self.point));
+        let uv = (local.x() - local.x().floor(), local.y() - local.y().floor());
+
+        let front_face = denom < 0.0;
+        let normal = if front_face { *normal } else { normal.reverse() };
+        Some(HitRecord::new(t, position, normal, front_face, uv, Arc::clone(&self.mat)))
+    }
+}
@@ -0,0 +1,406 @@
+// Polygon File Format (.ply) meshes in ASCII or binary of either byte order. Vertices may
+// carry normals, texture coordinates and colors; faces are lists of vertex indices and are
+// split into triangle fans. Other elements and properties are read past and ignored.
+use crate::mesh::{Mesh};
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color};
+use crate::image::{srgb_to_linear};
+use std::fmt;
+use std::fs;
+use std::path::{Path};
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(std::io::Error),
+    Unsupported(String),
+    Corrupt(String),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlyError::Io(e) => write!(f, "could not read mesh: {}", e),
+            PlyError::Unsupported(what) => write!(f, "unsupported ply: {}", what),
+            PlyError::Corrupt(what) => write!(f, "corrupt ply: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlyError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PlyError {
+    fn from(e: std::io::Error) -> PlyError {
+        PlyError::Io(e)
+    }
+}
+
+fn corrupt<T>(what: impl Into<String>) -> Result<T, PlyError> {
+    Err(PlyError::Corrupt(what.into()))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // integer colors are fractions of the largest value of their type
+    fn color_scale(self) -> Option<f64> {
+        match self {
+            Scalar::I8 => Some(i8::MAX as f64),
+            Scalar::U8 => Some(u8::MAX as f64),
+            Scalar::I16 => Some(i16::MAX as f64),
+            Scalar::U16 => Some(u16::MAX as f64),
+            Scalar::I32 => Some(i32::MAX as f64),
+            Scalar::U32 => Some(u32::MAX as f64),
+            Scalar::F32 | Scalar::F64 => None,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    // the type of the length, then of the items
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Copy, Clone)]
+enum Encoding {
+    Ascii,
+    Binary { big_endian: bool },
+}
+
+// returns the layout of the body and the offset at which it starts
+fn header(bytes: &[u8]) -> Result<(Encoding, Vec<Element>, usize), PlyError> {
+    let mut offset = 0;
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut first = true;
+    loop {
+        let Some(length) = bytes[offset..].iter().position(|b| *b == b'\n') else {
+            return corrupt("header does not end with \"end_header\"");
+        };
+        let line = String::from_utf8_lossy(&bytes[offset..offset + length]);
+        offset += length + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        if first {
+            if words != ["ply"] {
+                return corrupt("missing \"ply\" signature");
+            }
+            first = false;
+            continue;
+        }
+        match words.as_slice() {
+            [] => {},
+            ["comment", ..] | ["obj_info", ..] => {},
+            ["format", format, version] => {
+                if !version.starts_with('1') {
+                    return Err(PlyError::Unsupported(format!("format version {}", version)));
+                }
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::Binary { big_endian: false },
+                    "binary_big_endian" => Encoding::Binary { big_endian: true },
+                    _ => return Err(PlyError::Unsupported(format!("format \"{}\"", format))),
+                });
+            },
+            ["element", name, count] => {
+                let Ok(count) = count.parse() else {
+                    return corrupt(format!("invalid count for element \"{}\"", name));
+                };
+                elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+            },
+            ["property", "list", length, item, name] => {
+                let (Some(length), Some(item)) = (Scalar::parse(length), Scalar::parse(item)) else {
+                    return corrupt(format!("unknown type for property \"{}\"", name));
+                };
+                if matches!(length, Scalar::F32 | Scalar::F64) {
+                    return corrupt(format!("list \"{}\" has a floating point length", name));
+                }
+                let Some(element) = elements.last_mut() else {
+                    return corrupt("property before any element");
+                };
+                element.properties.push(Property::List(name.to_string(), length, item));
+            },
+            ["property", kind, name] => {
+                let Some(kind) = Scalar::parse(kind) else {
+                    return corrupt(format!("unknown type for property \"{}\"", name));
+                };
+                let Some(element) = elements.last_mut() else {
+                    return corrupt("property before any element");
+                };
+                element.properties.push(Property::Scalar(name.to_string(), kind));
+            },
+            ["end_header"] => break,
+            _ => return corrupt(format!("invalid header line \"{}\"", line.trim())),
+        }
+    }
+
+    match encoding {
+        Some(encoding) => Ok((encoding, elements, offset)),
+        None => corrupt("header has no format line"),
+    }
+}
+
+struct Body<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    encoding: Encoding,
+}
+
+impl Body<'_> {
+    fn read(&mut self, kind: Scalar) -> Result<f64, PlyError> {
+        let big_endian = match self.encoding {
+            Encoding::Ascii => return self.word(),
+            Encoding::Binary { big_endian } => big_endian,
+        };
+        let Some(raw) = self.bytes.get(self.offset..self.offset + kind.size()) else {
+            return corrupt("file ends inside the element data");
+        };
+        self.offset += kind.size();
+
+        macro_rules! number {
+            ($t:ty) => {{
+                let raw = raw.try_into().unwrap();
+                (if big_endian { <$t>::from_be_bytes(raw) } else { <$t>::from_le_bytes(raw) }) as f64
+            }};
+        }
+        Ok(match kind {
+            Scalar::I8 => number!(i8),
+            Scalar::U8 => number!(u8),
+            Scalar::I16 => number!(i16),
+            Scalar::U16 => number!(u16),
+            Scalar::I32 => number!(i32),
+            Scalar::U32 => number!(u32),
+            Scalar::F32 => number!(f32),
+            Scalar::F64 => number!(f64),
+        })
+    }
+
+    fn word(&mut self) -> Result<f64, PlyError> {
+        let rest = &self.bytes[self.offset..];
+        let start = rest.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(rest.len());
+        let length = rest[start..].iter().position(|b| b.is_ascii_whitespace()).unwrap_or(rest.len() - start);
+        self.offset += start + length;
+        if length == 0 {
+            return corrupt("file ends inside the element data");
+        }
+        let word = String::from_utf8_lossy(&rest[start..start + length]);
+        match word.parse::<f64>() {
+            Ok(n) => Ok(n),
+            Err(_) => corrupt(format!("invalid number \"{}\"", word)),
+        }
+    }
+
+    fn list(&mut self, length: Scalar, item: Scalar) -> Result<Vec<f64>, PlyError> {
+        let count = self.read(length)?;
+        if count < 0.0 || count.fract() != 0.0 {
+            return corrupt(format!("invalid list length {}", count));
+        }
+        // the length is checked against the data as it is read rather than trusted up front
+        let mut items = Vec::new();
+        for _ in 0..count as usize {
+            items.push(self.read(item)?);
+        }
+        Ok(items)
+    }
+}
+
+// where each vertex property goes
+#[derive(Copy, Clone, PartialEq)]
+enum Slot {
+    Position(usize),
+    Normal(usize),
+    Uv(usize),
+    Color(usize),
+    Skip,
+}
+
+fn slot(name: &str) -> Slot {
+    match name {
+        "x" => Slot::Position(0),
+        "y" => Slot::Position(1),
+        "z" => Slot::Position(2),
+        "nx" => Slot::Normal(0),
+        "ny" => Slot::Normal(1),
+        "nz" => Slot::Normal(2),
+        "u" | "s" | "texture_u" | "texture_s" => Slot::Uv(0),
+        "v" | "t" | "texture_v" | "texture_t" => Slot::Uv(1),
+        "red" | "diffuse_red" => Slot::Color(0),
+        "green" | "diffuse_green" => Slot::Color(1),
+        "blue" | "diffuse_blue" => Slot::Color(2),
+        _ => Slot::Skip,
+    }
+}
+
+pub fn load_ply(path: impl AsRef<Path>) -> Result<Mesh, PlyError> {
+    let bytes = fs::read(path)?;
+    decode_ply(&bytes)
+}
+
+pub fn decode_ply(bytes: &[u8]) -> Result<Mesh, PlyError> {
+    let (encoding, elements, offset) = header(bytes)?;
+    let mut body = Body { bytes, offset, encoding };
+    let mut mesh = Mesh::default();
+    let mut seen_vertices = false;
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                seen_vertices = true;
+                vertices(&mut body, element, &mut mesh)?;
+            },
+            "face" => faces(&mut body, element, &mut mesh)?,
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match property {
+                            Property::Scalar(_, kind) => { body.read(*kind)?; },
+                            Property::List(_, length, item) => { body.list(*length, *item)?; },
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    if !seen_vertices {
+        return corrupt("no vertex element");
+    }
+    if let Err(e) = mesh.validate() {
+        return corrupt(e);
+    }
+    Ok(mesh)
+}
+
+fn vertices(body: &mut Body, element: &Element, mesh: &mut Mesh) -> Result<(), PlyError> {
+    let slots: Vec<Slot> = element.properties.iter().map(|p| match p {
+        Property::Scalar(name, _) => slot(name),
+        Property::List(..) => Slot::Skip,
+    }).collect();
+    let has = |wanted: fn(usize) -> Slot, count: usize| (0..count).all(|i| slots.contains(&wanted(i)));
+    if !has(Slot::Position, 3) {
+        return corrupt("vertices need x, y and z");
+    }
+    let (normals, uvs, colors) = (has(Slot::Normal, 3), has(Slot::Uv, 2), has(Slot::Color, 3));
+
+    let capacity = element.count.min(body.bytes.len());
+    mesh.positions.reserve(capacity);
+    for _ in 0..element.count {
+        let (mut position, mut normal, mut uv, mut color) = ([0.0; 3], [0.0; 3], [0.0; 2], [0.0; 3]);
+        for (property, slot) in element.properties.iter().zip(&slots) {
+            let value = match property {
+                Property::Scalar(_, kind) => (body.read(*kind)?, *kind),
+                Property::List(_, length, item) => {
+                    body.list(*length, *item)?;
+                    continue;
+                },
+            };
+            match *slot {
+                Slot::Position(i) => position[i] = value.0,
+                Slot::Normal(i) => normal[i] = value.0,
+                Slot::Uv(i) => uv[i] = value.0,
+                // integer colors are stored gamma encoded, floating point ones linear
+                Slot::Color(i) => color[i] = match value.1.color_scale() {
+                    Some(scale) => srgb_to_linear(value.0 / scale),
+                    None => value.0,
+                },
+                Slot::Skip => {},
+            }
+        }
+
+        mesh.positions.push(Point::new(position));
+        if normals {
+            mesh.normals.push(Vec3::new(normal));
+        }
+        if uvs {
+            mesh.uvs.push((uv[0], uv[1]));
+        }
+        if colors {
+            mesh.colors.push(Color::new(color));
+        }
+    }
+    Ok(())
+}
+
+fn faces(body: &mut Body, element: &Element, mesh: &mut Mesh) -> Result<(), PlyError> {
+    let indices = element.properties.iter().position(|p| {
+        matches!(p, Property::List(..)) && (p.name() == "vertex_indices" || p.name() == "vertex_index")
+    });
+    let Some(indices) = indices else {
+        return corrupt("faces need a \"vertex_indices\" list");
+    };
+
+    for _ in 0..element.count {
+        let mut polygon = Vec::new();
+        for (i, property) in element.properties.iter().enumerate() {
+            match property {
+                Property::Scalar(_, kind) => { body.read(*kind)?; },
+                Property::List(_, length, item) if i == indices => polygon = body.list(*length, *item)?,
+                Property::List(_, length, item) => { body.list(*length, *item)?; },
+            }
+        }
+        if let Some(bad) = polygon.iter().find(|i| **i < 0.0 || i.fract() != 0.0) {
+            return corrupt(format!("invalid vertex index {}", bad));
+        }
+
+        // polygons with fewer than three corners cover no area and are dropped
+        let polygon: Vec<usize> = polygon.iter().map(|i| *i as usize).collect();
+        for k in 1..polygon.len().saturating_sub(1) {
+            mesh.indices.push([polygon[0], polygon[k], polygon[k + 1]]);
+        }
+    }
+    Ok(())
+}
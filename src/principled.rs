@@ -0,0 +1,310 @@
+// A Disney-style principled BSDF: diffuse with retro-reflection and sheen, an anisotropic
+// GGX specular lobe, a GTR1 clearcoat and a rough glass lobe, mixed by the artist parameters.
+// Like the other microfacet models it works in a local frame with the outward normal along +z.
+use crate::vec3::{Vec3};
+use crate::color::{Color, WHITE, BLACK};
+use crate::texture::{Texture};
+use crate::microfacet::{TrowbridgeReitz, RoughDielectric, LocalSample, cos_theta, same_hemisphere, reflect};
+use std::f64::consts::PI;
+use std::sync::{Arc};
+use rand::Rng;
+
+const MIN_ALPHA: f64 = 1e-3;
+
+#[derive(Clone)]
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    specular_tint: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    transmission: f64,
+    anisotropic: f64,
+    ior: f64,
+}
+
+// lobe selection probabilities, in the order diffuse, specular, clearcoat, glass
+struct Lobes {
+    diffuse: f64,
+    specular: f64,
+    clearcoat: f64,
+    glass: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Arc<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            anisotropic: 0.0,
+            ior: 1.5,
+        }
+    }
+
+    pub fn metallic(mut self, metallic: f64) -> Principled {
+        self.metallic = metallic.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn roughness(mut self, roughness: f64) -> Principled {
+        self.roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn specular(mut self, specular: f64) -> Principled {
+        self.specular = specular.max(0.0);
+        self
+    }
+
+    pub fn specular_tint(mut self, tint: f64) -> Principled {
+        self.specular_tint = tint.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn sheen(mut self, sheen: f64) -> Principled {
+        self.sheen = sheen.max(0.0);
+        self
+    }
+
+    pub fn sheen_tint(mut self, tint: f64) -> Principled {
+        self.sheen_tint = tint.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn clearcoat(mut self, clearcoat: f64) -> Principled {
+        self.clearcoat = clearcoat.max(0.0);
+        self
+    }
+
+    pub fn clearcoat_gloss(mut self, gloss: f64) -> Principled {
+        self.clearcoat_gloss = gloss.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn transmission(mut self, transmission: f64) -> Principled {
+        self.transmission = transmission.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn anisotropic(mut self, anisotropic: f64) -> Principled {
+        self.anisotropic = anisotropic.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn ior(mut self, ior: f64) -> Principled {
+        self.ior = ior.max(1.0);
+        self
+    }
+
+    pub fn base_color(&self) -> &Arc<dyn Texture> {
+        &self.base_color
+    }
+
+    fn lobes(&self) -> Lobes {
+        let diffuse = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let glass = (1.0 - self.metallic) * self.transmission;
+        let specular = 1.0 - glass;
+        let clearcoat = 0.25 * self.clearcoat;
+        let total = diffuse + specular + clearcoat + glass;
+        Lobes {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            clearcoat: clearcoat / total,
+            glass: glass / total,
+        }
+    }
+
+    fn specular_distribution(&self) -> TrowbridgeReitz {
+        let aspect = (1.0 - 0.9 * self.anisotropic).sqrt();
+        let alpha = self.roughness * self.roughness;
+        TrowbridgeReitz::new((alpha / aspect).max(MIN_ALPHA), (alpha * aspect).max(MIN_ALPHA))
+    }
+
+    fn glass(&self) -> RoughDielectric {
+        RoughDielectric::new(self.ior, self.roughness.max(MIN_ALPHA.sqrt() * 1.01))
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        (1.0 - self.clearcoat_gloss) * 0.1 + self.clearcoat_gloss * 0.001
+    }
+
+    // reflectance at normal incidence of the specular lobe
+    fn specular_f0(&self, base: &Color) -> Color {
+        let tint = (1.0 - self.specular_tint) * WHITE + self.specular_tint * tint(base);
+        let dielectric = 0.08 * self.specular * tint;
+        (1.0 - self.metallic) * dielectric + self.metallic * *base
+    }
+
+    pub fn sample(&self, wo: &Vec3, base: Color) -> Option<LocalSample> {
+        if cos_theta(wo) == 0.0 {
+            return None;
+        }
+        let lobes = self.lobes();
+        let mut rng = rand::thread_rng();
+        let pick = rng.gen_range(0.0..1.0);
+        let u = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+
+        // opaque lobes live on whichever side the viewer is
+        let side = if wo.z() < 0.0 { -1.0 } else { 1.0 };
+        let wo_up = flip(wo, side);
+
+        let wi = if pick < lobes.diffuse {
+            flip(&cosine_hemisphere(u), side)
+        } else if pick < lobes.diffuse + lobes.specular {
+            let wm = self.specular_distribution().sample_wm(&wo_up, u);
+            flip(&reflect(&wo_up, &wm), side)
+        } else if pick < lobes.diffuse + lobes.specular + lobes.clearcoat {
+            let wm = sample_gtr1(self.clearcoat_alpha(), u);
+            flip(&reflect(&wo_up, &wm), side)
+        } else {
+            self.glass().sample(wo)?.wi
+        };
+
+        let pdf = self.pdf(wo, &wi);
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(LocalSample {
+            wi,
+            weight: self.eval(wo, &wi, base) / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+
+    // bsdf times |cos theta_i|
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3, base: Color) -> Color {
+        let lobes = self.lobes();
+        let mut result = BLACK;
+
+        if lobes.glass > 0.0 {
+            let glass = (1.0 - self.metallic) * self.transmission * self.glass().eval(wo, wi);
+            result = result + if same_hemisphere(wo, wi) { glass } else { glass * base };
+        }
+        if !same_hemisphere(wo, wi) {
+            return result;
+        }
+
+        let side = if wo.z() < 0.0 { -1.0 } else { 1.0 };
+        let wo = flip(wo, side);
+        let wi = flip(wi, side);
+        let cos_o = cos_theta(&wo);
+        let cos_i = cos_theta(&wi);
+        let wm = wo + wi;
+        if cos_o == 0.0 || cos_i == 0.0 || wm.square() == 0.0 {
+            return result;
+        }
+        let wm = wm.unit();
+        let cos_d = wi.dot(&wm);
+
+        if lobes.diffuse > 0.0 {
+            let fo = schlick_weight(cos_o);
+            let fi = schlick_weight(cos_i);
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * fo) * (1.0 + (fd90 - 1.0) * fi);
+            let sheen_color = (1.0 - self.sheen_tint) * WHITE + self.sheen_tint * tint(&base);
+            let sheen = self.sheen * schlick_weight(cos_d) * sheen_color;
+            let diffuse_weight = (1.0 - self.metallic) * (1.0 - self.transmission);
+            result = result + diffuse_weight * cos_i * (base * (retro / PI) + sheen);
+        }
+
+        let specular_distribution = self.specular_distribution();
+        let f0 = self.specular_f0(&base);
+        let fresnel = f0 + (WHITE - f0) * schlick_weight(cos_d);
+        let specular_weight = 1.0 - (1.0 - self.metallic) * self.transmission;
+        let specular = specular_distribution.d(&wm) * specular_distribution.g(&wo, &wi) / (4.0 * cos_o);
+        result = result + specular_weight * specular * fresnel;
+
+        if self.clearcoat > 0.0 {
+            let d = gtr1(cos_theta(&wm), self.clearcoat_alpha());
+            let f = 0.04 + 0.96 * schlick_weight(cos_d);
+            let g = smith_g1(cos_o, 0.25) * smith_g1(cos_i, 0.25);
+            result = result + 0.25 * self.clearcoat * d * f * g / (4.0 * cos_o) * WHITE;
+        }
+
+        result
+    }
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let lobes = self.lobes();
+        let mut pdf = 0.0;
+
+        if lobes.glass > 0.0 {
+            pdf += lobes.glass * self.glass().pdf(wo, wi);
+        }
+        if !same_hemisphere(wo, wi) {
+            return pdf;
+        }
+
+        let side = if wo.z() < 0.0 { -1.0 } else { 1.0 };
+        let wo = flip(wo, side);
+        let wi = flip(wi, side);
+        let wm = wo + wi;
+        if wm.square() == 0.0 {
+            return pdf;
+        }
+        let wm = wm.unit();
+        let jacobian = 1.0 / (4.0 * wo.dot(&wm).abs());
+
+        pdf += lobes.diffuse * cos_theta(&wi).max(0.0) / PI;
+        pdf += lobes.specular * self.specular_distribution().pdf(&wo, &wm) * jacobian;
+        if lobes.clearcoat > 0.0 {
+            let cos_h = cos_theta(&wm);
+            pdf += lobes.clearcoat * gtr1(cos_h, self.clearcoat_alpha()) * cos_h * jacobian;
+        }
+        pdf
+    }
+}
+
+fn flip(w: &Vec3, side: f64) -> Vec3 {
+    Vec3::new([w.x(), w.y(), side * w.z()])
+}
+
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+// base color normalized to unit luminance, so tints change hue but not brightness
+fn tint(base: &Color) -> Color {
+    let luminance = 0.2126 * base.x() + 0.7152 * base.y() + 0.0722 * base.z();
+    if luminance > 0.0 { *base / luminance } else { WHITE }
+}
+
+fn cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let r = u.0.sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new([r * phi.cos(), r * phi.sin(), (1.0 - u.0).max(0.0).sqrt()])
+}
+
+// Berry's distribution, used by the clearcoat
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_h * cos_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+fn sample_gtr1(alpha: f64, u: (f64, f64)) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_h = ((1.0 - a2.powf(1.0 - u.0)) / (1.0 - a2)).max(0.0).sqrt();
+    let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new([sin_h * phi.cos(), sin_h * phi.sin(), cos_h])
+}
+
+fn smith_g1(cos: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let c2 = cos * cos;
+    1.0 / (cos + (a2 + c2 - a2 * c2).sqrt()) * 2.0 * cos
+}
@@ -0,0 +1,94 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::vec3::{Point, Vec3};
+use crate::world::{INF};
+use crate::aabb::{Aabb};
+use std::sync::{Arc};
+use rand::Rng;
+
+// parallelogram spanned by `u` and `v` from corner `q`
+pub struct Quad {
+    q: Point,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f64,
+    area: f64,
+    mat: Arc<dyn Bsdf>,
+}
+
+impl Quad {
+    pub fn new(q: Point, u: Vec3, v: Vec3, m: impl Into<Arc<dyn Bsdf>>) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.unit();
+        Quad {
+            q,
+            u,
+            v,
+            w: n / n.square(),
+            normal,
+            d: normal.dot(&q),
+            area: n.length(),
+            mat: m.into(),
+        }
+    }
+
+    pub fn corner(&self) -> &Point {
+        &self.q
+    }
+
+    pub fn edges(&self) -> (&Vec3, &Vec3) {
+        (&self.u, &self.v)
+    }
+}
+
+impl Hittable for Quad {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = self.normal.dot(ray.direct());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(ray.org())) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        // planar coordinates of the hit point along u and v
+        let position = ray.range(t);
+        let p = position - self.q;
+        let alpha = self.w.dot(&p.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&p));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let front_face = denom < 0.0;
+        let normal = if front_face { self.normal } else { self.normal.reverse() };
+        Some(HitRecord::new(t, position, normal, front_face, (alpha, beta), Arc::clone(&self.mat)))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let q = self.q;
+        Some(Aabb::from_points(&[q, q + self.u, q + self.v, q + self.u + self.v]))
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, time: f64) -> f64 {
+        match self.intersect(&Ray::with_time(*origin, *direction, time), 0.001, INF) {
+            Some(rec) => {
+                let distance_squared = rec.t() * rec.t() * direction.square();
+                let cosine = (direction.dot(&self.normal) / direction.length()).abs();
+                if cosine < 1e-8 { return 0.0; }
+                distance_squared / (cosine * self.area)
+            },
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point, _time: f64) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let p = self.q + rng.gen_range(0.0..1.0) * self.u + rng.gen_range(0.0..1.0) * self.v;
+        p - *origin
+    }
+}
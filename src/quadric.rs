@@ -0,0 +1,229 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::vec3::{Point, Vec3, Onb};
+use crate::aabb::{Aabb};
+use crate::csg::{Solid, Span};
+use crate::roots::{solve_quadratic};
+use std::f64::consts::PI;
+use std::sync::{Arc};
+
+// A surface of revolution around the local z axis whose squared radius is
+// alpha + beta z + gamma z^2, clipped to z0 <= z <= z1 and optionally closed by flat caps.
+// Cylinders, cones, paraboloids and hyperboloids of one sheet are all of this form.
+pub struct Quadric {
+    origin: Point,
+    frame: Onb,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    z0: f64,
+    z1: f64,
+    capped: bool,
+    mat: Arc<dyn Bsdf>,
+}
+
+impl Quadric {
+    // cylinder standing on the disk at `base`, reaching `height` along `axis`
+    pub fn cylinder(
+        base: Point,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        capped: bool,
+        m: impl Into<Arc<dyn Bsdf>>,
+    ) -> Quadric {
+        Quadric::new(base, axis, radius * radius, 0.0, 0.0, (0.0, height), capped, m.into())
+    }
+
+    // cone or frustum, with `top_radius` zero for a pointed cone
+    pub fn cone(
+        base: Point,
+        axis: Vec3,
+        base_radius: f64,
+        top_radius: f64,
+        height: f64,
+        capped: bool,
+        m: impl Into<Arc<dyn Bsdf>>,
+    ) -> Quadric {
+        let slope = (top_radius - base_radius) / height;
+        Quadric::new(
+            base,
+            axis,
+            base_radius * base_radius,
+            2.0 * base_radius * slope,
+            slope * slope,
+            (0.0, height),
+            capped,
+            m.into(),
+        )
+    }
+
+    // bowl with its vertex at `base`, opening to `radius` at `height`
+    pub fn paraboloid(
+        base: Point,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        capped: bool,
+        m: impl Into<Arc<dyn Bsdf>>,
+    ) -> Quadric {
+        Quadric::new(base, axis, 0.0, radius * radius / height, 0.0, (0.0, height), capped, m.into())
+    }
+
+    // one sheet hyperboloid centered on `center`, narrowest in the middle and `end_radius` wide at both ends
+    pub fn hyperboloid(
+        center: Point,
+        axis: Vec3,
+        waist_radius: f64,
+        end_radius: f64,
+        height: f64,
+        capped: bool,
+        m: impl Into<Arc<dyn Bsdf>>,
+    ) -> Quadric {
+        let half = height / 2.0;
+        let gamma = (end_radius * end_radius - waist_radius * waist_radius) / (half * half);
+        Quadric::new(
+            center,
+            axis,
+            waist_radius * waist_radius,
+            0.0,
+            gamma,
+            (-half, half),
+            capped,
+            m.into(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        origin: Point,
+        axis: Vec3,
+        alpha: f64,
+        beta: f64,
+        gamma: f64,
+        range: (f64, f64),
+        capped: bool,
+        mat: Arc<dyn Bsdf>,
+    ) -> Quadric {
+        Quadric {
+            origin,
+            frame: Onb::new(&axis.unit()),
+            alpha,
+            beta,
+            gamma,
+            z0: range.0.min(range.1),
+            z1: range.0.max(range.1),
+            capped,
+            mat,
+        }
+    }
+
+    fn radius2(&self, z: f64) -> f64 {
+        (self.alpha + self.beta * z + self.gamma * z * z).max(0.0)
+    }
+
+    // every crossing of the ray's line with the surface in local coordinates, sorted by t:
+    // (t, local outward normal, uv)
+    fn crossings(&self, origin: &Vec3, direction: &Vec3) -> Vec<(f64, Vec3, (f64, f64))> {
+        let mut found = Vec::new();
+
+        let (ox, oy, oz) = (origin.x(), origin.y(), origin.z());
+        let (dx, dy, dz) = (direction.x(), direction.y(), direction.z());
+        let a = dx * dx + dy * dy - self.gamma * dz * dz;
+        let b = 2.0 * (ox * dx + oy * dy) - self.beta * dz - 2.0 * self.gamma * oz * dz;
+        let c = ox * ox + oy * oy - self.alpha - self.beta * oz - self.gamma * oz * oz;
+        let a = if a.abs() < 1e-12 * b.abs() { 0.0 } else { a };
+
+        if let Some((t1, t2)) = solve_quadratic(a, b, c) {
+            // a linear equation has a single root, reported twice
+            let roots = if a == 0.0 { vec![t1] } else { vec![t1, t2] };
+            for t in roots {
+                let p = *origin + t * *direction;
+                if p.z() < self.z0 || p.z() > self.z1 {
+                    continue;
+                }
+                // gradient of x^2 + y^2 - r^2(z), pointing away from the axis
+                let normal = Vec3::new([2.0 * p.x(), 2.0 * p.y(), -(self.beta + 2.0 * self.gamma * p.z())]);
+                if normal.square() == 0.0 {
+                    continue;
+                }
+                let uv = (angle(&p), (p.z() - self.z0) / (self.z1 - self.z0));
+                found.push((t, normal.unit(), uv));
+            }
+        }
+
+        if self.capped && dz != 0.0 {
+            for (z, sign) in [(self.z0, -1.0), (self.z1, 1.0)] {
+                let r2 = self.radius2(z);
+                let t = (z - oz) / dz;
+                let p = *origin + t * *direction;
+                let d2 = p.x() * p.x() + p.y() * p.y();
+                if r2 == 0.0 || d2 > r2 {
+                    continue;
+                }
+                found.push((t, Vec3::new([0.0, 0.0, sign]), (angle(&p), (d2 / r2).sqrt())));
+            }
+        }
+
+        found.sort_by(|x, y| x.0.total_cmp(&y.0));
+        found
+    }
+
+    fn record(&self, ray: &Ray, crossing: (f64, Vec3, (f64, f64))) -> HitRecord {
+        let (t, local_normal, uv) = crossing;
+        let outward = self.frame.to_world(&local_normal);
+        let front_face = ray.direct().dot(&outward) < 0.0;
+        let normal = if front_face { outward } else { outward.reverse() };
+        HitRecord::new(t, ray.range(t), normal, front_face, uv, Arc::clone(&self.mat))
+    }
+
+    fn local_ray(&self, ray: &Ray) -> (Vec3, Vec3) {
+        (self.frame.to_local(&(*ray.org() - self.origin)), self.frame.to_local(ray.direct()))
+    }
+}
+
+fn angle(p: &Vec3) -> f64 {
+    p.y().atan2(p.x()).rem_euclid(2.0 * PI) / (2.0 * PI)
+}
+
+impl Hittable for Quadric {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (origin, direction) = self.local_ray(ray);
+        let crossing = self.crossings(&origin, &direction).into_iter()
+            .find(|(t, _, _)| t_min <= *t && *t <= t_max)?;
+        Some(self.record(ray, crossing))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut r2 = self.radius2(self.z0).max(self.radius2(self.z1));
+        if self.gamma < 0.0 {
+            let vertex = -self.beta / (2.0 * self.gamma);
+            if self.z0 < vertex && vertex < self.z1 {
+                r2 = r2.max(self.radius2(vertex));
+            }
+        }
+        let r = r2.sqrt();
+
+        let local = Aabb::new(Point::new([-r, -r, self.z0]), Point::new([r, r, self.z1]));
+        let corners: Vec<Point> = local.corners().iter()
+            .map(|c| self.origin + self.frame.to_world(c))
+            .collect();
+        Some(Aabb::from_points(&corners))
+    }
+}
+
+// only a capped quadric encloses a volume; open ones have no inside and yield no spans
+impl Solid for Quadric {
+    fn intersect_all(&self, ray: &Ray) -> Vec<Span> {
+        if !self.capped {
+            return Vec::new();
+        }
+        let (origin, direction) = self.local_ray(ray);
+        let mut crossings = self.crossings(&origin, &direction).into_iter();
+        let mut spans = Vec::new();
+        while let (Some(enter), Some(exit)) = (crossings.next(), crossings.next()) {
+            spans.push(Span { enter: self.record(ray, enter), exit: self.record(ray, exit) });
+        }
+        spans
+    }
+}
@@ -1,16 +1,25 @@
 use crate::vec3::{Point, Vec3};
-use crate::material::{Material};
+use crate::material::{Bsdf};
+use crate::aabb::{Aabb};
+use std::sync::{Arc};
 
 pub struct Ray {
     origin: Point,
-    direction: Vec3
+    direction: Vec3,
+    time: f64,
 }
 
 impl Ray {
     pub fn new(org: Point, direct: Vec3) -> Ray {
+        Ray::with_time(org, direct, 0.0)
+    }
+
+    // `time` runs over the camera shutter, nominally from 0 to 1
+    pub fn with_time(org: Point, direct: Vec3, time: f64) -> Ray {
         Ray {
-            origin: org, 
-            direction: direct
+            origin: org,
+            direction: direct,
+            time,
         }
     }
 
@@ -22,6 +31,10 @@ impl Ray {
         &self.direction
     }
 
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
     pub fn range(&self, pos: f64) -> Point {
         self.origin + self.direction * pos
     }
@@ -37,26 +50,51 @@ impl Ray {
 }
 
 
-#[derive(PartialEq)]
 pub struct HitRecord {
     t: f64,
     pos: Point,
     normal: Vec3,
+    // the normal of the surface itself, which interpolated shading normals can differ from
+    geometric_normal: Vec3,
     front_face: bool,
-    mat: Material,
+    u: f64,
+    v: f64,
+    mat: Arc<dyn Bsdf>,
 }
 
 impl HitRecord {
-    pub fn new(t: f64, p: Point, n: Vec3, front: bool, m: Material) -> HitRecord {
+    pub fn new(t: f64, p: Point, n: Vec3, front: bool, uv: (f64, f64), m: Arc<dyn Bsdf>) -> HitRecord {
         HitRecord {
-            t: t,
+            t,
             pos: p,
             normal: n,
+            geometric_normal: n,
             front_face: front,
+            u: uv.0,
+            v: uv.1,
             mat: m,
         } 
     }
 
+    // the same hit seen from another space, as used by instances
+    pub fn with_geometry(mut self, p: Point, n: Vec3) -> HitRecord {
+        self.pos = p;
+        self.normal = n;
+        self
+    }
+
+    // for surfaces whose shading normal is not the true one; it faces the ray like the normal
+    pub fn with_geometric_normal(mut self, n: Vec3) -> HitRecord {
+        self.geometric_normal = n;
+        self
+    }
+
+    // whether the ray arrives from outside; the normal keeps facing the ray either way
+    pub fn with_front_face(mut self, front: bool) -> HitRecord {
+        self.front_face = front;
+        self
+    }
+
     pub fn t(&self) -> f64 {
         self.t
     }
@@ -69,16 +107,49 @@ impl HitRecord {
         &self.normal
     }
 
+    pub fn geometric_normal(&self) -> &Vec3 {
+        &self.geometric_normal
+    }
+
     pub fn front_face(&self) -> bool {
         self.front_face
     }
 
-    pub fn mat(&self) -> &Material {
-        &self.mat
+    pub fn u(&self) -> f64 {
+        self.u
+    }
+
+    pub fn v(&self) -> f64 {
+        self.v
+    }
+
+    pub fn mat(&self) -> &dyn Bsdf {
+        &*self.mat
     }
 }
 
 pub trait Hittable: Sync + Send {
     fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    // None means unbounded, like an infinite plane; moving shapes bound their whole motion
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // Fraction of light that makes it between t_min and t_max, asked by shadow rays. Surfaces
+    // block everything they hit; media can answer with a smoother estimate than a coin flip.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.intersect(ray, t_min, t_max).is_some() { 0.0 } else { 1.0 }
+    }
+
+    // solid angle density of `random` picking `direction` from `origin` at `time`; shapes
+    // that cannot be sampled as area lights keep the defaults
+    fn pdf_value(&self, _origin: &Point, _direction: &Vec3, _time: f64) -> f64 {
+        0.0
+    }
+
+    fn random(&self, _origin: &Point, _time: f64) -> Vec3 {
+        Vec3::new([1.0, 0.0, 0.0])
+    }
 }
 
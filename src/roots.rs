@@ -0,0 +1,133 @@
+// Real roots of low degree polynomials, returned in ascending order.
+
+// a x^2 + b x + c = 0, avoiding the cancellation of the textbook formula
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        let x = -c / b;
+        return Some((x, x));
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    let x1 = q / a;
+    let x2 = if q != 0.0 { c / q } else { x1 };
+    Some(if x1 <= x2 { (x1, x2) } else { (x2, x1) })
+}
+
+// x^3 + a x^2 + b x + c = 0
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let shift = a / 3.0;
+
+    let mut roots = if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let s = -2.0 * q.sqrt();
+        vec![
+            s * (theta / 3.0).cos() - shift,
+            s * ((theta + 2.0 * std::f64::consts::PI) / 3.0).cos() - shift,
+            s * ((theta - 2.0 * std::f64::consts::PI) / 3.0).cos() - shift,
+        ]
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let big_b = if big_a != 0.0 { q / big_a } else { 0.0 };
+        vec![big_a + big_b - shift]
+    };
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+// c4 x^4 + c3 x^3 + c2 x^2 + c1 x + c0 = 0 by Ferrari's method; every root is then
+// polished with Newton steps on the original polynomial, which matters for grazing rays
+// where the resolvent loses most of its precision
+pub fn solve_quartic(c4: f64, c3: f64, c2: f64, c1: f64, c0: f64) -> Vec<f64> {
+    if c4 == 0.0 {
+        return Vec::new();
+    }
+    let a = c3 / c4;
+    let b = c2 / c4;
+    let c = c1 / c4;
+    let d = c0 / c4;
+
+    // depressed quartic y^4 + p y^2 + q y + r with x = y - a / 4
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+    let scale = 1.0 + p.abs() + r.abs();
+
+    let mut ys = Vec::with_capacity(4);
+    if q.abs() < 1e-12 * scale {
+        // biquadratic in y^2
+        for z in lenient_quadratic(1.0, p, r) {
+            if z >= 0.0 {
+                ys.push(z.sqrt());
+                ys.push(-z.sqrt());
+            } else if z > -1e-9 * scale {
+                ys.push(0.0);
+            }
+        }
+    } else {
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        let k = q / (2.0 * s);
+        ys.extend(lenient_quadratic(1.0, -s, p / 2.0 + m + k));
+        ys.extend(lenient_quadratic(1.0, s, p / 2.0 + m - k));
+    }
+
+    let mut roots: Vec<f64> = ys.into_iter()
+        .map(|y| polish(y - a / 4.0, a, b, c, d))
+        .filter(|x| x.is_finite())
+        .collect();
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+// like `solve_quadratic`, but a slightly negative discriminant from rounding counts as a double root
+fn lenient_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let mut discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        if discriminant > -1e-10 * (b * b + (4.0 * a * c).abs()) {
+            discriminant = 0.0;
+        } else {
+            return Vec::new();
+        }
+    }
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    if q == 0.0 {
+        return vec![0.0, 0.0];
+    }
+    vec![q / a, c / q]
+}
+
+fn polish(x: f64, a: f64, b: f64, c: f64, d: f64) -> f64 {
+    let value = |x: f64| (((x + a) * x + b) * x + c) * x + d;
+    let mut x = x;
+    let mut f = value(x);
+    for _ in 0..4 {
+        let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+        if f == 0.0 || df == 0.0 {
+            break;
+        }
+        // near a double root the derivative is mostly rounding noise, so only keep steps that help
+        let next = x - f / df;
+        let f_next = value(next);
+        if f_next.abs() >= f.abs() {
+            break;
+        }
+        x = next;
+        f = f_next;
+    }
+    x
+}
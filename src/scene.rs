@@ -0,0 +1,485 @@
+// A plain text description of everything needed to render an image: camera, render settings,
+// named textures and materials, shapes and the background. `Scene::parse` reads the format,
+// `Display` writes it back and `build` turns the description into a `World` and a `Camera`.
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color};
+use crate::world::{World, Background};
+use crate::camera::{Camera};
+use crate::ray::{Hittable};
+use crate::material::{Material, Bsdf};
+use crate::microfacet::{Conductor, RoughDielectric};
+use crate::principled::{Principled};
+use crate::texture::{Texture, NoiseTexture, Marble, Wood, Clouds, Checker};
+use crate::image::{ImageTexture};
+use crate::sphere::{Sphere};
+use crate::quad::{Quad};
+use crate::disk::{Disk};
+use crate::plane::{Plane};
+use crate::cuboid::{Cuboid};
+use crate::quadric::{Quadric};
+use crate::torus::{Torus};
+use crate::instance::{Instance};
+use crate::medium::{ConstantMedium};
+use crate::bvh::{Bvh};
+use crate::transform::{Transform};
+use std::collections::{HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path};
+use std::sync::{Arc};
+
+mod parse;
+mod write;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse { line: usize, column: usize, message: String },
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "could not read scene: {}", e),
+            SceneError::Parse { line, column, message } => {
+                write!(f, "line {}, column {}: {}", line, column, message)
+            },
+            SceneError::Invalid(what) => write!(f, "invalid scene: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> SceneError {
+        SceneError::Io(e)
+    }
+}
+
+// the camera together with the render settings, mirroring the builder methods of `Camera`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraDesc {
+    pub look_from: Point,
+    pub look_at: Point,
+    pub up: Vec3,
+    pub fov: f64,
+    pub defocus_angle: f64,
+    pub focus_dist: f64,
+    pub shutter: (f64, f64),
+    pub width: u32,
+    pub height: u32,
+    pub samples: u16,
+    pub max_depth: u8,
+}
+
+impl CameraDesc {
+    // the defaults of `Camera::new`
+    pub fn new(look_from: Point, look_at: Point) -> CameraDesc {
+        CameraDesc::from(&Camera::new(look_from, look_at))
+    }
+
+    pub fn build(&self) -> Camera {
+        Camera::new(self.look_from, self.look_at)
+            .up(self.up)
+            .v_fov(self.fov)
+            .resolution(self.width, self.height)
+            .defocus(self.defocus_angle, self.focus_dist)
+            .samples(self.samples)
+            .max_depth(self.max_depth)
+            .shutter(self.shutter.0, self.shutter.1)
+    }
+}
+
+impl From<&Camera> for CameraDesc {
+    fn from(camera: &Camera) -> CameraDesc {
+        CameraDesc {
+            look_from: *camera.look_from(),
+            look_at: *camera.look_at(),
+            up: *camera.vup(),
+            fov: camera.fov(),
+            defocus_angle: camera.defocus_angle(),
+            focus_dist: camera.focus_dist(),
+            shutter: camera.shutter_interval(),
+            width: camera.width(),
+            height: camera.height(),
+            samples: camera.sample_num(),
+            max_depth: camera.reflect_depth(),
+        }
+    }
+}
+
+// Marble, Wood, Clouds and Checker fall back to their built-in palettes without `colors`;
+// image paths are relative to the scene file
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureDesc {
+    Noise { seed: u64, scale: f64 },
+    Marble { seed: u64, scale: f64, colors: Option<(Color, Color)> },
+    Wood { seed: u64, scale: f64, rings: f64, colors: Option<(Color, Color)> },
+    Clouds { seed: u64, scale: f64, cover: f64, colors: Option<(Color, Color)> },
+    Checker { scale: f64, colors: Option<(Color, Color)> },
+    Image { path: String },
+}
+
+// a color input is either a constant or the name of a texture
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorSource {
+    Value(Color),
+    Texture(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrincipledDesc {
+    pub base_color: ColorSource,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub anisotropic: f64,
+    pub ior: f64,
+}
+
+impl PrincipledDesc {
+    // the defaults of `Principled::new`
+    pub fn new(base_color: ColorSource) -> PrincipledDesc {
+        PrincipledDesc {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            anisotropic: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+// Conductor roughness is given along u and v, equal for an isotropic surface
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaterialDesc {
+    Lambertian { albedo: ColorSource },
+    Metal { albedo: ColorSource, fuzz: f64 },
+    Dielectric { ior: f64 },
+    Conductor { eta: Color, k: Color, roughness: (f64, f64) },
+    RoughDielectric { ior: f64, roughness: f64 },
+    Principled(PrincipledDesc),
+    Light { emit: ColorSource },
+    Isotropic { albedo: ColorSource },
+    HenyeyGreenstein { albedo: ColorSource, g: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShapeDesc {
+    // `to` makes the sphere move from `center` at time 0 to `to` at time 1
+    Sphere { center: Point, radius: f64, to: Option<Point> },
+    Quad { corner: Point, u: Vec3, v: Vec3 },
+    Disk { center: Point, normal: Vec3, radius: f64 },
+    Plane { point: Point, normal: Vec3 },
+    Box { min: Point, max: Point },
+    Cylinder { base: Point, axis: Vec3, radius: f64, height: f64, capped: bool },
+    Cone { base: Point, axis: Vec3, radius: f64, top_radius: f64, height: f64, capped: bool },
+    Torus { center: Point, axis: Vec3, major: f64, minor: f64 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TransformDesc {
+    Translate(Vec3),
+    // axis and angle in degrees
+    Rotate(Vec3, f64),
+    Scale(Vec3),
+}
+
+// Transforms apply in the order listed. With `medium` the shape only bounds a constant
+// density volume and `material` is its phase function; `light` registers the object
+// for light sampling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectDesc {
+    pub shape: ShapeDesc,
+    pub material: String,
+    pub transforms: Vec<TransformDesc>,
+    pub light: bool,
+    pub medium: Option<f64>,
+}
+
+impl ObjectDesc {
+    pub fn new(shape: ShapeDesc, material: &str) -> ObjectDesc {
+        ObjectDesc {
+            shape,
+            material: material.to_string(),
+            transforms: Vec::new(),
+            light: false,
+            medium: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub camera: CameraDesc,
+    pub background: Background,
+    pub textures: Vec<(String, TextureDesc)>,
+    pub materials: Vec<(String, MaterialDesc)>,
+    pub objects: Vec<ObjectDesc>,
+}
+
+impl Scene {
+    pub fn new(camera: CameraDesc) -> Scene {
+        Scene {
+            camera,
+            background: Background::default(),
+            textures: Vec::new(),
+            materials: Vec::new(),
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse::parse(source)
+    }
+
+    // Image paths are kept as written; `build_in` resolves them against the directory of
+    // the scene file, so saving a loaded scene writes the same paths back.
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        Scene::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    // relative image paths are looked up in the working directory
+    pub fn build(&self) -> Result<(World, Camera), SceneError> {
+        self.build_in("")
+    }
+
+    // relative image paths are looked up in `directory`
+    pub fn build_in(&self, directory: impl AsRef<Path>) -> Result<(World, Camera), SceneError> {
+        let mut textures: HashMap<&str, Arc<dyn Texture>> = HashMap::new();
+        for (name, desc) in &self.textures {
+            textures.insert(name, build_texture(name, desc, directory.as_ref())?);
+        }
+
+        let mut materials: HashMap<&str, Arc<dyn Bsdf>> = HashMap::new();
+        for (name, desc) in &self.materials {
+            let material = build_material(desc, &textures)
+                .map_err(|e| SceneError::Invalid(format!("material `{}`: {}", name, e)))?;
+            materials.insert(name, material.into());
+        }
+
+        let mut world = World::new();
+        world.set_background(self.background);
+        for (_, desc) in &self.textures {
+            if let TextureDesc::Image { path } = desc {
+                world.add_source(directory.as_ref().join(path));
+            }
+        }
+        let mut objects: Vec<Arc<dyn Hittable>> = Vec::new();
+        for desc in &self.objects {
+            let material = materials.get(desc.material.as_str())
+                .ok_or_else(|| SceneError::Invalid(format!("unknown material `{}`", desc.material)))?;
+            let object = build_object(desc, material)?;
+            if desc.light {
+                world.add_light(object);
+            } else {
+                objects.push(object);
+            }
+        }
+        if !objects.is_empty() {
+            world.add(Arc::new(Bvh::new(objects)));
+        }
+
+        Ok((world, self.camera.build()))
+    }
+}
+
+fn build_texture(name: &str, desc: &TextureDesc, directory: &Path) -> Result<Arc<dyn Texture>, SceneError> {
+    Ok(match desc {
+        TextureDesc::Noise { seed, scale } => Arc::new(NoiseTexture::new(*seed, *scale)),
+        TextureDesc::Marble { seed, scale, colors } => match colors {
+            Some((vein, base)) => Arc::new(Marble::colored(*seed, *scale, *vein, *base)),
+            None => Arc::new(Marble::new(*seed, *scale)),
+        },
+        TextureDesc::Wood { seed, scale, rings, colors } => match colors {
+            Some((light, dark)) => Arc::new(Wood::colored(*seed, *scale, *rings, *light, *dark)),
+            None => Arc::new(Wood::new(*seed, *scale, *rings)),
+        },
+        TextureDesc::Clouds { seed, scale, cover, colors } => match colors {
+            Some((sky, cloud)) => Arc::new(Clouds::colored(*seed, *scale, *cover, *sky, *cloud)),
+            None => Arc::new(Clouds::new(*seed, *scale, *cover)),
+        },
+        TextureDesc::Checker { scale, colors } => match colors {
+            Some((even, odd)) => Arc::new(Checker::colored(*scale, *even, *odd)),
+            None => Arc::new(Checker::new(*scale)),
+        },
+        TextureDesc::Image { path } => match ImageTexture::load(directory.join(path)) {
+            Ok(texture) => Arc::new(texture),
+            Err(e) => return Err(SceneError::Invalid(format!("texture `{}`: {}", name, e))),
+        },
+    })
+}
+
+fn color_source(
+    source: &ColorSource,
+    textures: &HashMap<&str, Arc<dyn Texture>>,
+) -> Result<Arc<dyn Texture>, String> {
+    match source {
+        ColorSource::Value(color) => Ok(Arc::new(*color)),
+        ColorSource::Texture(name) => textures.get(name.as_str())
+            .cloned()
+            .ok_or_else(|| format!("unknown texture `{}`", name)),
+    }
+}
+
+fn build_material(
+    desc: &MaterialDesc,
+    textures: &HashMap<&str, Arc<dyn Texture>>,
+) -> Result<Material, String> {
+    Ok(match desc {
+        MaterialDesc::Lambertian { albedo } => Material::Lambertian(color_source(albedo, textures)?),
+        MaterialDesc::Metal { albedo, fuzz } => {
+            Material::Metal(color_source(albedo, textures)?, Arc::new(*fuzz))
+        },
+        MaterialDesc::Dielectric { ior } => Material::Dielectric(*ior),
+        MaterialDesc::Conductor { eta, k, roughness } => {
+            Material::Conductor(Conductor::anisotropic(*eta, *k, roughness.0, roughness.1))
+        },
+        MaterialDesc::RoughDielectric { ior, roughness } => {
+            Material::RoughDielectric(RoughDielectric::new(*ior, *roughness))
+        },
+        MaterialDesc::Principled(p) => Material::Principled(
+            Principled::new(color_source(&p.base_color, textures)?)
+                .metallic(p.metallic)
+                .roughness(p.roughness)
+                .specular(p.specular)
+                .specular_tint(p.specular_tint)
+                .sheen(p.sheen)
+                .sheen_tint(p.sheen_tint)
+                .clearcoat(p.clearcoat)
+                .clearcoat_gloss(p.clearcoat_gloss)
+                .transmission(p.transmission)
+                .anisotropic(p.anisotropic)
+                .ior(p.ior),
+        ),
+        MaterialDesc::Light { emit } => Material::DiffuseLight(color_source(emit, textures)?),
+        MaterialDesc::Isotropic { albedo } => Material::Isotropic(color_source(albedo, textures)?),
+        MaterialDesc::HenyeyGreenstein { albedo, g } => {
+            Material::HenyeyGreenstein(color_source(albedo, textures)?, *g)
+        },
+    })
+}
+
+fn build_shape(shape: &ShapeDesc, m: &Arc<dyn Bsdf>) -> Arc<dyn Hittable> {
+    let m = Arc::clone(m);
+    match *shape {
+        ShapeDesc::Sphere { center, radius, to } => match to {
+            Some(end) => Arc::new(Sphere::moving(center, end, radius, m)),
+            None => Arc::new(Sphere::new(center, radius, m)),
+        },
+        ShapeDesc::Quad { corner, u, v } => Arc::new(Quad::new(corner, u, v, m)),
+        ShapeDesc::Disk { center, normal, radius } => Arc::new(Disk::new(center, normal, radius, m)),
+        ShapeDesc::Plane { point, normal } => Arc::new(Plane::new(point, normal, m)),
+        ShapeDesc::Box { min, max } => Arc::new(Cuboid::new(min, max, m)),
+        ShapeDesc::Cylinder { base, axis, radius, height, capped } => {
+            Arc::new(Quadric::cylinder(base, axis, radius, height, capped, m))
+        },
+        ShapeDesc::Cone { base, axis, radius, top_radius, height, capped } => {
+            Arc::new(Quadric::cone(base, axis, radius, top_radius, height, capped, m))
+        },
+        ShapeDesc::Torus { center, axis, major, minor } => {
+            Arc::new(Torus::new(center, axis, major, minor, m))
+        },
+    }
+}
+
+fn build_object(desc: &ObjectDesc, material: &Arc<dyn Bsdf>) -> Result<Arc<dyn Hittable>, SceneError> {
+    let mut object = build_shape(&desc.shape, material);
+    let flat = |v: &Vec3| v.x() == 0.0 || v.y() == 0.0 || v.z() == 0.0;
+    if desc.transforms.iter().any(|step| matches!(step, TransformDesc::Scale(f) if flat(f))) {
+        return Err(SceneError::Invalid("scale factors must be non-zero".to_string()));
+    }
+    if let Some(density) = desc.medium {
+        object = Arc::new(ConstantMedium::new(object, density, Arc::clone(material)));
+    }
+    if !desc.transforms.is_empty() {
+        let transform = desc.transforms.iter().fold(Transform::identity(), |t, step| {
+            t.then(&match *step {
+                TransformDesc::Translate(offset) => Transform::translate(offset),
+                TransformDesc::Rotate(axis, degrees) => Transform::rotate(axis, degrees),
+                TransformDesc::Scale(factors) => Transform::scale(factors),
+            })
+        });
+        object = Arc::new(Instance::new(object, transform));
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{BUILT_IN_SCENES};
+    use std::path::{PathBuf};
+
+    // saves `scene` into `directory`, loads it back and checks nothing changed, twice over so
+    // a path rewritten on load would show up
+    fn assert_round_trip(scene: &Scene, directory: &Path, name: &str) {
+        fs::create_dir_all(directory).unwrap();
+        let path = directory.join(name);
+        scene.save(&path).unwrap();
+        let loaded = Scene::load(&path).unwrap();
+        assert_eq!(&loaded, scene, "{} changed on the first round trip", name);
+        loaded.save(&path).unwrap();
+        assert_eq!(&Scene::load(&path).unwrap(), scene, "{} changed on the second round trip", name);
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn scratch_directory(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ray-tracing-{}-{}", name, std::process::id())).join("d")
+    }
+
+    #[test]
+    fn cornell_file_round_trips() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/cornell.scene");
+        let scene = Scene::load(&path).unwrap();
+        assert_round_trip(&scene, &scratch_directory("cornell"), "cornell.scene");
+    }
+
+    #[test]
+    fn built_in_scenes_round_trip() {
+        let directory = scratch_directory("built-in");
+        // seeds go straight into textures, so try one that needs all 64 bits
+        for built_in in BUILT_IN_SCENES {
+            for seed in [7, u64::MAX - 2] {
+                let scene = built_in.build(seed);
+                assert_round_trip(&scene, &directory, &format!("{}.scene", built_in.name));
+            }
+        }
+    }
+
+    #[test]
+    fn image_paths_are_kept_as_written() {
+        let mut scene = Scene::new(CameraDesc::new(Point::new([0.0, 0.0, 1.0]), Point::new([0.0, 0.0, 0.0])));
+        for (name, path) in [("relative", "earthmap.png"), ("nested", "maps/earth map.png"), ("parent", "../earthmap.png")] {
+            scene.textures.push((name.to_string(), TextureDesc::Image { path: path.to_string() }));
+        }
+        assert_round_trip(&scene, &scratch_directory("images"), "images.scene");
+    }
+}
@@ -0,0 +1,744 @@
+// Hand-written reader for the scene format. A file is a sequence of blocks
+//
+//     keyword [arguments] {
+//         property value...
+//     }
+//
+// with one property per line (or separated by `;`) and `#` starting a comment.
+use super::{
+    Scene, SceneError, CameraDesc, TextureDesc, ColorSource, PrincipledDesc, MaterialDesc,
+    ShapeDesc, TransformDesc, ObjectDesc,
+};
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color};
+use crate::world::{Background};
+use crate::microfacet::{measured_metal};
+use std::collections::{HashSet};
+use std::iter::{Peekable};
+use std::str::{Chars};
+
+// every whole number up to here is exactly representable as an f64
+const MAX_EXACT: f64 = 9007199254740992.0;
+
+#[derive(Debug, Copy, Clone)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+fn error<T>(at: Pos, message: impl Into<String>) -> Result<T, SceneError> {
+    Err(SceneError::Parse { line: at.line, column: at.column, message: message.into() })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    // the value and the text it was read from, which integers are taken from exactly
+    Number(f64, String),
+    Str(String),
+    Open,
+    Close,
+    // a line break or `;`
+    End,
+    Eof,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(w) => format!("`{}`", w),
+            Token::Number(_, text) => format!("number {}", text),
+            Token::Str(s) => format!("string \"{}\"", s),
+            Token::Open => "`{`".to_string(),
+            Token::Close => "`}`".to_string(),
+            Token::End => "end of line".to_string(),
+            Token::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Lexer<'a> {
+        Lexer { chars: source.chars().peekable(), line: 1, column: 1 }
+    }
+
+    fn pos(&self) -> Pos {
+        Pos { line: self.line, column: self.column }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    // consumes characters while `accept` holds
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !accept(c) {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+        text
+    }
+
+    fn next_token(&mut self) -> Result<(Pos, Token), SceneError> {
+        loop {
+            match self.chars.peek() {
+                Some('#') => {
+                    self.take_while(|c| c != '\n');
+                },
+                Some(&c) if c != '\n' && c.is_whitespace() => {
+                    self.bump();
+                },
+                _ => break,
+            }
+        }
+
+        let at = self.pos();
+        let c = match self.chars.peek() {
+            Some(&c) => c,
+            None => return Ok((at, Token::Eof)),
+        };
+        let token = match c {
+            '\n' | ';' => {
+                self.bump();
+                Token::End
+            },
+            '{' => {
+                self.bump();
+                Token::Open
+            },
+            '}' => {
+                self.bump();
+                Token::Close
+            },
+            '"' => {
+                self.bump();
+                let mut text = String::new();
+                loop {
+                    let escape_at = self.pos();
+                    match self.bump() {
+                        None | Some('\n') => return error(at, "unterminated string"),
+                        Some('"') => break,
+                        Some('\\') => match self.bump() {
+                            Some(c @ ('"' | '\\')) => text.push(c),
+                            Some('n') => text.push('\n'),
+                            _ => return error(escape_at, "unknown escape sequence"),
+                        },
+                        Some(c) => text.push(c),
+                    }
+                }
+                Token::Str(text)
+            },
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let text = self.take_while(|c| c.is_ascii_alphanumeric() || "+-._".contains(c));
+                match text.parse::<f64>() {
+                    Ok(n) if n.is_finite() => Token::Number(n, text),
+                    _ => return error(at, format!("invalid number `{}`", text)),
+                }
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                Token::Word(self.take_while(|c| c.is_alphanumeric() || "_-.".contains(c)))
+            },
+            c => return error(at, format!("unexpected character `{}`", c)),
+        };
+        Ok((at, token))
+    }
+}
+
+struct Property {
+    at: Pos,
+    name: String,
+    values: Vec<(Pos, Token)>,
+}
+
+impl Property {
+    fn count(&self, n: usize) -> Result<(), SceneError> {
+        if self.values.len() > n {
+            return error(self.values[n].0, format!("unexpected value after `{}`", self.name));
+        }
+        if self.values.len() < n {
+            let plural = if n == 1 { "" } else { "s" };
+            return error(self.at, format!("`{}` takes {} value{}", self.name, n, plural));
+        }
+        Ok(())
+    }
+
+    fn floats(&self) -> Result<Vec<f64>, SceneError> {
+        self.values.iter().map(|(at, token)| match token {
+            Token::Number(n, _) => Ok(*n),
+            other => error(*at, format!("expected a number, found {}", other.describe())),
+        }).collect()
+    }
+
+    fn number(&self) -> Result<f64, SceneError> {
+        self.count(1)?;
+        Ok(self.floats()?[0])
+    }
+
+    fn positive(&self) -> Result<f64, SceneError> {
+        let n = self.number()?;
+        if n <= 0.0 {
+            return error(self.values[0].0, format!("`{}` must be positive", self.name));
+        }
+        Ok(n)
+    }
+
+    // read from the digits rather than the f64, so seeds keep all 64 bits; forms like `1e3`
+    // are still accepted while they are exact
+    fn integer(&self, min: u64, max: u64) -> Result<u64, SceneError> {
+        let n = self.number()?;
+        let exact = match &self.values[0].1 {
+            Token::Number(_, text) => text.strip_prefix('+').unwrap_or(text).parse::<u64>().ok(),
+            _ => None,
+        };
+        let n = exact.or((n.fract() == 0.0 && (0.0..=MAX_EXACT).contains(&n)).then_some(n as u64));
+        match n {
+            Some(n) if (min..=max).contains(&n) => Ok(n),
+            _ => {
+                let message = format!("`{}` must be a whole number from {} to {}", self.name, min, max);
+                error(self.values[0].0, message)
+            },
+        }
+    }
+
+    fn vec3(&self) -> Result<Vec3, SceneError> {
+        self.count(3)?;
+        let v = self.floats()?;
+        Ok(Vec3::new([v[0], v[1], v[2]]))
+    }
+
+    // a vector used as a direction, which must not vanish
+    fn direction(&self) -> Result<Vec3, SceneError> {
+        let v = self.vec3()?;
+        if v.square() == 0.0 {
+            return error(self.at, format!("`{}` must not be a zero vector", self.name));
+        }
+        Ok(v)
+    }
+
+    fn pair(&self) -> Result<(f64, f64), SceneError> {
+        self.count(2)?;
+        let v = self.floats()?;
+        Ok((v[0], v[1]))
+    }
+
+    fn color_pair(&self) -> Result<(Color, Color), SceneError> {
+        self.count(6)?;
+        let v = self.floats()?;
+        Ok((Color::new([v[0], v[1], v[2]]), Color::new([v[3], v[4], v[5]])))
+    }
+
+    fn flag(&self) -> Result<bool, SceneError> {
+        self.count(0)?;
+        Ok(true)
+    }
+
+    fn name(&self) -> Result<(Pos, String), SceneError> {
+        self.count(1)?;
+        name(&self.values[0])
+    }
+
+    // three numbers, or the name of a texture
+    fn color_source(&self) -> Result<(ColorSource, Option<(Pos, String)>), SceneError> {
+        match self.values.first() {
+            Some((at, Token::Word(n))) | Some((at, Token::Str(n))) => {
+                self.count(1)?;
+                Ok((ColorSource::Texture(n.clone()), Some((*at, n.clone()))))
+            },
+            _ => Ok((ColorSource::Value(self.vec3()?), None)),
+        }
+    }
+}
+
+fn name(value: &(Pos, Token)) -> Result<(Pos, String), SceneError> {
+    match value {
+        (at, Token::Word(w)) | (at, Token::Str(w)) => Ok((*at, w.clone())),
+        (at, other) => error(*at, format!("expected a name, found {}", other.describe())),
+    }
+}
+
+struct Block {
+    at: Pos,
+    keyword: String,
+    args: Vec<(Pos, Token)>,
+    properties: Vec<Property>,
+}
+
+impl Block {
+    fn take(&mut self, name: &str) -> Result<Option<Property>, SceneError> {
+        let Some(index) = self.properties.iter().position(|p| p.name == name) else {
+            return Ok(None);
+        };
+        let property = self.properties.remove(index);
+        if let Some(again) = self.properties.iter().find(|p| p.name == name) {
+            return error(again.at, format!("`{}` given more than once", name));
+        }
+        Ok(Some(property))
+    }
+
+    fn require(&mut self, name: &str) -> Result<Property, SceneError> {
+        match self.take(name)? {
+            Some(property) => Ok(property),
+            None => error(self.at, format!("`{}` needs `{}`", self.keyword, name)),
+        }
+    }
+
+    // every property with one of `names`, keeping their order
+    fn take_all(&mut self, names: &[&str]) -> Vec<Property> {
+        let (taken, rest) = std::mem::take(&mut self.properties)
+            .into_iter()
+            .partition(|p| names.contains(&p.name.as_str()));
+        self.properties = rest;
+        taken
+    }
+
+    fn number_or(&mut self, name: &str, default: f64) -> Result<f64, SceneError> {
+        self.take(name)?.map_or(Ok(default), |p| p.number())
+    }
+
+    fn args(&self, n: usize, usage: &str) -> Result<(), SceneError> {
+        if self.args.len() > n {
+            return error(self.args[n].0, format!("unexpected {}, expected `{}`", self.args[n].1.describe(), usage));
+        }
+        if self.args.len() < n {
+            return error(self.at, format!("expected `{}`", usage));
+        }
+        Ok(())
+    }
+
+    // fails on whatever the caller did not consume
+    fn finish(self) -> Result<(), SceneError> {
+        match self.properties.first() {
+            Some(p) => error(p.at, format!("unknown property `{}` in `{}`", p.name, self.keyword)),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<(Pos, Token)>,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<(Pos, Token), SceneError> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.lexer.next_token(),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&(Pos, Token), SceneError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next_token()?);
+        }
+        Ok(self.peeked.as_ref().unwrap())
+    }
+
+    // the next block, or None at the end of the file
+    fn block(&mut self) -> Result<Option<Block>, SceneError> {
+        let (at, keyword) = loop {
+            match self.next()? {
+                (_, Token::End) => continue,
+                (_, Token::Eof) => return Ok(None),
+                (at, Token::Word(w)) => break (at, w),
+                (at, other) => return error(at, format!("expected a block, found {}", other.describe())),
+            }
+        };
+
+        let mut args = Vec::new();
+        loop {
+            match self.next()? {
+                (_, Token::Open) => break,
+                (at, token @ (Token::Word(_) | Token::Number(..) | Token::Str(_))) => args.push((at, token)),
+                (at, other) => {
+                    return error(at, format!("expected `{{` after `{}`, found {}", keyword, other.describe()));
+                },
+            }
+        }
+
+        let mut properties = Vec::new();
+        loop {
+            match self.next()? {
+                (_, Token::End) => continue,
+                (_, Token::Close) => break,
+                (_, Token::Eof) => return error(at, format!("`{}` block is never closed", keyword)),
+                (name_at, Token::Word(name)) => {
+                    let mut values = Vec::new();
+                    loop {
+                        match self.peek()? {
+                            (_, Token::End | Token::Close | Token::Eof) => break,
+                            (at, Token::Open) => return error(*at, "blocks cannot be nested"),
+                            _ => values.push(self.next()?),
+                        }
+                    }
+                    properties.push(Property { at: name_at, name, values });
+                },
+                (at, other) => return error(at, format!("expected a property name, found {}", other.describe())),
+            }
+        }
+
+        Ok(Some(Block { at, keyword, args, properties }))
+    }
+}
+
+// names used before or after their definition, checked once the whole file is read
+struct References {
+    textures: Vec<(Pos, String)>,
+    materials: Vec<(Pos, String)>,
+}
+
+pub(crate) fn parse(source: &str) -> Result<Scene, SceneError> {
+    let mut parser = Parser { lexer: Lexer::new(source), peeked: None };
+    let mut camera: Option<CameraDesc> = None;
+    let mut render: Option<Block> = None;
+    let mut background: Option<Background> = None;
+    let mut textures = Vec::new();
+    let mut materials = Vec::new();
+    let mut objects = Vec::new();
+    let mut texture_names = HashSet::new();
+    let mut material_names = HashSet::new();
+    let mut refs = References { textures: Vec::new(), materials: Vec::new() };
+
+    while let Some(block) = parser.block()? {
+        match block.keyword.as_str() {
+            "camera" => {
+                if camera.is_some() {
+                    return error(block.at, "only one `camera` block is allowed");
+                }
+                camera = Some(parse_camera(block)?);
+            },
+            "render" => {
+                if render.is_some() {
+                    return error(block.at, "only one `render` block is allowed");
+                }
+                block.args(0, "render {")?;
+                render = Some(block);
+            },
+            "background" => {
+                if background.is_some() {
+                    return error(block.at, "only one `background` block is allowed");
+                }
+                background = Some(parse_background(block)?);
+            },
+            "texture" => {
+                block.args(2, "texture <name> <type> {")?;
+                let (at, name) = name(&block.args[0])?;
+                if !texture_names.insert(name.clone()) {
+                    return error(at, format!("texture `{}` is already defined", name));
+                }
+                textures.push((name, parse_texture(block)?));
+            },
+            "material" => {
+                block.args(2, "material <name> <type> {")?;
+                let (at, name) = name(&block.args[0])?;
+                if !material_names.insert(name.clone()) {
+                    return error(at, format!("material `{}` is already defined", name));
+                }
+                materials.push((name, parse_material(block, &mut refs)?));
+            },
+            _ => objects.push(parse_object(block, &mut refs)?),
+        }
+    }
+
+    for (at, name) in refs.textures {
+        if !texture_names.contains(&name) {
+            return error(at, format!("unknown texture `{}`", name));
+        }
+    }
+    for (at, name) in refs.materials {
+        if !material_names.contains(&name) {
+            return error(at, format!("unknown material `{}`", name));
+        }
+    }
+
+    let Some(mut camera) = camera else {
+        return Err(SceneError::Invalid("the scene has no `camera` block".to_string()));
+    };
+    if let Some(block) = render {
+        parse_render(block, &mut camera)?;
+    }
+
+    let mut scene = Scene::new(camera);
+    scene.background = background.unwrap_or_default();
+    scene.textures = textures;
+    scene.materials = materials;
+    scene.objects = objects;
+    Ok(scene)
+}
+
+fn parse_camera(mut block: Block) -> Result<CameraDesc, SceneError> {
+    block.args(0, "camera {")?;
+    let look_from = block.require("look_from")?.vec3()?;
+    let look_at = block.require("look_at")?.vec3()?;
+    if look_from == look_at {
+        return error(block.at, "`look_from` and `look_at` must differ");
+    }
+    let mut camera = CameraDesc::new(look_from, look_at);
+    if let Some(p) = block.take("up")? {
+        camera.up = p.direction()?;
+    }
+    if let Some(p) = block.take("fov")? {
+        camera.fov = p.positive()?;
+    }
+    if let Some(p) = block.take("defocus")? {
+        (camera.defocus_angle, camera.focus_dist) = p.pair()?;
+    }
+    if let Some(p) = block.take("shutter")? {
+        camera.shutter = p.pair()?;
+    }
+    block.finish()?;
+    Ok(camera)
+}
+
+fn parse_render(mut block: Block, camera: &mut CameraDesc) -> Result<(), SceneError> {
+    if let Some(p) = block.take("resolution")? {
+        p.count(2)?;
+        let size = p.floats()?;
+        if size.iter().any(|n| n.fract() != 0.0 || *n < 1.0 || *n > u32::MAX as f64) {
+            return error(p.at, "`resolution` takes a positive whole width and height");
+        }
+        camera.width = size[0] as u32;
+        camera.height = size[1] as u32;
+    }
+    if let Some(p) = block.take("samples")? {
+        camera.samples = p.integer(1, u16::MAX as u64)? as u16;
+    }
+    if let Some(p) = block.take("max_depth")? {
+        camera.max_depth = p.integer(1, u8::MAX as u64)? as u8;
+    }
+    block.finish()
+}
+
+fn parse_background(mut block: Block) -> Result<Background, SceneError> {
+    block.args(0, "background {")?;
+    let background = match (block.take("color")?, block.take("horizon")?, block.take("zenith")?) {
+        (Some(color), None, None) => Background::Solid(color.vec3()?),
+        (None, Some(horizon), Some(zenith)) => Background::Gradient(horizon.vec3()?, zenith.vec3()?),
+        (Some(_), _, _) => return error(block.at, "`background` takes either `color` or `horizon` and `zenith`"),
+        _ => return error(block.at, "`background` needs `color`, or `horizon` and `zenith`"),
+    };
+    block.finish()?;
+    Ok(background)
+}
+
+fn parse_texture(mut block: Block) -> Result<TextureDesc, SceneError> {
+    let (at, kind) = name(&block.args[1])?;
+    let (seed, scale, colors) = match kind.as_str() {
+        "image" => (0, 1.0, None),
+        _ => (
+            match kind.as_str() {
+                "checker" => 0,
+                _ => block.take("seed")?.map_or(Ok(0), |p| p.integer(0, u64::MAX))?,
+            },
+            block.number_or("scale", 1.0)?,
+            match kind.as_str() {
+                "noise" => None,
+                _ => block.take("colors")?.map(|p| p.color_pair()).transpose()?,
+            },
+        ),
+    };
+
+    let texture = match kind.as_str() {
+        "noise" => TextureDesc::Noise { seed, scale },
+        "marble" => TextureDesc::Marble { seed, scale, colors },
+        "wood" => TextureDesc::Wood { seed, scale, rings: block.number_or("rings", 10.0)?, colors },
+        "clouds" => TextureDesc::Clouds { seed, scale, cover: block.number_or("cover", 0.5)?, colors },
+        "checker" => TextureDesc::Checker { scale, colors },
+        "image" => TextureDesc::Image { path: block.require("path")?.name()?.1 },
+        _ => return error(at, format!("unknown texture type `{}`", kind)),
+    };
+    block.finish()?;
+    Ok(texture)
+}
+
+fn color_input(
+    block: &mut Block,
+    name: &str,
+    refs: &mut References,
+) -> Result<ColorSource, SceneError> {
+    let (source, reference) = block.require(name)?.color_source()?;
+    refs.textures.extend(reference);
+    Ok(source)
+}
+
+fn parse_material(mut block: Block, refs: &mut References) -> Result<MaterialDesc, SceneError> {
+    let (at, kind) = name(&block.args[1])?;
+    let material = match kind.as_str() {
+        "lambertian" => MaterialDesc::Lambertian { albedo: color_input(&mut block, "albedo", refs)? },
+        "metal" => MaterialDesc::Metal {
+            albedo: color_input(&mut block, "albedo", refs)?,
+            fuzz: block.number_or("fuzz", 0.0)?,
+        },
+        "dielectric" => MaterialDesc::Dielectric { ior: block.require("ior")?.positive()? },
+        "conductor" => {
+            let (eta, k) = match (block.take("preset")?, block.take("eta")?, block.take("k")?) {
+                (Some(preset), None, None) => {
+                    let (at, metal) = preset.name()?;
+                    match measured_metal(&metal) {
+                        Some(ior) => ior,
+                        None => return error(at, format!("unknown metal `{}`", metal)),
+                    }
+                },
+                (None, Some(eta), Some(k)) => (eta.vec3()?, k.vec3()?),
+                _ => return error(block.at, "`conductor` needs either `preset` or both `eta` and `k`"),
+            };
+            let roughness = match block.take("roughness")? {
+                Some(p) => match p.floats()?[..] {
+                    [r] => (r, r),
+                    [u, v] => (u, v),
+                    _ => return error(p.at, "`roughness` takes one value, or two for u and v"),
+                },
+                None => (0.0, 0.0),
+            };
+            MaterialDesc::Conductor { eta, k, roughness }
+        },
+        "rough_dielectric" => MaterialDesc::RoughDielectric {
+            ior: block.require("ior")?.positive()?,
+            roughness: block.number_or("roughness", 0.0)?,
+        },
+        "principled" => {
+            let mut p = PrincipledDesc::new(color_input(&mut block, "base_color", refs)?);
+            for (name, value) in [
+                ("metallic", &mut p.metallic),
+                ("roughness", &mut p.roughness),
+                ("specular", &mut p.specular),
+                ("specular_tint", &mut p.specular_tint),
+                ("sheen", &mut p.sheen),
+                ("sheen_tint", &mut p.sheen_tint),
+                ("clearcoat", &mut p.clearcoat),
+                ("clearcoat_gloss", &mut p.clearcoat_gloss),
+                ("transmission", &mut p.transmission),
+                ("anisotropic", &mut p.anisotropic),
+                ("ior", &mut p.ior),
+            ] {
+                *value = block.number_or(name, *value)?;
+            }
+            MaterialDesc::Principled(p)
+        },
+        "light" => MaterialDesc::Light { emit: color_input(&mut block, "emit", refs)? },
+        "isotropic" => MaterialDesc::Isotropic { albedo: color_input(&mut block, "albedo", refs)? },
+        "henyey_greenstein" => {
+            let albedo = color_input(&mut block, "albedo", refs)?;
+            let g = block.require("g")?;
+            let value = g.number()?;
+            if value.abs() >= 1.0 {
+                return error(g.values[0].0, "`g` must lie strictly between -1 and 1");
+            }
+            MaterialDesc::HenyeyGreenstein { albedo, g: value }
+        },
+        _ => return error(at, format!("unknown material type `{}`", kind)),
+    };
+    block.finish()?;
+    Ok(material)
+}
+
+fn parse_shape(block: &mut Block) -> Result<ShapeDesc, SceneError> {
+    let capped = |block: &mut Block| block.take("capped")?.map_or(Ok(false), |p| p.flag());
+    Ok(match block.keyword.as_str() {
+        "sphere" => ShapeDesc::Sphere {
+            center: block.require("center")?.vec3()?,
+            radius: block.require("radius")?.number()?,
+            to: block.take("to")?.map(|p| p.vec3()).transpose()?,
+        },
+        "quad" => ShapeDesc::Quad {
+            corner: block.require("corner")?.vec3()?,
+            u: block.require("u")?.direction()?,
+            v: block.require("v")?.direction()?,
+        },
+        "disk" => ShapeDesc::Disk {
+            center: block.require("center")?.vec3()?,
+            normal: block.require("normal")?.direction()?,
+            radius: block.require("radius")?.positive()?,
+        },
+        "plane" => ShapeDesc::Plane {
+            point: block.require("point")?.vec3()?,
+            normal: block.require("normal")?.direction()?,
+        },
+        "box" => ShapeDesc::Box {
+            min: block.require("min")?.vec3()?,
+            max: block.require("max")?.vec3()?,
+        },
+        "cylinder" => ShapeDesc::Cylinder {
+            base: block.require("base")?.vec3()?,
+            axis: block.require("axis")?.direction()?,
+            radius: block.require("radius")?.positive()?,
+            height: block.require("height")?.positive()?,
+            capped: capped(block)?,
+        },
+        "cone" => ShapeDesc::Cone {
+            base: block.require("base")?.vec3()?,
+            axis: block.require("axis")?.direction()?,
+            radius: block.require("radius")?.positive()?,
+            top_radius: block.number_or("top_radius", 0.0)?,
+            height: block.require("height")?.positive()?,
+            capped: capped(block)?,
+        },
+        "torus" => ShapeDesc::Torus {
+            center: block.require("center")?.vec3()?,
+            axis: block.require("axis")?.direction()?,
+            major: block.require("major")?.positive()?,
+            minor: block.require("minor")?.positive()?,
+        },
+        other => return error(block.at, format!("unknown block `{}`", other)),
+    })
+}
+
+fn parse_transform(p: &Property) -> Result<TransformDesc, SceneError> {
+    Ok(match p.name.as_str() {
+        "translate" => TransformDesc::Translate(p.vec3()?),
+        "rotate" => {
+            p.count(4)?;
+            let v = p.floats()?;
+            let axis = Point::new([v[0], v[1], v[2]]);
+            if axis.square() == 0.0 {
+                return error(p.at, "the rotation axis must not be a zero vector");
+            }
+            TransformDesc::Rotate(axis, v[3])
+        },
+        _ => {
+            let factors = match p.floats()?[..] {
+                [s] => Vec3::new([s; 3]),
+                [x, y, z] => Vec3::new([x, y, z]),
+                _ => return error(p.at, "`scale` takes one uniform factor or three"),
+            };
+            if factors.x() == 0.0 || factors.y() == 0.0 || factors.z() == 0.0 {
+                return error(p.at, "scale factors must be non-zero");
+            }
+            TransformDesc::Scale(factors)
+        },
+    })
+}
+
+fn parse_object(mut block: Block, refs: &mut References) -> Result<ObjectDesc, SceneError> {
+    let shape = parse_shape(&mut block)?;
+    block.args(0, &format!("{} {{", block.keyword))?;
+    let (at, material) = block.require("material")?.name()?;
+    refs.materials.push((at, material.clone()));
+
+    let mut object = ObjectDesc::new(shape, &material);
+    object.light = block.take("light")?.map_or(Ok(false), |p| p.flag())?;
+    object.medium = block.take("medium")?.map(|p| p.positive()).transpose()?;
+    object.transforms = block.take_all(&["translate", "rotate", "scale"])
+        .iter()
+        .map(parse_transform)
+        .collect::<Result<_, _>>()?;
+    block.finish()?;
+    Ok(object)
+}
@@ -0,0 +1,236 @@
+// Writes a scene back out in the text format. Numbers use the shortest form that reads
+// back to the same f64, so parsing the output reproduces the scene exactly.
+use super::{
+    Scene, CameraDesc, TextureDesc, ColorSource, MaterialDesc, ShapeDesc, TransformDesc, ObjectDesc,
+};
+use crate::vec3::{Vec3};
+use crate::world::{Background};
+use std::fmt;
+
+fn vec3(v: &Vec3) -> String {
+    format!("{} {} {}", v.x(), v.y(), v.z())
+}
+
+// bare words where the parser reads them back as one, quoted strings otherwise
+fn name(text: &str) -> String {
+    let mut chars = text.chars();
+    let bare = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || "_-.".contains(c));
+    if bare {
+        return text.to_string();
+    }
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+fn color_source(source: &ColorSource) -> String {
+    match source {
+        ColorSource::Value(color) => vec3(color),
+        ColorSource::Texture(texture) => name(texture),
+    }
+}
+
+fn write_camera(f: &mut fmt::Formatter, camera: &CameraDesc) -> fmt::Result {
+    writeln!(f, "camera {{")?;
+    writeln!(f, "    look_from {}", vec3(&camera.look_from))?;
+    writeln!(f, "    look_at {}", vec3(&camera.look_at))?;
+    writeln!(f, "    up {}", vec3(&camera.up))?;
+    writeln!(f, "    fov {}", camera.fov)?;
+    writeln!(f, "    defocus {} {}", camera.defocus_angle, camera.focus_dist)?;
+    writeln!(f, "    shutter {} {}", camera.shutter.0, camera.shutter.1)?;
+    writeln!(f, "}}\n")?;
+
+    writeln!(f, "render {{")?;
+    writeln!(f, "    resolution {} {}", camera.width, camera.height)?;
+    writeln!(f, "    samples {}", camera.samples)?;
+    writeln!(f, "    max_depth {}", camera.max_depth)?;
+    writeln!(f, "}}\n")
+}
+
+fn write_background(f: &mut fmt::Formatter, background: &Background) -> fmt::Result {
+    match background {
+        Background::Gradient(horizon, zenith) => {
+            writeln!(f, "background {{ horizon {}; zenith {} }}\n", vec3(horizon), vec3(zenith))
+        },
+        Background::Solid(color) => writeln!(f, "background {{ color {} }}\n", vec3(color)),
+    }
+}
+
+fn write_texture(f: &mut fmt::Formatter, texture_name: &str, texture: &TextureDesc) -> fmt::Result {
+    let mut properties = Vec::new();
+    let kind = match texture {
+        TextureDesc::Noise { seed, scale } => {
+            properties.push(format!("seed {}; scale {}", seed, scale));
+            "noise"
+        },
+        TextureDesc::Marble { seed, scale, .. } => {
+            properties.push(format!("seed {}; scale {}", seed, scale));
+            "marble"
+        },
+        TextureDesc::Wood { seed, scale, rings, .. } => {
+            properties.push(format!("seed {}; scale {}; rings {}", seed, scale, rings));
+            "wood"
+        },
+        TextureDesc::Clouds { seed, scale, cover, .. } => {
+            properties.push(format!("seed {}; scale {}; cover {}", seed, scale, cover));
+            "clouds"
+        },
+        TextureDesc::Checker { scale, .. } => {
+            properties.push(format!("scale {}", scale));
+            "checker"
+        },
+        TextureDesc::Image { path } => {
+            properties.push(format!("path {}", name(path)));
+            "image"
+        },
+    };
+    if let TextureDesc::Marble { colors: Some((a, b)), .. }
+        | TextureDesc::Wood { colors: Some((a, b)), .. }
+        | TextureDesc::Clouds { colors: Some((a, b)), .. }
+        | TextureDesc::Checker { colors: Some((a, b)), .. } = texture
+    {
+        properties.push(format!("colors {} {}", vec3(a), vec3(b)));
+    }
+    writeln!(f, "texture {} {} {{ {} }}", name(texture_name), kind, properties.join("; "))
+}
+
+fn write_material(f: &mut fmt::Formatter, material_name: &str, material: &MaterialDesc) -> fmt::Result {
+    let (kind, properties) = match material {
+        MaterialDesc::Lambertian { albedo } => {
+            ("lambertian", vec![format!("albedo {}", color_source(albedo))])
+        },
+        MaterialDesc::Metal { albedo, fuzz } => {
+            ("metal", vec![format!("albedo {}", color_source(albedo)), format!("fuzz {}", fuzz)])
+        },
+        MaterialDesc::Dielectric { ior } => ("dielectric", vec![format!("ior {}", ior)]),
+        MaterialDesc::Conductor { eta, k, roughness } => {
+            let roughness = if roughness.0 == roughness.1 {
+                format!("roughness {}", roughness.0)
+            } else {
+                format!("roughness {} {}", roughness.0, roughness.1)
+            };
+            ("conductor", vec![format!("eta {}", vec3(eta)), format!("k {}", vec3(k)), roughness])
+        },
+        MaterialDesc::RoughDielectric { ior, roughness } => {
+            ("rough_dielectric", vec![format!("ior {}", ior), format!("roughness {}", roughness)])
+        },
+        MaterialDesc::Principled(p) => ("principled", vec![
+            format!("base_color {}", color_source(&p.base_color)),
+            format!("metallic {}", p.metallic),
+            format!("roughness {}", p.roughness),
+            format!("specular {}", p.specular),
+            format!("specular_tint {}", p.specular_tint),
+            format!("sheen {}", p.sheen),
+            format!("sheen_tint {}", p.sheen_tint),
+            format!("clearcoat {}", p.clearcoat),
+            format!("clearcoat_gloss {}", p.clearcoat_gloss),
+            format!("transmission {}", p.transmission),
+            format!("anisotropic {}", p.anisotropic),
+            format!("ior {}", p.ior),
+        ]),
+        MaterialDesc::Light { emit } => ("light", vec![format!("emit {}", color_source(emit))]),
+        MaterialDesc::Isotropic { albedo } => {
+            ("isotropic", vec![format!("albedo {}", color_source(albedo))])
+        },
+        MaterialDesc::HenyeyGreenstein { albedo, g } => {
+            ("henyey_greenstein", vec![format!("albedo {}", color_source(albedo)), format!("g {}", g)])
+        },
+    };
+    writeln!(f, "material {} {} {{ {} }}", name(material_name), kind, properties.join("; "))
+}
+
+fn write_object(f: &mut fmt::Formatter, object: &ObjectDesc) -> fmt::Result {
+    let (keyword, mut properties) = match &object.shape {
+        ShapeDesc::Sphere { center, radius, to } => {
+            let mut properties = vec![format!("center {}", vec3(center)), format!("radius {}", radius)];
+            if let Some(end) = to {
+                properties.push(format!("to {}", vec3(end)));
+            }
+            ("sphere", properties)
+        },
+        ShapeDesc::Quad { corner, u, v } => ("quad", vec![
+            format!("corner {}", vec3(corner)),
+            format!("u {}", vec3(u)),
+            format!("v {}", vec3(v)),
+        ]),
+        ShapeDesc::Disk { center, normal, radius } => ("disk", vec![
+            format!("center {}", vec3(center)),
+            format!("normal {}", vec3(normal)),
+            format!("radius {}", radius),
+        ]),
+        ShapeDesc::Plane { point, normal } => {
+            ("plane", vec![format!("point {}", vec3(point)), format!("normal {}", vec3(normal))])
+        },
+        ShapeDesc::Box { min, max } => ("box", vec![format!("min {}", vec3(min)), format!("max {}", vec3(max))]),
+        ShapeDesc::Cylinder { base, axis, radius, height, capped } => {
+            let mut properties = vec![
+                format!("base {}", vec3(base)),
+                format!("axis {}", vec3(axis)),
+                format!("radius {}", radius),
+                format!("height {}", height),
+            ];
+            if *capped {
+                properties.push("capped".to_string());
+            }
+            ("cylinder", properties)
+        },
+        ShapeDesc::Cone { base, axis, radius, top_radius, height, capped } => {
+            let mut properties = vec![
+                format!("base {}", vec3(base)),
+                format!("axis {}", vec3(axis)),
+                format!("radius {}", radius),
+                format!("top_radius {}", top_radius),
+                format!("height {}", height),
+            ];
+            if *capped {
+                properties.push("capped".to_string());
+            }
+            ("cone", properties)
+        },
+        ShapeDesc::Torus { center, axis, major, minor } => ("torus", vec![
+            format!("center {}", vec3(center)),
+            format!("axis {}", vec3(axis)),
+            format!("major {}", major),
+            format!("minor {}", minor),
+        ]),
+    };
+
+    properties.push(format!("material {}", name(&object.material)));
+    if object.light {
+        properties.push("light".to_string());
+    }
+    if let Some(density) = object.medium {
+        properties.push(format!("medium {}", density));
+    }
+    for step in &object.transforms {
+        properties.push(match step {
+            TransformDesc::Translate(offset) => format!("translate {}", vec3(offset)),
+            TransformDesc::Rotate(axis, degrees) => format!("rotate {} {}", vec3(axis), degrees),
+            TransformDesc::Scale(factors) => format!("scale {}", vec3(factors)),
+        });
+    }
+    writeln!(f, "{} {{ {} }}", keyword, properties.join("; "))
+}
+
+impl fmt::Display for Scene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_camera(f, &self.camera)?;
+        write_background(f, &self.background)?;
+        for (texture_name, texture) in &self.textures {
+            write_texture(f, texture_name, texture)?;
+        }
+        if !self.textures.is_empty() {
+            writeln!(f)?;
+        }
+        for (material_name, material) in &self.materials {
+            write_material(f, material_name, material)?;
+        }
+        if !self.materials.is_empty() {
+            writeln!(f)?;
+        }
+        for object in &self.objects {
+            write_object(f, object)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::vec3::{Point, Vec3, Onb};
+use crate::aabb::{Aabb};
+use std::f64::consts::PI;
+use std::sync::{Arc};
+
+// A signed distance field: negative inside, positive outside, and never more than the true
+// distance to the surface, so sphere tracing can step by it without overshooting.
+pub trait Sdf: Sync + Send {
+    fn distance(&self, p: &Point) -> f64;
+
+    // bounds of the surface; None for fields that repeat forever
+    fn bounding_box(&self) -> Option<Aabb>;
+}
+
+pub struct SdfSphere {
+    center: Point,
+    radius: f64,
+}
+
+impl SdfSphere {
+    pub fn new(center: Point, radius: f64) -> SdfSphere {
+        SdfSphere { center, radius }
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: &Point) -> f64 {
+        (*p - self.center).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new([self.radius; 3]);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+// axis aligned
+pub struct SdfBox {
+    center: Point,
+    half: Vec3,
+}
+
+impl SdfBox {
+    pub fn new(center: Point, half_extents: Vec3) -> SdfBox {
+        SdfBox { center, half: half_extents }
+    }
+}
+
+impl Sdf for SdfBox {
+    fn distance(&self, p: &Point) -> f64 {
+        let mut q = *p - self.center;
+        for i in 0..3 {
+            q[i] = q[i].abs() - self.half[i];
+        }
+        let outside = Vec3::new([q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)]).length();
+        let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+        outside + inside
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.center - self.half, self.center + self.half))
+    }
+}
+
+pub struct SdfTorus {
+    center: Point,
+    frame: Onb,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl SdfTorus {
+    pub fn new(center: Point, axis: Vec3, major_radius: f64, minor_radius: f64) -> SdfTorus {
+        SdfTorus {
+            center,
+            frame: Onb::new(&axis.unit()),
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, p: &Point) -> f64 {
+        let q = self.frame.to_local(&(*p - self.center));
+        let rho = (q.x() * q.x() + q.y() * q.y()).sqrt() - self.major_radius;
+        (rho * rho + q.z() * q.z()).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let local = Aabb::new(
+            Point::new([-outer, -outer, -self.minor_radius]),
+            Point::new([outer, outer, self.minor_radius]),
+        );
+        let corners: Vec<Point> = local.corners().iter()
+            .map(|c| self.center + self.frame.to_world(c))
+            .collect();
+        Some(Aabb::from_points(&corners))
+    }
+}
+
+// inflates a shape by `radius`, rounding its edges
+pub struct Rounded {
+    inner: Arc<dyn Sdf>,
+    radius: f64,
+}
+
+impl Rounded {
+    pub fn new(inner: Arc<dyn Sdf>, radius: f64) -> Rounded {
+        Rounded { inner, radius }
+    }
+}
+
+impl Sdf for Rounded {
+    fn distance(&self, p: &Point) -> f64 {
+        self.inner.distance(p) - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = self.inner.bounding_box()?;
+        let r = Vec3::new([self.radius.max(0.0); 3]);
+        Some(Aabb::new(*b.min() - r, *b.max() + r))
+    }
+}
+
+// polynomial smooth minimum; blends within `k` of the seam and digs at most k / 4 below min(a, b)
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k / 4.0
+}
+
+pub struct SmoothUnion {
+    a: Arc<dyn Sdf>,
+    b: Arc<dyn Sdf>,
+    k: f64,
+}
+
+impl SmoothUnion {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, k: f64) -> SmoothUnion {
+        SmoothUnion { a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &Point) -> f64 {
+        smooth_min(self.a.distance(p), self.b.distance(p), self.k)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = self.a.bounding_box()?.union(&self.b.bounding_box()?);
+        let pad = Vec3::new([self.k.max(0.0) / 4.0; 3]);
+        Some(Aabb::new(*b.min() - pad, *b.max() + pad))
+    }
+}
+
+// `a` with `b` carved out, the rim rounded over `k`
+pub struct SmoothSubtraction {
+    a: Arc<dyn Sdf>,
+    b: Arc<dyn Sdf>,
+    k: f64,
+}
+
+impl SmoothSubtraction {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, k: f64) -> SmoothSubtraction {
+        SmoothSubtraction { a, b, k }
+    }
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: &Point) -> f64 {
+        -smooth_min(-self.a.distance(p), self.b.distance(p), self.k)
+    }
+
+    // only ever removes material from `a`
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box()
+    }
+}
+
+// Tiles space into cells of size `spacing` and evaluates `inner` around the origin of each.
+// The inner shape must fit inside one cell centered on the origin for distances to stay valid.
+pub struct Repeat {
+    inner: Arc<dyn Sdf>,
+    spacing: Vec3,
+    limit: Option<[f64; 3]>,
+}
+
+impl Repeat {
+    pub fn new(inner: Arc<dyn Sdf>, spacing: Vec3) -> Repeat {
+        Repeat { inner, spacing, limit: None }
+    }
+
+    // only the cells within `count` steps of the origin along each axis
+    pub fn limited(inner: Arc<dyn Sdf>, spacing: Vec3, count: [u32; 3]) -> Repeat {
+        Repeat {
+            inner,
+            spacing,
+            limit: Some(count.map(f64::from)),
+        }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: &Point) -> f64 {
+        let mut q = *p;
+        for i in 0..3 {
+            if self.spacing[i] <= 0.0 {
+                continue;
+            }
+            let mut cell = (p[i] / self.spacing[i]).round();
+            if let Some(limit) = self.limit {
+                cell = cell.clamp(-limit[i], limit[i]);
+            }
+            q[i] = p[i] - self.spacing[i] * cell;
+        }
+        self.inner.distance(&q)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let limit = self.limit?;
+        let b = self.inner.bounding_box()?;
+        let mut reach = Vec3::new([0.0; 3]);
+        for i in 0..3 {
+            reach[i] = self.spacing[i].max(0.0) * limit[i];
+        }
+        Some(Aabb::new(*b.min() - reach, *b.max() + reach))
+    }
+}
+
+const MAX_STEPS: usize = 512;
+const EPSILON: f64 = 1e-4;
+// how far unbounded fields are marched before giving up
+const MAX_DISTANCE: f64 = 1e4;
+
+// Renders any `Sdf` by sphere tracing: step along the ray by the distance to the surface until
+// it falls below `epsilon`. Only the part of the ray within the bounding box is marched.
+pub struct RayMarched {
+    sdf: Arc<dyn Sdf>,
+    mat: Arc<dyn Bsdf>,
+    max_steps: usize,
+    epsilon: f64,
+}
+
+impl RayMarched {
+    pub fn new(sdf: Arc<dyn Sdf>, m: impl Into<Arc<dyn Bsdf>>) -> RayMarched {
+        RayMarched {
+            sdf,
+            mat: m.into(),
+            max_steps: MAX_STEPS,
+            epsilon: EPSILON,
+        }
+    }
+
+    pub fn max_steps(mut self, steps: usize) -> RayMarched {
+        self.max_steps = steps.max(1);
+        self
+    }
+
+    pub fn epsilon(mut self, epsilon: f64) -> RayMarched {
+        self.epsilon = epsilon.abs().max(1e-9);
+        self
+    }
+
+    fn normal(&self, p: &Point) -> Vec3 {
+        let h = self.epsilon / 2.0;
+        let mut n = Vec3::new([0.0; 3]);
+        for i in 0..3 {
+            let mut offset = Vec3::new([0.0; 3]);
+            offset[i] = h;
+            n[i] = self.sdf.distance(&(*p + offset)) - self.sdf.distance(&(*p - offset));
+        }
+        if n.near_zero() { Vec3::new([0.0, 1.0, 0.0]) } else { n.unit() }
+    }
+}
+
+impl Hittable for RayMarched {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let length = ray.direct().length();
+        if length == 0.0 {
+            return None;
+        }
+        let (start, end) = match self.bounding_box() {
+            Some(b) => b.clip(ray, t_min, t_max)?,
+            None => (t_min, t_max.min(MAX_DISTANCE / length)),
+        };
+
+        // Distances are measured along the unit direction. A ray leaving the surface starts
+        // within epsilon of it, so the first sample is never taken as a hit.
+        // Running out of steps is a miss, not a hit where the march stopped.
+        let mut t = start;
+        let mut hit = false;
+        for step in 0..self.max_steps {
+            if t > end {
+                return None;
+            }
+            let d = self.sdf.distance(&ray.range(t)).abs();
+            if d < self.epsilon && step > 0 {
+                hit = true;
+                break;
+            }
+            t += d.max(self.epsilon) / length;
+        }
+        if !hit || t > end || t < t_min {
+            return None;
+        }
+
+        let pos = ray.range(t);
+        let outward = self.normal(&pos);
+        let front_face = ray.direct().dot(&outward) < 0.0;
+        let normal = if front_face { outward } else { outward.reverse() };
+        let uv = (
+            (-outward.z()).atan2(outward.x()) / (2.0 * PI) + 0.5,
+            (-outward.y()).clamp(-1.0, 1.0).acos() / PI,
+        );
+        Some(HitRecord::new(t, pos, normal, front_face, uv, Arc::clone(&self.mat)))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = self.sdf.bounding_box()?;
+        let pad = Vec3::new([self.epsilon; 3]);
+        Some(Aabb::new(*b.min() - pad, *b.max() + pad))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material};
+
+    #[test]
+    fn running_out_of_steps_is_a_miss() {
+        // the ray passes the rows of spheres no closer than about 0.54, but the repeated field
+        // never ends, so the march runs out of steps before it can leave the scene
+        let spheres = Repeat::new(Arc::new(SdfSphere::new(Point::new([0.0; 3]), 0.5)), Vec3::new([2.0; 3]));
+        let marched = RayMarched::new(Arc::new(spheres), Material::Dielectric(1.5));
+        let ray = Ray::new(Point::new([0.0, 1.0, 0.3]), Vec3::new([1.0, 0.0, 0.0]));
+        assert!(marched.intersect(&ray, 0.001, f64::INFINITY).is_none());
+    }
+}
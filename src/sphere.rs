@@ -1,26 +1,55 @@
 use crate::ray::{Ray, HitRecord, Hittable};
-use crate::material::{Material};
-use crate::vec3::{Point};
+use crate::material::{Bsdf};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use crate::csg::{Solid, Span};
+use std::f64::consts::PI;
+use std::sync::{Arc};
 
 pub struct Sphere {
     center: Point,
+    // displacement over the unit time interval, zero for a still sphere
+    motion: Vec3,
     radius: f64,
-    mat: Material,
+    mat: Arc<dyn Bsdf>,
 }
 
 impl Sphere {
-    pub const fn new(p: Point, r: f64, m: Material) -> Sphere {
+    pub fn new(p: Point, r: f64, m: impl Into<Arc<dyn Bsdf>>) -> Sphere {
         Sphere {
             center: p,
+            motion: Vec3::new([0.0; 3]),
             radius: r,
-            mat: m,
+            mat: m.into(),
         }
     }
+
+    // a sphere travelling in a straight line from `p0` at time 0 to `p1` at time 1
+    pub fn moving(p0: Point, p1: Point, r: f64, m: impl Into<Arc<dyn Bsdf>>) -> Sphere {
+        Sphere {
+            center: p0,
+            motion: p1 - p0,
+            radius: r,
+            mat: m.into(),
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Point {
+        self.center + time * self.motion
+    }
+
+    // u is the angle around the y axis from x = -1, v the angle from y = -1 to y = +1
+    fn uv(outward_normal: &Vec3) -> (f64, f64) {
+        let theta = (-outward_normal.y()).acos();
+        let phi = (-outward_normal.z()).atan2(outward_normal.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
 }
 
-impl Hittable for Sphere {
-    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = self.center - *ray.org();
+impl Sphere {
+    // both crossings of the ray's line, in ascending order
+    fn roots(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let oc = self.center(ray.time()) - *ray.org();
         let a = ray.direct().square();
         let h = ray.direct().dot(&oc);
         let c = oc.square() - self.radius * self.radius;
@@ -31,8 +60,30 @@ impl Hittable for Sphere {
         }
 
         let delta_sqrt = delta.sqrt();
-        let x1 = (h - delta_sqrt) / a;
-        let x2 = (h + delta_sqrt) / a;
+        Some(((h - delta_sqrt) / a, (h + delta_sqrt) / a))
+    }
+
+    fn record(&self, ray: &Ray, root: f64) -> HitRecord {
+        let position = ray.range(root);
+        let mut normal = (position - self.center(ray.time())) / self.radius;
+        let uv = Sphere::uv(&normal);
+        let front_face = ray.direct().dot(&normal) < 0.0;
+        if !front_face { normal = normal.reverse(); }
+
+        HitRecord::new(
+            root,
+            position,
+            normal,
+            front_face,
+            uv,
+            Arc::clone(&self.mat),
+        )
+    }
+}
+
+impl Hittable for Sphere {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (x1, x2) = self.roots(ray)?;
 
         let root: f64;
         if t_min <= x1 && x1 <= t_max {
@@ -43,18 +94,23 @@ impl Hittable for Sphere {
             return None;
         }
 
-        let position = ray.range(root);
-        let mut normal = (position - self.center) / self.radius;
-        let front_face = ray.direct().dot(&normal) < 0.0;
-        if !front_face { normal = normal.reverse(); }
+        Some(self.record(ray, root))
+    }
 
-        Some(HitRecord::new(
-            root,
-            position,
-            normal,
-            front_face,
-            self.mat,
-        ))
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new([self.radius; 3]);
+        let start = Aabb::new(self.center - r, self.center + r);
+        let end = Aabb::new(self.center(1.0) - r, self.center(1.0) + r);
+        Some(start.union(&end))
+    }
+}
+
+impl Solid for Sphere {
+    fn intersect_all(&self, ray: &Ray) -> Vec<Span> {
+        match self.roots(ray) {
+            Some((x1, x2)) => vec![Span { enter: self.record(ray, x1), exit: self.record(ray, x2) }],
+            None => Vec::new(),
+        }
     }
 }
 
@@ -62,5 +118,9 @@ impl Hittable for &Sphere {
     fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         (*self).intersect(ray, t_min, t_max)
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (*self).bounding_box()
+    }
 }
 
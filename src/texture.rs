@@ -0,0 +1,177 @@
+use crate::vec3::{Point};
+use crate::color::{Color, WHITE, BLACK};
+use crate::perlin::{Perlin};
+
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color;
+}
+
+// a plain color is a constant texture
+impl Texture for Color {
+    fn value(&self, _u: f64, _v: f64, _p: &Point) -> Color {
+        *self
+    }
+}
+
+// a scalar is a constant grey texture, handy for roughness inputs
+impl Texture for f64 {
+    fn value(&self, _u: f64, _v: f64, _p: &Point) -> Color {
+        Color::new([*self; 3])
+    }
+}
+
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        let n = self.noise.noise(&(*p * self.scale));
+        0.5 * (1.0 + n) * WHITE
+    }
+}
+
+pub struct Marble {
+    noise: Perlin,
+    scale: f64,
+    turbulence: f64,
+    vein: Color,
+    base: Color,
+}
+
+impl Marble {
+    pub fn new(seed: u64, scale: f64) -> Marble {
+        Marble::colored(seed, scale, BLACK, WHITE)
+    }
+
+    pub fn colored(seed: u64, scale: f64, vein: Color, base: Color) -> Marble {
+        Marble {
+            noise: Perlin::new(seed),
+            scale,
+            turbulence: 10.0,
+            vein,
+            base,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        let phase = self.scale * p.z() + self.turbulence * self.noise.turbulence(p, 7);
+        let t = 0.5 * (1.0 + phase.sin());
+        (1.0 - t) * self.vein + t * self.base
+    }
+}
+
+pub struct Wood {
+    noise: Perlin,
+    scale: f64,
+    rings: f64,
+    light: Color,
+    dark: Color,
+}
+
+impl Wood {
+    pub fn new(seed: u64, scale: f64, rings: f64) -> Wood {
+        Wood::colored(
+            seed,
+            scale,
+            rings,
+            Color::new([0.79, 0.6, 0.37]),
+            Color::new([0.42, 0.25, 0.12]),
+        )
+    }
+
+    pub fn colored(seed: u64, scale: f64, rings: f64, light: Color, dark: Color) -> Wood {
+        Wood {
+            noise: Perlin::new(seed),
+            scale,
+            rings,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        // rings grow outwards from the y axis, wobbled by turbulence
+        let q = *p * self.scale;
+        let radius = (q.x() * q.x() + q.z() * q.z()).sqrt();
+        let grain = radius * self.rings + 2.0 * self.noise.turbulence(&q, 4);
+        let t = grain - grain.floor();
+        let t = t * t * (3.0 - 2.0 * t);
+        (1.0 - t) * self.light + t * self.dark
+    }
+}
+
+pub struct Clouds {
+    noise: Perlin,
+    scale: f64,
+    cover: f64,
+    sky: Color,
+    cloud: Color,
+}
+
+impl Clouds {
+    // `cover` in [0, 1] controls how much of the sky is clouded
+    pub fn new(seed: u64, scale: f64, cover: f64) -> Clouds {
+        Clouds::colored(seed, scale, cover, Color::new([0.35, 0.55, 0.9]), WHITE)
+    }
+
+    pub fn colored(seed: u64, scale: f64, cover: f64, sky: Color, cloud: Color) -> Clouds {
+        Clouds {
+            noise: Perlin::new(seed),
+            scale,
+            cover,
+            sky,
+            cloud,
+        }
+    }
+}
+
+impl Texture for Clouds {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        let density = 0.5 * (1.0 + self.noise.fbm(&(*p * self.scale), 6, 2.0, 0.5));
+        let t = ((density - (1.0 - self.cover)) / self.cover.max(1e-3)).clamp(0.0, 1.0);
+        (1.0 - t) * self.sky + t * self.cloud
+    }
+}
+
+// alternating cubes of two colors filling space, `scale` wide each
+pub struct Checker {
+    scale: f64,
+    even: Color,
+    odd: Color,
+}
+
+impl Checker {
+    pub fn new(scale: f64) -> Checker {
+        Checker::colored(scale, Color::new([0.2, 0.3, 0.1]), Color::new([0.9, 0.9, 0.9]))
+    }
+
+    pub fn colored(scale: f64, even: Color, odd: Color) -> Checker {
+        Checker { scale, even, odd }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Bsdf};
+use crate::vec3::{Point, Vec3, Onb};
+use crate::aabb::{Aabb};
+use crate::csg::{Solid, Span};
+use crate::roots::{solve_quartic};
+use std::f64::consts::PI;
+use std::sync::{Arc};
+
+// A ring around `axis` through `center`: the set of points at distance `minor_radius`
+// from the circle of radius `major_radius`. Intersections solve the quartic
+// (|p|^2 - R^2 - r^2)^2 = 4 R^2 (r^2 - z^2) in the local frame, with the axis along z.
+pub struct Torus {
+    center: Point,
+    frame: Onb,
+    major_radius: f64,
+    minor_radius: f64,
+    mat: Arc<dyn Bsdf>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        m: impl Into<Arc<dyn Bsdf>>,
+    ) -> Torus {
+        Torus {
+            center,
+            frame: Onb::new(&axis.unit()),
+            major_radius: major_radius.abs(),
+            minor_radius: minor_radius.abs(),
+            mat: m.into(),
+        }
+    }
+
+    // u runs around the axis, v around the tube starting from its outer equator
+    fn uv(&self, p: &Vec3) -> (f64, f64) {
+        let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let u = p.y().atan2(p.x()).rem_euclid(2.0 * PI) / (2.0 * PI);
+        let v = p.z().atan2(rho - self.major_radius).rem_euclid(2.0 * PI) / (2.0 * PI);
+        (u, v)
+    }
+
+    // the direction away from the nearest point of the core circle, which stays exact where
+    // the gradient of the quartic is tiny
+    fn local_normal(&self, p: &Vec3) -> Vec3 {
+        let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let core = if rho > 0.0 {
+            Vec3::new([p.x() / rho, p.y() / rho, 0.0]) * self.major_radius
+        } else {
+            Vec3::new([self.major_radius, 0.0, 0.0])
+        };
+        let n = *p - core;
+        if n.square() == 0.0 { Vec3::new([0.0, 0.0, 1.0]) } else { n.unit() }
+    }
+
+    fn contains(&self, p: &Point) -> bool {
+        let local = self.frame.to_local(&(*p - self.center));
+        let rho = (local.x() * local.x() + local.y() * local.y()).sqrt();
+        let radial = rho - self.major_radius;
+        radial * radial + local.z() * local.z() < self.minor_radius * self.minor_radius
+    }
+}
+
+impl Torus {
+    // every crossing of the ray's line, sorted by t
+    fn roots(&self, ray: &Ray) -> Vec<f64> {
+        let length = ray.direct().length();
+        if length == 0.0 {
+            return Vec::new();
+        }
+        let origin = self.frame.to_local(&(*ray.org() - self.center));
+        let direction = self.frame.to_local(ray.direct()) / length;
+
+        // Move the origin to where the line enters the bounding sphere first: the quartic
+        // coefficients grow with the fourth power of the distance and would otherwise swamp
+        // the roots in rounding error.
+        let bound = self.major_radius + self.minor_radius;
+        let f = origin.dot(&direction);
+        let c = origin.square() - bound * bound;
+        let delta = f * f - c;
+        if delta < 0.0 {
+            return Vec::new();
+        }
+        let shift = -f - delta.sqrt();
+        let o = origin + shift * direction;
+
+        let r2 = self.major_radius * self.major_radius;
+        let e = o.square() - r2 - self.minor_radius * self.minor_radius;
+        let f = o.dot(&direction);
+        let (oz, dz) = (o.z(), direction.z());
+        solve_quartic(
+            1.0,
+            4.0 * f,
+            2.0 * e + 4.0 * f * f + 4.0 * r2 * dz * dz,
+            4.0 * f * e + 8.0 * r2 * oz * dz,
+            e * e - 4.0 * r2 * (self.minor_radius * self.minor_radius - oz * oz),
+        ).into_iter()
+            .map(|s| (s + shift) / length)
+            .collect()
+    }
+
+    fn record(&self, ray: &Ray, t: f64) -> HitRecord {
+        let local = self.frame.to_local(&(ray.range(t) - self.center));
+        let outward = self.frame.to_world(&self.local_normal(&local));
+        let front_face = ray.direct().dot(&outward) < 0.0;
+        let normal = if front_face { outward } else { outward.reverse() };
+        HitRecord::new(t, ray.range(t), normal, front_face, self.uv(&local), Arc::clone(&self.mat))
+    }
+}
+
+impl Hittable for Torus {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let t = self.roots(ray).into_iter().find(|t| t_min <= *t && *t <= t_max)?;
+        Some(self.record(ray, t))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let local = Aabb::new(
+            Point::new([-outer, -outer, -self.minor_radius]),
+            Point::new([outer, outer, self.minor_radius]),
+        );
+        let corners: Vec<Point> = local.corners().iter()
+            .map(|c| self.center + self.frame.to_world(c))
+            .collect();
+        Some(Aabb::from_points(&corners))
+    }
+}
+
+impl Solid for Torus {
+    fn intersect_all(&self, ray: &Ray) -> Vec<Span> {
+        // A tangent double root may come back once, twice or not at all, so rather than pair
+        // up roots, each stretch between neighbouring roots is tested for being inside.
+        let roots = self.roots(ray);
+        let mut spans = Vec::new();
+        let mut enter: Option<f64> = None;
+        for pair in roots.windows(2) {
+            let inside = self.contains(&ray.range((pair[0] + pair[1]) / 2.0));
+            match enter {
+                None if inside => enter = Some(pair[0]),
+                Some(t) if !inside => {
+                    spans.push(Span { enter: self.record(ray, t), exit: self.record(ray, pair[0]) });
+                    enter = None;
+                },
+                _ => {},
+            }
+        }
+        if let (Some(t), Some(last)) = (enter, roots.last()) {
+            spans.push(Span { enter: self.record(ray, t), exit: self.record(ray, *last) });
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material};
+
+    fn spans(ray: &Ray) -> Vec<(f64, f64)> {
+        let torus = Torus::new(Point::new([0.0; 3]), Vec3::new([0.0, 0.0, 1.0]), 2.0, 0.5, Material::Dielectric(1.5));
+        torus.intersect_all(ray).iter().map(|s| (s.enter.t(), s.exit.t())).collect()
+    }
+
+    #[test]
+    fn tangent_rays_give_well_formed_spans() {
+        let through = spans(&Ray::new(Point::new([-5.0, 0.0, 0.0]), Vec3::new([1.0, 0.0, 0.0])));
+        assert_eq!(through.len(), 2);
+        assert!((through[0].0 - 2.5).abs() < 1e-6 && (through[1].1 - 7.5).abs() < 1e-6);
+
+        // skimming the top of the tube touches it twice without ever going inside
+        for z in [0.5, 0.5 - 1e-9, 0.5 + 1e-9] {
+            let grazing = spans(&Ray::new(Point::new([-5.0, 0.0, z]), Vec3::new([1.0, 0.0, 0.0])));
+            assert!(grazing.windows(2).all(|w| w[0].1 <= w[1].0));
+            assert!(grazing.iter().all(|(enter, exit)| enter <= exit && exit - enter < 1e-3));
+        }
+    }
+}
@@ -0,0 +1,522 @@
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use std::ops::{Mul};
+
+// row major 4x4 matrix acting on column vectors
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Mat4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub const fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub const fn identity() -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.m[row][col]
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in self.m.iter().enumerate() {
+            for (j, x) in row.iter().enumerate() {
+                t[j][i] = *x;
+            }
+        }
+        Mat4::new(t)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; None for singular matrices
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Mat4::new(inv))
+    }
+
+    pub fn point(&self, p: &Point) -> Point {
+        let m = &self.m;
+        let mut out = Point::new([0.0; 3]);
+        for (i, row) in m.iter().take(3).enumerate() {
+            out[i] = row[0] * p.x() + row[1] * p.y() + row[2] * p.z() + row[3];
+        }
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w != 1.0 && w != 0.0 { out / w } else { out }
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let mut out = Vec3::new([0.0; 3]);
+        for (i, row) in self.m.iter().take(3).enumerate() {
+            out[i] = row[0] * v.x() + row[1] * v.y() + row[2] * v.z();
+        }
+        out
+    }
+}
+
+impl Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4::new(m)
+    }
+}
+
+// an invertible affine map, keeping the inverse around for rays and normals
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    pub fn from_matrix(matrix: Mat4) -> Option<Transform> {
+        matrix.inverse().map(|inverse| Transform { matrix, inverse })
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let (x, y, z) = (offset.x(), offset.y(), offset.z());
+        Transform {
+            matrix: Mat4::new([
+                [1.0, 0.0, 0.0, x],
+                [0.0, 1.0, 0.0, y],
+                [0.0, 0.0, 1.0, z],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+            inverse: Mat4::new([
+                [1.0, 0.0, 0.0, -x],
+                [0.0, 1.0, 0.0, -y],
+                [0.0, 0.0, 1.0, -z],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+        }
+    }
+
+    // panics on a zero factor, which would flatten the object
+    pub fn scale(factors: Vec3) -> Transform {
+        let (x, y, z) = (factors.x(), factors.y(), factors.z());
+        if x == 0.0 || y == 0.0 || z == 0.0 {
+            panic!("Scale factors must be non-zero, got {}", factors);
+        }
+        Transform {
+            matrix: Mat4::new([
+                [x, 0.0, 0.0, 0.0],
+                [0.0, y, 0.0, 0.0],
+                [0.0, 0.0, z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+            inverse: Mat4::new([
+                [1.0 / x, 0.0, 0.0, 0.0],
+                [0.0, 1.0 / y, 0.0, 0.0],
+                [0.0, 0.0, 1.0 / z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+        }
+    }
+
+    // counter clockwise by `degrees` when looking down `axis` towards the origin
+    pub fn rotate(axis: Vec3, degrees: f64) -> Transform {
+        let a = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        let matrix = Mat4::new([
+            [
+                a.x() * a.x() * k + cos,
+                a.x() * a.y() * k - a.z() * sin,
+                a.x() * a.z() * k + a.y() * sin,
+                0.0,
+            ],
+            [
+                a.y() * a.x() * k + a.z() * sin,
+                a.y() * a.y() * k + cos,
+                a.y() * a.z() * k - a.x() * sin,
+                0.0,
+            ],
+            [
+                a.z() * a.x() * k - a.y() * sin,
+                a.z() * a.y() * k + a.x() * sin,
+                a.z() * a.z() * k + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    pub fn rotate_x(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new([1.0, 0.0, 0.0]), degrees)
+    }
+
+    pub fn rotate_y(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new([0.0, 1.0, 0.0]), degrees)
+    }
+
+    pub fn rotate_z(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new([0.0, 0.0, 1.0]), degrees)
+    }
+
+    // apply `self` first and `next` afterwards
+    pub fn then(&self, next: &Transform) -> Transform {
+        *next * *self
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn point(&self, p: &Point) -> Point {
+        self.matrix.point(p)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.vector(v)
+    }
+
+    // normals go through the inverse transpose to stay perpendicular to the surface; not normalized
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse.transpose().vector(n)
+    }
+
+    pub fn bounding_box(&self, b: &Aabb) -> Aabb {
+        let corners: Vec<Point> = b.corners().iter().map(|c| self.point(c)).collect();
+        Aabb::from_points(&corners)
+    }
+
+    // true for mirroring transforms, which turn counter clockwise winding clockwise
+    // how much the transform scales volumes
+    pub fn linear_determinant(&self) -> f64 {
+        determinant3(&self.matrix)
+    }
+
+    pub fn swaps_handedness(&self) -> bool {
+        determinant3(&self.matrix) < 0.0
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+// `a * b` applies `b` first, like the matrices
+impl Mul<Transform> for Transform {
+    type Output = Transform;
+    fn mul(self, rhs: Transform) -> Self::Output {
+        Transform {
+            matrix: self.matrix * rhs.matrix,
+            inverse: rhs.inverse * self.inverse,
+        }
+    }
+}
+
+// Interpolates between two transforms over the shutter interval [0, 1]. Each end is split
+// into translation, rotation and scale (M = T R S, via polar decomposition) so that rotations
+// are slerped instead of blending matrices, which would shear and shrink the object mid-way.
+#[derive(Debug, Clone)]
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Transform,
+    parts: Option<(Decomposed, Decomposed)>,
+}
+
+// how many instants are sampled to bound the swept volume
+const MOTION_STEPS: usize = 64;
+
+impl AnimatedTransform {
+    pub fn new(start: Transform, end: Transform) -> AnimatedTransform {
+        let parts = if start == end {
+            None
+        } else {
+            Some((Decomposed::new(start.matrix()), Decomposed::new(end.matrix())))
+        };
+        AnimatedTransform { start, end, parts }
+    }
+
+    pub fn fixed(transform: Transform) -> AnimatedTransform {
+        AnimatedTransform::new(transform, transform)
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.parts.is_some()
+    }
+
+    pub fn start(&self) -> &Transform {
+        &self.start
+    }
+
+    pub fn end(&self) -> &Transform {
+        &self.end
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let (a, b) = match &self.parts {
+            None => return self.start,
+            Some(parts) => parts,
+        };
+        if time <= 0.0 {
+            return self.start;
+        }
+        if time >= 1.0 {
+            return self.end;
+        }
+        Transform::from_matrix(a.lerp(b, time).matrix()).unwrap_or(self.start)
+    }
+
+    // Union of the boxes at evenly spaced instants, grown by half the largest step any corner
+    // takes between two of them. Each corner path stays that close to its sampled chords.
+    pub fn bounding_box(&self, b: &Aabb) -> Aabb {
+        if !self.is_animated() {
+            return self.start.bounding_box(b);
+        }
+        let corners = b.corners();
+        let mut previous: Vec<Point> = corners.iter().map(|c| self.start.point(c)).collect();
+        let mut bounds = Aabb::from_points(&previous);
+        let mut step = 0.0_f64;
+        for i in 1..=MOTION_STEPS {
+            let transform = self.at(i as f64 / MOTION_STEPS as f64);
+            let current: Vec<Point> = corners.iter().map(|c| transform.point(c)).collect();
+            for (p, q) in previous.iter().zip(current.iter()) {
+                step = step.max((*q - *p).length());
+            }
+            bounds = bounds.union(&Aabb::from_points(&current));
+            previous = current;
+        }
+        let pad = Vec3::new([step / 2.0; 3]);
+        Aabb::new(*bounds.min() - pad, *bounds.max() + pad)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Decomposed {
+    translation: Vec3,
+    rotation: Quaternion,
+    scale: Mat4,
+}
+
+impl Decomposed {
+    fn new(m: &Mat4) -> Decomposed {
+        let translation = Vec3::new([m.get(0, 3), m.get(1, 3), m.get(2, 3)]);
+        let mut linear = *m;
+        for i in 0..3 {
+            linear.m[i][3] = 0.0;
+            linear.m[3][i] = 0.0;
+        }
+        linear.m[3][3] = 1.0;
+
+        // polar decomposition by averaging with the inverse transpose until it settles
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inverse_transpose = match rotation.inverse() {
+                Some(inverse) => inverse.transpose(),
+                None => break,
+            };
+            let mut next = rotation;
+            let mut change = 0.0_f64;
+            for i in 0..3 {
+                for j in 0..3 {
+                    next.m[i][j] = 0.5 * (rotation.m[i][j] + inverse_transpose.m[i][j]);
+                    change = change.max((next.m[i][j] - rotation.m[i][j]).abs());
+                }
+            }
+            rotation = next;
+            if change < 1e-12 {
+                break;
+            }
+        }
+
+        // a mirroring transform leaves a reflection in the rotation; move it into the scale
+        if determinant3(&rotation) < 0.0 {
+            for row in rotation.m.iter_mut().take(3) {
+                for x in row.iter_mut().take(3) {
+                    *x = -*x;
+                }
+            }
+        }
+        let scale = rotation.transpose() * linear;
+
+        Decomposed {
+            translation,
+            rotation: Quaternion::from_matrix(&rotation),
+            scale,
+        }
+    }
+
+    fn lerp(&self, other: &Decomposed, t: f64) -> Decomposed {
+        let mut scale = Mat4::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                scale.m[i][j] = (1.0 - t) * self.scale.m[i][j] + t * other.scale.m[i][j];
+            }
+        }
+        Decomposed {
+            translation: (1.0 - t) * self.translation + t * other.translation,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale,
+        }
+    }
+
+    fn matrix(&self) -> Mat4 {
+        let mut m = self.rotation.to_matrix() * self.scale;
+        for i in 0..3 {
+            m.m[i][3] = self.translation[i];
+        }
+        m
+    }
+}
+
+fn determinant3(m: &Mat4) -> f64 {
+    let a = &m.m;
+    a[0][0] * (a[1][1] * a[2][2] - a[1][2] * a[2][1])
+        - a[0][1] * (a[1][0] * a[2][2] - a[1][2] * a[2][0])
+        + a[0][2] * (a[1][0] * a[2][1] - a[1][1] * a[2][0])
+}
+
+// unit quaternion w + xi + yj + zk
+#[derive(Debug, Copy, Clone)]
+struct Quaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Quaternion {
+    // Shepperd's method, picking the largest diagonal term for stability
+    fn from_matrix(m: &Mat4) -> Quaternion {
+        let a = &m.m;
+        let trace = a[0][0] + a[1][1] + a[2][2];
+        let q = if trace > 0.0 {
+            let s = 0.5 / (trace + 1.0).sqrt();
+            Quaternion {
+                w: 0.25 / s,
+                x: (a[2][1] - a[1][2]) * s,
+                y: (a[0][2] - a[2][0]) * s,
+                z: (a[1][0] - a[0][1]) * s,
+            }
+        } else if a[0][0] > a[1][1] && a[0][0] > a[2][2] {
+            let s = 2.0 * (1.0 + a[0][0] - a[1][1] - a[2][2]).sqrt();
+            Quaternion {
+                w: (a[2][1] - a[1][2]) / s,
+                x: 0.25 * s,
+                y: (a[0][1] + a[1][0]) / s,
+                z: (a[0][2] + a[2][0]) / s,
+            }
+        } else if a[1][1] > a[2][2] {
+            let s = 2.0 * (1.0 + a[1][1] - a[0][0] - a[2][2]).sqrt();
+            Quaternion {
+                w: (a[0][2] - a[2][0]) / s,
+                x: (a[0][1] + a[1][0]) / s,
+                y: 0.25 * s,
+                z: (a[1][2] + a[2][1]) / s,
+            }
+        } else {
+            let s = 2.0 * (1.0 + a[2][2] - a[0][0] - a[1][1]).sqrt();
+            Quaternion {
+                w: (a[1][0] - a[0][1]) / s,
+                x: (a[0][2] + a[2][0]) / s,
+                y: (a[1][2] + a[2][1]) / s,
+                z: 0.25 * s,
+            }
+        };
+        q.normalized()
+    }
+
+    fn to_matrix(self) -> Mat4 {
+        let Quaternion { w, x, y, z } = self;
+        Mat4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn normalized(self) -> Quaternion {
+        let n = self.dot(&self).sqrt();
+        Quaternion { w: self.w / n, x: self.x / n, y: self.y / n, z: self.z / n }
+    }
+
+    // along the shorter arc, falling back to a normalized lerp when the ends nearly coincide
+    fn slerp(&self, other: &Quaternion, t: f64) -> Quaternion {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        if cos < 0.0 {
+            cos = -cos;
+            other = Quaternion { w: -other.w, x: -other.x, y: -other.y, z: -other.z };
+        }
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quaternion {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        }.normalized()
+    }
+}
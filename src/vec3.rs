@@ -38,7 +38,7 @@ impl fmt::Display for Vec3 {
 
 impl Vec3 {
     pub const fn new(e: [f64; DIMENSION]) -> Vec3 {
-        Vec3 { e: e }
+        Vec3 { e }
     }
 
     pub fn random(min: f64, max: f64) -> Vec3 {
@@ -82,7 +82,7 @@ impl Vec3 {
         for i in 0..DIMENSION {
             e[i] = -self[i];
         }
-        Vec3 { e: e }
+        Vec3 { e }
     }
 
     pub fn square(&self) -> f64 {
@@ -116,14 +116,14 @@ impl Vec3 {
         }
         e[DIMENSION - 2] = self[DIMENSION - 1] * rhs[0] - self[0] * rhs[DIMENSION - 1];
         e[DIMENSION - 1] = self[0] * rhs[1] - self[1] * rhs[0];
-        Vec3 { e: e }
+        Vec3 { e }
     }
 
     pub fn near_zero(&self) -> bool {
         let mut result = true;
         let threshold = 1e-8;
         for i in 0..DIMENSION {
-            result &= self[i] < threshold;
+            result &= self[i].abs() < threshold;
             if !result { break; }
         }
         result
@@ -141,6 +141,38 @@ impl Vec3 {
     }
 }
 
+// orthonormal basis whose w axis is a given unit vector
+#[derive(Debug, Clone)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: &Vec3) -> Onb {
+        // Duff et al., "Building an Orthonormal Basis, Revisited"
+        let sign = 1.0_f64.copysign(w.z());
+        let a = -1.0 / (sign + w.z());
+        let b = w.x() * w.y() * a;
+        let u = Vec3::new([1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x()]);
+        let v = Vec3::new([b, sign + w.y() * w.y() * a, -w.y()]);
+        Onb { u, v, w: *w }
+    }
+
+    pub fn u(&self) -> &Vec3 { &self.u }
+    pub fn v(&self) -> &Vec3 { &self.v }
+    pub fn w(&self) -> &Vec3 { &self.w }
+
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new([a.dot(&self.u), a.dot(&self.v), a.dot(&self.w)])
+    }
+
+    pub fn to_world(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+}
+
 
 impl Add<Vec3> for Vec3 {
     type Output = Vec3;
@@ -149,14 +181,18 @@ impl Add<Vec3> for Vec3 {
         for i in 0..DIMENSION {
             e[i] = self[i] + rhs[i];
         }
-        Vec3 { e: e }
+        Vec3 { e }
     }
 }
         
 impl Sub<Vec3> for Vec3 {
     type Output = Vec3;
     fn sub(self, rhs: Vec3) -> Self::Output {
-        self + rhs.reverse()
+        let mut e = [0.0; DIMENSION];
+        for i in 0..DIMENSION {
+            e[i] = self[i] - rhs[i];
+        }
+        Vec3 { e }
     }
 }
 
@@ -169,7 +205,7 @@ impl Mul<Vec3> for Vec3 {
         for i in 0..DIMENSION {
             e[i] = self[i] * rhs[i];
         }
-        Vec3 { e: e }
+        Vec3 { e }
     }
 }
 
@@ -181,7 +217,7 @@ impl Add<f64> for Vec3 {
         for i in 0..DIMENSION {
             e[i] = self[i] + rhs;
         }
-        Vec3 { e: e }
+        Vec3 { e }
     }
 }
 
@@ -199,7 +235,7 @@ impl Mul<f64> for Vec3 {
         for i in 0..DIMENSION {
             e[i] = self[i] * rhs;
         }
-        Vec3 { e: e }
+        Vec3 { e }
     }
 }
 
@@ -0,0 +1,396 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material, Bsdf, BsdfSample};
+use crate::texture::{Texture};
+use crate::perlin::{Perlin};
+use crate::vec3::{Point, Vec3};
+use crate::color::{Color, WHITE, BLACK};
+use crate::aabb::{Aabb};
+use std::fs;
+use std::io::{self, Write};
+use std::ops::{Add, Mul};
+use std::path::{Path};
+use std::sync::{Arc};
+use rand::Rng;
+
+// header of the voxel file format, followed by the grid size and channel count on the same line
+const MAGIC: &str = "VOXELS";
+// 1024^3 voxels at most
+const MAX_VOXELS: usize = 1 << 30;
+
+// A regular grid of voxels spanning the unit cube, x varying fastest, then y, then z. Every
+// voxel has a density and optionally a scattering albedo and an emitted radiance; values in
+// between voxel centers are trilinearly interpolated.
+pub struct VoxelGrid {
+    size: [usize; 3],
+    density: Vec<f64>,
+    albedo: Option<Vec<Color>>,
+    emission: Option<Vec<Color>>,
+    max_density: f64,
+}
+
+impl VoxelGrid {
+    pub fn new(size: [usize; 3], density: Vec<f64>) -> io::Result<VoxelGrid> {
+        if voxel_count(size)? != density.len() {
+            return Err(invalid("voxel count does not match grid size"));
+        }
+        if !density.iter().all(|d| *d >= 0.0) {
+            return Err(invalid("densities must not be negative"));
+        }
+        Ok(VoxelGrid::filled(size, density))
+    }
+
+    // `density` already checked against the size
+    fn filled(size: [usize; 3], density: Vec<f64>) -> VoxelGrid {
+        let max_density = density.iter().fold(0.0, |m: f64, d| m.max(*d));
+        VoxelGrid { size, density, albedo: None, emission: None, max_density }
+    }
+
+    // fills the grid by evaluating `density` at every voxel center in [0, 1]^3
+    pub fn from_fn(size: [usize; 3], density: impl Fn(&Point) -> f64) -> io::Result<VoxelGrid> {
+        let mut values = Vec::with_capacity(voxel_count(size)?);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let p = Point::new([
+                        (x as f64 + 0.5) / size[0] as f64,
+                        (y as f64 + 0.5) / size[1] as f64,
+                        (z as f64 + 0.5) / size[2] as f64,
+                    ]);
+                    values.push(density(&p).max(0.0));
+                }
+            }
+        }
+        Ok(VoxelGrid::filled(size, values))
+    }
+
+    // a billowing cloud: fBm noise fading out towards the faces of the cube
+    pub fn noise(resolution: usize, seed: u64, scale: f64) -> io::Result<VoxelGrid> {
+        let perlin = Perlin::new(seed);
+        VoxelGrid::from_fn([resolution; 3], |p| {
+            let centered = *p - Point::new([0.5; 3]);
+            let falloff = 1.0 - 2.0 * centered.length();
+            perlin.fbm(&(scale * *p), 5, 2.0, 0.5) + falloff - 0.3
+        })
+    }
+
+    pub fn with_albedo(mut self, albedo: Vec<Color>) -> io::Result<VoxelGrid> {
+        if self.density.len() != albedo.len() {
+            return Err(invalid("albedo count does not match grid size"));
+        }
+        self.albedo = Some(albedo);
+        Ok(self)
+    }
+
+    pub fn with_emission(mut self, emission: Vec<Color>) -> io::Result<VoxelGrid> {
+        if self.density.len() != emission.len() {
+            return Err(invalid("emission count does not match grid size"));
+        }
+        self.emission = Some(emission);
+        Ok(self)
+    }
+
+    // `VOXELS nx ny nz channels` on one text line, then nx * ny * nz voxels of little endian f32
+    // channels: 1 for density alone, 4 adding albedo rgb, 7 adding emission rgb after that
+    pub fn load(path: impl AsRef<Path>) -> io::Result<VoxelGrid> {
+        VoxelGrid::decode(&fs::read(path)?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<VoxelGrid> {
+        let line_end = bytes.iter().position(|b| *b == b'\n')
+            .ok_or_else(|| invalid("missing voxel header"))?;
+        let header = std::str::from_utf8(&bytes[..line_end]).map_err(|_| invalid("voxel header is not text"))?;
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        if fields.len() != 5 || fields[0] != MAGIC {
+            return Err(invalid("expected `VOXELS nx ny nz channels`"));
+        }
+        let numbers: Vec<usize> = fields[1..].iter()
+            .map(|f| f.parse::<usize>().map_err(|_| invalid("voxel header fields must be integers")))
+            .collect::<io::Result<_>>()?;
+        let size = [numbers[0], numbers[1], numbers[2]];
+        let channels = numbers[3];
+        if !matches!(channels, 1 | 4 | 7) {
+            return Err(invalid("voxel channels must be 1, 4 or 7"));
+        }
+        let count = voxel_count(size)?;
+
+        let payload = &bytes[line_end + 1..];
+        if Some(payload.len()) != count.checked_mul(channels * 4) {
+            return Err(invalid("voxel data does not match the header"));
+        }
+        let values: Vec<f64> = payload.chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+            .collect();
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err(invalid("voxel data contains non-finite values"));
+        }
+
+        let voxel = |i: usize, channel: usize| values[i * channels + channel];
+        let color = |i: usize, first: usize| Color::new([voxel(i, first), voxel(i, first + 1), voxel(i, first + 2)]);
+        let mut grid = VoxelGrid::new(size, (0..count).map(|i| voxel(i, 0).max(0.0)).collect())?;
+        if channels >= 4 {
+            grid = grid.with_albedo((0..count).map(|i| color(i, 1)).collect())?;
+        }
+        if channels == 7 {
+            grid = grid.with_emission((0..count).map(|i| color(i, 4)).collect())?;
+        }
+        Ok(grid)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let channels = if self.emission.is_some() { 7 } else if self.albedo.is_some() { 4 } else { 1 };
+        let mut out = Vec::with_capacity(32 + self.density.len() * channels * 4);
+        writeln!(out, "{} {} {} {} {}", MAGIC, self.size[0], self.size[1], self.size[2], channels)?;
+        for i in 0..self.density.len() {
+            let mut values = vec![self.density[i]];
+            if channels >= 4 {
+                let a = self.albedo.as_ref().map_or(WHITE, |albedo| albedo[i]);
+                values.extend([a.x(), a.y(), a.z()]);
+            }
+            if let Some(emission) = &self.emission {
+                values.extend([emission[i].x(), emission[i].y(), emission[i].z()]);
+            }
+            for v in values {
+                out.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+        }
+        fs::write(path, out)
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    pub fn max_density(&self) -> f64 {
+        self.max_density
+    }
+
+    // `p` in grid space, the unit cube; zero outside it
+    pub fn density(&self, p: &Point) -> f64 {
+        self.lookup(p, &self.density, 0.0)
+    }
+
+    pub fn albedo(&self, p: &Point) -> Color {
+        match &self.albedo {
+            Some(albedo) => self.lookup(p, albedo, BLACK),
+            None => WHITE,
+        }
+    }
+
+    pub fn emission(&self, p: &Point) -> Color {
+        match &self.emission {
+            Some(emission) => self.lookup(p, emission, BLACK),
+            None => BLACK,
+        }
+    }
+
+    fn lookup<T: Copy + Add<Output = T> + Mul<f64, Output = T>>(&self, p: &Point, values: &[T], zero: T) -> T {
+        if (0..3).any(|i| p[i] < 0.0 || p[i] > 1.0) {
+            return zero;
+        }
+        let mut base = [0usize; 3];
+        let mut frac = [0.0; 3];
+        for i in 0..3 {
+            let x = (p[i] * self.size[i] as f64 - 0.5).clamp(0.0, (self.size[i] - 1) as f64);
+            base[i] = (x.floor() as usize).min(self.size[i].saturating_sub(2));
+            frac[i] = if self.size[i] > 1 { x - base[i] as f64 } else { 0.0 };
+        }
+        let mut result = zero;
+        for corner in 0..8 {
+            let mut index = [0usize; 3];
+            let mut weight = 1.0;
+            for i in 0..3 {
+                let upper = corner & (1 << i) != 0;
+                index[i] = (base[i] + upper as usize).min(self.size[i] - 1);
+                weight *= if upper { frac[i] } else { 1.0 - frac[i] };
+            }
+            if weight == 0.0 {
+                continue;
+            }
+            let at = index[0] + self.size[0] * (index[1] + self.size[1] * index[2]);
+            result = result + values[at] * weight;
+        }
+        result
+    }
+}
+
+// every side must hold at least one voxel, and the whole grid fit in memory
+fn voxel_count(size: [usize; 3]) -> io::Result<usize> {
+    size[0].checked_mul(size[1]).and_then(|n| n.checked_mul(size[2]))
+        .filter(|n| *n <= MAX_VOXELS && size.iter().all(|side| *side > 0))
+        .ok_or_else(|| invalid("bad voxel grid size"))
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+// A heterogeneous medium filling an axis aligned box with a voxel grid. Free flights are
+// sampled with delta tracking against the grid's largest density, and shadow rays estimate
+// transmittance with ratio tracking. At a real collision the voxel's albedo is the chance of
+// scattering and the rest is absorbed, emitting the voxel's radiance.
+pub struct GridMedium {
+    bounds: Aabb,
+    density_scale: f64,
+    phase: Arc<GridPhase>,
+}
+
+impl GridMedium {
+    // `density_scale` turns grid densities into extinction per unit length; `g` is the
+    // Henyey-Greenstein asymmetry of the scattering
+    pub fn new(grid: Arc<VoxelGrid>, bounds: Aabb, density_scale: f64, g: f64) -> GridMedium {
+        let local = GridSpace { min: *bounds.min(), extent: bounds.extent() };
+        GridMedium {
+            bounds,
+            density_scale: density_scale.max(0.0),
+            phase: Arc::new(GridPhase {
+                phase: Material::HenyeyGreenstein(Arc::new(GridAlbedo { grid: Arc::clone(&grid), local }), g),
+                grid,
+                local,
+            }),
+        }
+    }
+
+    fn majorant(&self) -> f64 {
+        self.phase.grid.max_density() * self.density_scale
+    }
+
+    fn extinction(&self, p: &Point) -> f64 {
+        self.phase.grid.density(&self.phase.local.to_grid(p)) * self.density_scale
+    }
+}
+
+impl Hittable for GridMedium {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let majorant = self.majorant();
+        if majorant == 0.0 {
+            return None;
+        }
+        let (start, end) = self.bounds.clip(ray, t_min, t_max)?;
+        let length = ray.direct().length();
+        let mut rng = rand::thread_rng();
+
+        // delta tracking: tentative collisions at the majorant rate, kept in proportion to the
+        // real density so the empty remainder acts as a null medium
+        let mut t = start;
+        loop {
+            t -= (1.0 - rng.gen_range(0.0..1.0_f64)).ln() / (majorant * length);
+            if t >= end {
+                return None;
+            }
+            let p = ray.range(t);
+            if rng.gen_range(0.0..1.0) * majorant < self.extinction(&p) {
+                let normal = ray.direct().reverse() / length;
+                let phase: Arc<dyn Bsdf> = self.phase.clone();
+                return Some(HitRecord::new(t, p, normal, true, (0.0, 0.0), phase));
+            }
+        }
+    }
+
+    // ratio tracking, with Russian roulette once little light is left
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let majorant = self.majorant();
+        let (start, end) = match self.bounds.clip(ray, t_min, t_max) {
+            Some(range) if majorant > 0.0 => range,
+            _ => return 1.0,
+        };
+        let length = ray.direct().length();
+        let mut rng = rand::thread_rng();
+
+        let mut transmittance = 1.0;
+        let mut t = start;
+        loop {
+            t -= (1.0 - rng.gen_range(0.0..1.0_f64)).ln() / (majorant * length);
+            if t >= end {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.extinction(&ray.range(t)) / majorant;
+            if transmittance < 0.1 {
+                if rng.gen_range(0.0..1.0) < 0.5 {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+// maps world points into the unit cube of the grid
+#[derive(Copy, Clone)]
+struct GridSpace {
+    min: Point,
+    extent: Vec3,
+}
+
+impl GridSpace {
+    fn to_grid(self, p: &Point) -> Point {
+        let mut local = *p - self.min;
+        for i in 0..3 {
+            local[i] /= self.extent[i];
+        }
+        local
+    }
+}
+
+struct GridAlbedo {
+    grid: Arc<VoxelGrid>,
+    local: GridSpace,
+}
+
+impl Texture for GridAlbedo {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        self.grid.albedo(&self.local.to_grid(p))
+    }
+}
+
+// scattering at a collision inside the grid, plus the emission of the absorbed share
+struct GridPhase {
+    phase: Material,
+    grid: Arc<VoxelGrid>,
+    local: GridSpace,
+}
+
+impl Bsdf for GridPhase {
+    fn sample(&self, wo: &Vec3, rec: &HitRecord) -> Option<BsdfSample> {
+        self.phase.sample(wo, rec)
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        self.phase.eval(wo, wi, rec)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        self.phase.pdf(wo, wi, rec)
+    }
+
+    fn emitted(&self, _wo: &Vec3, rec: &HitRecord) -> Color {
+        let p = self.local.to_grid(rec.pos());
+        let emission = self.grid.emission(&p);
+        if emission == BLACK {
+            return BLACK;
+        }
+        (WHITE - self.grid.albedo(&p)) * emission
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_grids_are_errors() {
+        assert!(VoxelGrid::decode(b"VOXELS 2147483648 2147483648 1 7\n").is_err());
+        assert!(VoxelGrid::new([usize::MAX, 2, 1], vec![1.0]).is_err());
+        assert!(VoxelGrid::new([2, 1, 1], vec![1.0, -1.0]).is_err());
+        assert!(VoxelGrid::new([0, 0, 0], vec![]).is_err());
+        assert!(VoxelGrid::new([0, 5, 1], vec![]).is_err());
+        assert!(VoxelGrid::from_fn([usize::MAX, usize::MAX, 1], |_| 1.0).is_err());
+        assert!(VoxelGrid::from_fn([1 << 20; 3], |_| 1.0).is_err());
+        assert!(VoxelGrid::noise(0, 1, 1.0).is_err());
+        let grid = VoxelGrid::new([2, 1, 1], vec![1.0, 0.5]).unwrap();
+        assert!(grid.with_albedo(vec![WHITE]).is_err());
+    }
+}
@@ -1,25 +1,130 @@
 use crate::ray::{Ray, HitRecord, Hittable};
-use crate::vec3::{Point};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use crate::color::{Color, WHITE};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc};
+use rand::Rng;
 
-pub const INF: f64 = std::f64::INFINITY;
+pub const INF: f64 = f64::INFINITY;
 pub const ORIGIN: Point = Point::new([0.0, 0.0, 0.0]);
 
+// what rays that escape the scene see
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Background {
+    // blends from `horizon` below to `zenith` straight up
+    Gradient(Color, Color),
+    Solid(Color),
+}
+
+impl Background {
+    pub fn value(&self, direction: &Vec3) -> Color {
+        match self {
+            Background::Gradient(horizon, zenith) => {
+                let alpha = (direction.unit().y() + 1.0) / 2.0;
+                (1.0 - alpha) * *horizon + alpha * *zenith
+            },
+            Background::Solid(color) => *color,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient(WHITE, Color::new([0.5, 0.7, 1.0]))
+    }
+}
+
 pub struct World {
-    objects: Vec<Arc<dyn Hittable>>
+    objects: Vec<Arc<dyn Hittable>>,
+    lights: Vec<Arc<dyn Hittable>>,
+    background: Background,
+    // the files besides the scene file itself that the world was built from
+    sources: Vec<PathBuf>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> World {
         World {
-            objects: Vec::new()
+            objects: Vec::new(),
+            lights: Vec::new(),
+            background: Background::default(),
+            sources: Vec::new(),
         }
     }
 
-    pub fn add(&mut self, object: Arc<impl Hittable + 'static>) {
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn add_source(&mut self, path: impl Into<PathBuf>) {
+        self.sources.push(path.into());
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.sources.iter().map(PathBuf::as_path)
+    }
+
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
     }
-    
+
+    // adds an emitter that is also sampled directly for next event estimation
+    pub fn add_light(&mut self, light: Arc<dyn Hittable>) {
+        self.objects.push(light.clone());
+        self.lights.push(light);
+    }
+
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty()
+    }
+
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    // picks one light uniformly and returns a direction towards a point on it, with moving
+    // lights where they are at `time`
+    pub fn sample_light(&self, origin: &Point, time: f64) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let index = rand::thread_rng().gen_range(0..self.lights.len());
+        Some(self.lights[index].random(origin, time))
+    }
+
+    // the closest registered light along the ray, as targeted by a light sample
+    pub fn nearest_light(&self, ray: &Ray, t_min: f64) -> Option<HitRecord> {
+        let mut closest = INF;
+        let mut result = None;
+        for light in self.lights.iter() {
+            if let Some(rec) = light.intersect(ray, t_min, closest) {
+                closest = rec.t();
+                result = Some(rec);
+            }
+        }
+        result
+    }
+
+    pub fn light_pdf(&self, origin: &Point, direction: &Vec3, time: f64) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.lights.iter()
+            .map(|light| light.pdf_value(origin, direction, time))
+            .sum();
+        sum / self.lights.len() as f64
+    }
 }
 
 impl Hittable for World {
@@ -36,4 +141,21 @@ impl Hittable for World {
 
         result
     }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut result = 1.0;
+        for obj in self.objects.iter() {
+            result *= obj.transmittance(ray, t_min, t_max);
+            if result == 0.0 {
+                break;
+            }
+        }
+        result
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|obj| obj.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.union(&b?)))
+    }
 }
\ No newline at end of file